@@ -194,3 +194,56 @@ fn basic(b: &mut Bencher) {
 
     b.iter(|| dispatcher.dispatch(&mut res));
 }
+
+struct FetchMany;
+
+impl<'a> System<'a> for FetchMany {
+    type SystemData = (Fetch<'a, u8>,
+     Fetch<'a, u16>,
+     Fetch<'a, u32>,
+     Fetch<'a, u64>,
+     Fetch<'a, i8>,
+     Fetch<'a, i16>,
+     Fetch<'a, i32>,
+     FetchMut<'a, i64>);
+
+    fn run(&mut self, (a, b, c, d, e, f, g, mut sum): Self::SystemData) {
+        *sum += *a as i64 + *b as i64 + *c as i64 + *d as i64 + *e as i64 + *f as i64 +
+                *g as i64;
+    }
+}
+
+fn fetch_many_resources() -> Resources {
+    let mut res = Resources::new();
+    res.add(1u8);
+    res.add(2u16);
+    res.add(3u32);
+    res.add(4u64);
+    res.add(5i8);
+    res.add(6i16);
+    res.add(7i32);
+    res.add(0i64);
+
+    // Other resources, so the lookups don't all hit a tiny map
+    for id in 1..100 {
+        res.add_with_id(0u64, id);
+    }
+
+    res
+}
+
+#[bench]
+fn fetch_unresolved(b: &mut Bencher) {
+    let res = fetch_many_resources();
+
+    b.iter(|| FetchMany.run_now(&res));
+}
+
+#[cfg(feature = "std")]
+#[bench]
+fn fetch_resolved(b: &mut Bencher) {
+    let res = fetch_many_resources();
+    let mut resolution = Resolution::default();
+
+    b.iter(|| res.run_resolved(&mut resolution, || FetchMany.run_now(&res)));
+}
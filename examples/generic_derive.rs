@@ -25,4 +25,37 @@ struct WithWhereClause<'a, T>
     k: Fetch<'a, T>,
 }
 
+#[derive(SystemData)]
+struct Nested<'a, T>
+    where T: Debug + Resource
+{
+    tuple: SomeTuple<'a, T>,
+    bundle: WithWhereClause<'a, T>,
+    other: Option<Fetch<'a, u32>>,
+}
+
+#[derive(SystemData)]
+struct MultipleLifetimes<'a, 'b: 'a, T>
+    where T: Debug + Resource
+{
+    nested: Nested<'a, T>,
+    borrowed: Borrowed<'b>,
+}
+
+struct Borrowed<'b>(&'b ());
+
+impl<'a, 'b> shred::SystemData<'a> for Borrowed<'b> {
+    fn fetch(_: &'a shred::Resources, _: usize) -> Self {
+        Borrowed(&())
+    }
+
+    fn reads(_: usize) -> Vec<shred::ResourceId> {
+        vec![]
+    }
+
+    fn writes(_: usize) -> Vec<shred::ResourceId> {
+        vec![]
+    }
+}
+
 fn main() {}
@@ -16,7 +16,9 @@ struct Data<'a> {
     b: FetchMut<'a, ResB>,
 }
 
-struct EmptySystem(*mut i8); // System is not thread-safe
+// The field is only there to make the system not thread-safe
+#[allow(dead_code)]
+struct EmptySystem(*mut i8);
 
 impl<'a> System<'a> for EmptySystem {
     type SystemData = Data<'a>;
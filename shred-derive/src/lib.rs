@@ -6,13 +6,24 @@ extern crate syn;
 extern crate quote;
 
 use proc_macro::TokenStream;
-use syn::{Body, Field, Ident, Lifetime, LifetimeDef, MacroInput, Ty, TyParam, VariantData,
-          WhereClause};
+use syn::{Body, Field, Ident, Lifetime, LifetimeDef, Lit, MacroInput, MetaItem, NestedMetaItem,
+          Ty, TyParam, VariantData, WhereClause};
 use quote::Tokens;
 
 /// Used to `#[derive]` the trait
 /// `SystemData`.
-#[proc_macro_derive(SystemData)]
+///
+/// Fields are fetched with the id passed to `fetch`, unless
+/// they have an attribute like `#[shred(id = 1)]` or
+/// `#[shred(id = "minimap")]` (see `shred::DynamicId`).
+///
+/// `#[shred(id_expr = "id + 1")]` computes the id with an
+/// expression of type `usize`, in which `id` is the id passed
+/// to `fetch` (constants and functions in scope can be used too).
+/// Note that `id` may be a named id, in which case the result
+/// is unrelated to the name (named ids count up from
+/// `shred::DynamicId::FIRST_NAMED`).
+#[proc_macro_derive(SystemData, attributes(shred))]
 pub fn system_data(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_macro_input(&s).unwrap();
@@ -28,8 +39,9 @@ fn impl_system_data(ast: &MacroInput) -> Tokens {
     let ty_params = &ast.generics.ty_params;
     let where_clause = &ast.generics.where_clause;
 
-    let (fetch_return, tys) = gen_from_body(&ast.body, name);
+    let (fetch_return, tys, ids) = gen_from_body(&ast.body, name);
     let tys = &tys;
+    let ids = &ids;
     // Assumes that the first lifetime is the fetch lt
     let def_fetch_lt = lifetime_defs
         .iter()
@@ -45,11 +57,17 @@ fn impl_system_data(ast: &MacroInput) -> Tokens {
     // but need to be cloned before.
 
     quote! {
+        // `id` is unused if all fields have fixed ids
+        #[allow(unused_variables)]
         impl< #def_lt_tokens , #def_ty_params >
             ::shred::SystemData< #impl_fetch_lt >
             for #name< #impl_lt_tokens , #impl_ty_params >
             where #where_clause
         {
+            fn setup(res: &mut ::shred::Resources, id: usize) {
+                #( <#tys as ::shred::SystemData> :: setup(res, #ids); )*
+            }
+
             fn fetch(res: & #impl_fetch_lt ::shred::Resources, id: usize) -> Self {
                 #fetch_return
             }
@@ -58,7 +76,7 @@ fn impl_system_data(ast: &MacroInput) -> Tokens {
                 let mut r = Vec::new();
 
                 #( {
-                        let mut reads = <#tys as ::shred::SystemData> :: reads(id);
+                        let mut reads = <#tys as ::shred::SystemData> :: reads(#ids);
                         r.append(&mut reads);
                     } )*
 
@@ -69,7 +87,7 @@ fn impl_system_data(ast: &MacroInput) -> Tokens {
                 let mut r = Vec::new();
 
                 #( {
-                        let mut writes = <#tys as ::shred::SystemData> :: writes(id);
+                        let mut writes = <#tys as ::shred::SystemData> :: writes(#ids);
                         r.append(&mut writes);
                     } )*
 
@@ -83,6 +101,56 @@ fn collect_field_types(fields: &Vec<Field>) -> Vec<Ty> {
     fields.iter().map(|x| x.ty.clone()).collect()
 }
 
+/// Returns the expressions for the ids the fields are fetched with,
+/// which is `id` unless there is a `#[shred(id = ..)]` or
+/// `#[shred(id_expr = "..")]`.
+fn gen_field_ids(fields: &Vec<Field>) -> Vec<Tokens> {
+    fields.iter().map(gen_field_id).collect()
+}
+
+fn gen_field_id(field: &Field) -> Tokens {
+    let items = field
+        .attrs
+        .iter()
+        .filter_map(|attr| match attr.value {
+                        MetaItem::List(ref name, ref items) if name == "shred" => Some(items),
+                        _ => None,
+                    })
+        .flat_map(|items| items);
+
+    let mut id = quote! { id };
+
+    for item in items {
+        id = match *item {
+            NestedMetaItem::MetaItem(MetaItem::NameValue(ref name, Lit::Int(value, _)))
+                if name == "id" => quote! { #value as usize },
+            NestedMetaItem::MetaItem(MetaItem::NameValue(ref name, Lit::Str(ref value, _)))
+                if name == "id" => {
+                // Interned once, not on every fetch
+                quote! {{
+                    static ID: ::shred::NamedId = ::shred::NamedId::new(#value);
+                    ID.get()
+                }}
+            }
+            NestedMetaItem::MetaItem(MetaItem::NameValue(ref name, Lit::Str(ref value, _)))
+                if name == "id_expr" => {
+                // The generated code is parsed from a string,
+                // so the expression can be inserted as is.
+                let mut expr = Tokens::new();
+                expr.append(value);
+
+                quote! { (#expr) }
+            }
+            _ => {
+                panic!("Expected `#[shred(id = <integer or string>)]` or \
+                        `#[shred(id_expr = \"<expression>\")]`")
+            }
+        };
+    }
+
+    id
+}
+
 fn gen_identifiers(fields: &Vec<Field>) -> Vec<Ident> {
     fields.iter().map(|x| x.ident.clone().unwrap()).collect()
 }
@@ -144,7 +212,7 @@ fn gen_where_clause(clause: &WhereClause, fetch_lt: &Lifetime, tys: &Vec<Ty>) ->
     tokens
 }
 
-fn gen_from_body(ast: &Body, name: &Ident) -> (Tokens, Vec<Ty>) {
+fn gen_from_body(ast: &Body, name: &Ident) -> (Tokens, Vec<Ty>, Vec<Tokens>) {
     enum BodyType {
         Struct,
         Tuple,
@@ -157,26 +225,27 @@ fn gen_from_body(ast: &Body, name: &Ident) -> (Tokens, Vec<Ty>) {
     };
 
     let tys = collect_field_types(fields);
+    let ids = gen_field_ids(fields);
 
     let fetch_return = match body {
         BodyType::Struct => {
             let identifiers = gen_identifiers(fields);
+            let ids = &ids;
 
             quote! {
                 #name {
-                    #( #identifiers: ::shred::SystemData::fetch(res, id) ),*
+                    #( #identifiers: ::shred::SystemData::fetch(res, #ids) ),*
                 }
             }
         }
         BodyType::Tuple => {
-            let count = tys.len();
-            let fetch = vec![quote! { ::shred::SystemData::fetch(res, id) }; count];
+            let ids = &ids;
 
             quote! {
-                #name ( #( #fetch ),* )
+                #name ( #( ::shred::SystemData::fetch(res, #ids) ),* )
             }
         }
     };
 
-    (fetch_return, tys)
+    (fetch_return, tys, ids)
 }
@@ -0,0 +1,169 @@
+//! A token telling systems to stop
+//! because the frame budget is exhausted.
+
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A flag for cancelling a dispatch, stored as a resource.
+///
+/// A token is cancelled once `cancel` is called or its deadline
+/// has passed. Systems added as interruptible (see
+/// `DispatcherBuilder::with_interruptible`) are skipped after that,
+/// and long running systems can fetch the token to stop early
+/// (e.g. with `ReadDefault<CancellationToken>`, which reads the same
+/// resource from all systems without conflicts).
+///
+/// `Dispatcher::dispatch_with_budget` adds a token if there
+/// is none and sets its deadline for a single dispatch. Clones
+/// share their state, so a clone kept outside the resources can
+/// cancel the dispatch from another thread.
+///
+/// The clock is only read if a deadline is set. `Instant::now`
+/// panics on targets without a clock (like `wasm32-unknown-unknown`),
+/// so only `cancel` can be used there, not deadlines or
+/// `dispatch_with_budget`. Without the `std` feature, there
+/// are no deadlines at all.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use shred::{CancellationToken, DispatcherBuilder, ReadDefault, Resources, System};
+/// struct Pathfinding;
+///
+/// impl<'a> System<'a> for Pathfinding {
+///     type SystemData = ReadDefault<'a, CancellationToken>;
+///
+///     fn run(&mut self, token: Self::SystemData) {
+///         for _ in 0..100 {
+///             if token.is_cancelled() {
+///                 // Continue in the next frame
+///                 return;
+///             }
+///
+///             // Search a bit
+///         }
+///     }
+/// }
+///
+/// let mut res = Resources::new();
+/// let mut dispatcher = DispatcherBuilder::new()
+///     .add(Pathfinding, "pathfinding", &[])
+///     .with_interruptible("pathfinding")
+///     .build();
+///
+/// # #[cfg(feature = "std")]
+/// dispatcher.dispatch_with_budget(&mut res, Duration::from_millis(16));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    #[cfg(feature = "std")]
+    deadline: Mutex<Option<Instant>>,
+}
+
+impl CancellationToken {
+    /// Creates a token which isn't
+    /// cancelled and has no deadline.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Cancels the token.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns true if `cancel` has been called
+    /// or the deadline has passed.
+    #[cfg(feature = "std")]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire) ||
+        self.remaining() == Some(Duration::new(0, 0))
+    }
+
+    /// Returns true if `cancel` has been called.
+    #[cfg(not(feature = "std"))]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns the time left until the deadline,
+    /// or `None` if the token has no deadline.
+    #[cfg(feature = "std")]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline()
+            .map(|deadline| {
+                     let now = Instant::now();
+
+                     if deadline > now {
+                         deadline - now
+                     } else {
+                         Duration::new(0, 0)
+                     }
+                 })
+    }
+
+    /// Returns the deadline of the token, if set.
+    #[cfg(feature = "std")]
+    pub fn deadline(&self) -> Option<Instant> {
+        *self.inner.deadline.lock().expect("Mutex poisoned")
+    }
+
+    /// Sets the deadline after which the token is
+    /// cancelled, or removes it if `None` is passed.
+    #[cfg(feature = "std")]
+    pub fn set_deadline(&self, deadline: Option<Instant>) {
+        *self.inner.deadline.lock().expect("Mutex poisoned") = deadline;
+    }
+
+    /// Clears the cancellation and the deadline.
+    pub fn reset(&self) {
+        self.inner.cancelled.store(false, Ordering::Release);
+        #[cfg(feature = "std")]
+        self.set_deadline(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel() {
+        let token = CancellationToken::new();
+        let shared = token.clone();
+        assert!(!token.is_cancelled());
+
+        shared.cancel();
+        assert!(token.is_cancelled());
+
+        token.reset();
+        assert!(!shared.is_cancelled());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn deadline() {
+        let token = CancellationToken::new();
+        let shared = token.clone();
+        assert_eq!(token.remaining(), None);
+
+        token.set_deadline(Some(Instant::now() + Duration::new(60, 0)));
+        assert!(!token.is_cancelled());
+        assert!(token.remaining().unwrap() > Duration::new(59, 0));
+
+        token.set_deadline(Some(Instant::now()));
+        assert!(shared.is_cancelled());
+        assert_eq!(token.remaining(), Some(Duration::new(0, 0)));
+    }
+}
@@ -1,9 +1,31 @@
+//! Helper module for the thread-safe cell
+//! resources are stored in.
+//!
+//! You only need this if you implement a custom
+//! [`ResourceStorage`].
+//!
+//! [`ResourceStorage`]: ../trait.ResourceStorage.html
+
 use std::cell::UnsafeCell;
 use std::error::Error;
 use std::fmt::{Display, Error as FormatError, Formatter};
+use std::mem::forget;
 use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicUsize, Ordering};
-
+#[cfg(feature = "diagnostics")]
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(feature = "diagnostics")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "parking")]
+use std::time::Duration;
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// Error returned by `TrustCell::try_borrow`
+/// and `TrustCell::try_borrow_mut`.
 #[derive(Clone, Copy, Debug)]
 pub struct InvalidBorrow;
 
@@ -20,13 +42,99 @@ impl Error for InvalidBorrow {
     }
 }
 
+/// Error returned by `Resources::fetch_blocking`
+/// and `Resources::fetch_mut_blocking`.
+///
+/// Only available with the `parking` feature.
+#[cfg(feature = "parking")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlockingError {
+    /// Waiting could never finish, because the cell is borrowed
+    /// mutably by the waiting thread, or by a thread which
+    /// (possibly through other threads) waits for the waiting one.
+    Deadlock,
+    /// The cell was still borrowed when the timeout elapsed.
+    Timeout,
+}
+
+#[cfg(feature = "parking")]
+impl Display for BlockingError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            BlockingError::Deadlock => write!(f, "Waiting for the borrow would deadlock"),
+            BlockingError::Timeout => write!(f, "Timed out waiting for the borrow"),
+        }
+    }
+}
+
+#[cfg(feature = "parking")]
+impl Error for BlockingError {
+    fn description(&self) -> &str {
+        "This error is returned when a blocking borrow times out \
+         or would wait forever"
+    }
+}
+
+/// The last borrow of a `TrustCell`, returned by
+/// `TrustCell::last_borrow`.
+///
+/// Only available with the `diagnostics` feature.
+#[cfg(feature = "diagnostics")]
+#[derive(Clone, Debug)]
+pub struct Borrower {
+    /// The code which borrowed the cell.
+    pub location: &'static Location<'static>,
+    /// True if the borrow was mutable.
+    pub mutable: bool,
+    /// The name of the system which borrowed the cell,
+    /// if it was borrowed by a dispatched system.
+    pub system: Option<String>,
+}
+
+#[cfg(feature = "diagnostics")]
+impl Display for Borrower {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        if self.mutable {
+            write!(f, "mutably ")?;
+        }
+
+        match self.system {
+            Some(ref system) => write!(f, "by system \"{}\" at {}", system, self.location),
+            None => write!(f, "at {}", self.location),
+        }
+    }
+}
+
+/// A shared borrow of a `TrustCell`.
+/// Releases the borrow once dropped.
 #[derive(Debug)]
-pub struct Ref<'a, T: 'a> {
+pub struct Ref<'a, T: ?Sized + 'a> {
     flag: &'a AtomicUsize,
     value: &'a T,
 }
 
-impl<'a, T> Deref for Ref<'a, T> {
+impl<'a, T: ?Sized> Ref<'a, T> {
+    /// Makes a new `Ref` for a part of the borrowed value,
+    /// e.g. a field, keeping the borrow alive.
+    ///
+    /// This is an associated function, because
+    /// `T` could have a method with the same name.
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> Ref<'a, U>
+        where F: FnOnce(&T) -> &U
+    {
+        // If `f` panics, `this` is dropped and releases the borrow
+        let value = f(this.value);
+        let flag = this.flag;
+        forget(this);
+
+        Ref {
+            flag: flag,
+            value: value,
+        }
+    }
+}
+
+impl<'a, T: ?Sized> Deref for Ref<'a, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -34,19 +142,86 @@ impl<'a, T> Deref for Ref<'a, T> {
     }
 }
 
-impl<'a, T> Drop for Ref<'a, T> {
+impl<'a, T: ?Sized> Drop for Ref<'a, T> {
     fn drop(&mut self) {
         self.flag.fetch_sub(1, Ordering::Release);
     }
 }
 
+/// An exclusive borrow of a `TrustCell`.
+/// Releases the borrow once dropped.
+///
+/// If it's dropped while the thread is panicking,
+/// the cell gets poisoned (only with the `std` feature,
+/// as panics can't be detected otherwise). The version of the cell
+/// is bumped the first time the value is
+/// dereferenced mutably.
 #[derive(Debug)]
-pub struct RefMut<'a, T: 'a> {
+pub struct RefMut<'a, T: ?Sized + 'a> {
     flag: &'a AtomicUsize,
+    modified: bool,
+    #[cfg(feature = "parking")]
+    owner: &'a AtomicUsize,
+    poisoned: &'a AtomicBool,
     value: &'a mut T,
+    version: &'a AtomicUsize,
+}
+
+impl<'a, T: ?Sized> RefMut<'a, T> {
+    /// Makes a new `RefMut` for a part of the borrowed value,
+    /// e.g. a field, keeping the borrow alive.
+    ///
+    /// Like `Ref::map`, this is an associated function.
+    /// Only modifications through the returned borrow
+    /// bump the version of the cell.
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> RefMut<'a, U>
+        where F: FnOnce(&mut T) -> &mut U
+    {
+        // If `f` panics, `this` is dropped and releases the borrow
+        let value = f(unsafe { &mut *(this.value as *mut T) });
+        let flag = this.flag;
+        let modified = this.modified;
+        #[cfg(feature = "parking")]
+        let owner = this.owner;
+        let poisoned = this.poisoned;
+        let version = this.version;
+        forget(this);
+
+        RefMut {
+            flag: flag,
+            modified: modified,
+            #[cfg(feature = "parking")]
+            owner: owner,
+            poisoned: poisoned,
+            value: value,
+            version: version,
+        }
+    }
+
+    /// Turns this borrow into a shared borrow,
+    /// without releasing the cell in between.
+    ///
+    /// Other shared borrows are possible afterwards.
+    pub fn downgrade(self) -> Ref<'a, T> {
+        let flag = self.flag;
+        let value = unsafe { &*(self.value as *const T) };
+
+        #[cfg(feature = "parking")]
+        self.owner.store(0, Ordering::Release);
+
+        // The mutable flag can't be modified by other borrows,
+        // so it can be replaced by a single shared borrow.
+        flag.store(1, Ordering::Release);
+        forget(self);
+
+        Ref {
+            flag: flag,
+            value: value,
+        }
+    }
 }
 
-impl<'a, T> Deref for RefMut<'a, T> {
+impl<'a, T: ?Sized> Deref for RefMut<'a, T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -54,49 +229,313 @@ impl<'a, T> Deref for RefMut<'a, T> {
     }
 }
 
-impl<'a, T> DerefMut for RefMut<'a, T> {
+impl<'a, T: ?Sized> DerefMut for RefMut<'a, T> {
     fn deref_mut(&mut self) -> &mut T {
+        if !self.modified {
+            self.modified = true;
+            self.version.fetch_add(1, Ordering::AcqRel);
+        }
+
         self.value
     }
 }
 
-impl<'a, T> Drop for RefMut<'a, T> {
+impl<'a, T: ?Sized> Drop for RefMut<'a, T> {
     fn drop(&mut self) {
+        #[cfg(feature = "std")]
+        {
+            if thread::panicking() {
+                self.poisoned.store(true, Ordering::Release);
+            }
+        }
+
+        #[cfg(feature = "parking")]
+        self.owner.store(0, Ordering::Release);
+
         self.flag.store(0, Ordering::Release)
     }
 }
 
 /// A custom cell similar to
 /// `RefCell`, but it is thread-safe.
+///
+/// With the `diagnostics` feature, the cell remembers where it
+/// was borrowed last, which is added to the panic messages
+/// of conflicting borrows.
+///
+/// With the `parking` feature, `borrow_blocking` and
+/// `borrow_mut_blocking` wait for conflicting borrows
+/// to be released instead of failing.
 #[derive(Debug)]
 pub struct TrustCell<T> {
+    #[cfg(feature = "diagnostics")]
+    borrower: Mutex<Option<Borrower>>,
     flag: AtomicUsize,
     inner: UnsafeCell<T>,
+    /// The thread holding the mutable borrow, or `0`.
+    #[cfg(feature = "parking")]
+    owner: AtomicUsize,
+    poisoned: AtomicBool,
+    version: AtomicUsize,
 }
 
 impl<T> TrustCell<T> {
+    /// Creates a new cell containing `val`.
     pub fn new(val: T) -> Self {
         TrustCell {
+            #[cfg(feature = "diagnostics")]
+            borrower: Mutex::new(None),
             flag: AtomicUsize::new(0),
             inner: UnsafeCell::new(val),
+            #[cfg(feature = "parking")]
+            owner: AtomicUsize::new(0),
+            poisoned: AtomicBool::new(false),
+            version: AtomicUsize::new(0),
         }
     }
 
+    /// Borrows the value immutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is borrowed mutably.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
     pub fn borrow(&self) -> Ref<T> {
-        self.check_flag_read().expect("Already borrowed mutably");
+        match self.try_borrow() {
+            Ok(r) => r,
+            Err(_) => panic!("Already borrowed mutably{}", self.borrower_note()),
+        }
+    }
+
+    /// Like `borrow`, but returns an error instead of
+    /// panicking if the cell is already borrowed mutably.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn try_borrow(&self) -> Result<Ref<T>, InvalidBorrow> {
+        self.check_flag_read()?;
+
+        #[cfg(feature = "diagnostics")]
+        self.record(Location::caller(), false);
+
+        Ok(self.shared())
+    }
+
+    /// Borrows the value mutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is borrowed already.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub fn borrow_mut(&self) -> RefMut<T> {
+        match self.try_borrow_mut() {
+            Ok(r) => r,
+            Err(_) => panic!("Already borrowed{}", self.borrower_note()),
+        }
+    }
+
+    /// Like `borrow_mut`, but returns an error instead of
+    /// panicking if the cell is already borrowed.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<T>, InvalidBorrow> {
+        self.check_flag_write()?;
+
+        #[cfg(feature = "diagnostics")]
+        self.record(Location::caller(), true);
+
+        Ok(self.exclusive())
+    }
+
+    /// Borrows the value immutably, waiting up to `timeout`
+    /// while it is borrowed mutably.
+    ///
+    /// It spins for a short while before it sleeps, so borrows
+    /// released within a few microseconds are picked up quickly.
+    /// Fails early with `BlockingError::Deadlock` if the mutable
+    /// borrow is held by this thread, or by a thread waiting for
+    /// a cell this thread borrowed mutably. Waiting for shared
+    /// borrows isn't checked, so such deadlocks only time out.
+    ///
+    /// Only available with the `parking` feature.
+    #[cfg(feature = "parking")]
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn borrow_blocking(&self, timeout: Duration) -> Result<Ref<T>, BlockingError> {
+        self.wait(timeout, || self.check_flag_read().is_ok())?;
+
+        #[cfg(feature = "diagnostics")]
+        self.record(Location::caller(), false);
+
+        Ok(self.shared())
+    }
+
+    /// Borrows the value mutably, waiting up to `timeout`
+    /// while it is borrowed.
+    ///
+    /// Please see `borrow_blocking` for details.
+    ///
+    /// Only available with the `parking` feature.
+    #[cfg(feature = "parking")]
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn borrow_mut_blocking(&self, timeout: Duration) -> Result<RefMut<T>, BlockingError> {
+        self.wait(timeout, || self.check_flag_write().is_ok())?;
+
+        #[cfg(feature = "diagnostics")]
+        self.record(Location::caller(), true);
+
+        Ok(self.exclusive())
+    }
+
+    /// Returns true if a thread panicked while
+    /// holding a mutable borrow of this cell, so
+    /// the value may be in an inconsistent state.
+    ///
+    /// Borrowing a poisoned cell is still possible.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Acquire)
+    }
+
+    /// Clears the poison flag, e.g. after
+    /// the value has been repaired.
+    pub fn clear_poison(&self) {
+        self.poisoned.store(false, Ordering::Release);
+    }
+
+    /// Returns how many mutable borrows modified
+    /// the value, i.e. dereferenced it mutably.
+    ///
+    /// Modifications through `get_mut` aren't counted, as the
+    /// cell can't tell whether the reference is written to;
+    /// callers with exclusive access have to bump the version
+    /// themselves with `set_version`.
+    pub fn version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+
+    /// Sets the version of the cell, e.g. to keep versions
+    /// increasing when a value is replaced by a new cell.
+    pub fn set_version(&mut self, version: usize) {
+        *self.version.get_mut() = version;
+    }
+
+    /// Returns a mutable reference to the inner value.
+    ///
+    /// No runtime checks are necessary, because
+    /// this requires exclusive access to the cell.
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.inner.get() }
+    }
+
+    /// Consumes the cell, returning the inner value.
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Returns the last borrow of the cell, which
+    /// isn't necessarily still alive.
+    ///
+    /// Only available with the `diagnostics` feature.
+    #[cfg(feature = "diagnostics")]
+    pub fn last_borrow(&self) -> Option<Borrower> {
+        self.borrower.lock().expect("Mutex poisoned").clone()
+    }
 
+    #[cfg(feature = "diagnostics")]
+    fn record(&self, location: &'static Location<'static>, mutable: bool) {
+        let borrower = Borrower {
+            location: location,
+            mutable: mutable,
+            system: ::dispatch::current_system(),
+        };
+
+        *self.borrower.lock().expect("Mutex poisoned") = Some(borrower);
+    }
+
+    /// Describes the last borrow for panic messages,
+    /// which is empty without the `diagnostics` feature.
+    fn borrower_note(&self) -> String {
+        #[cfg(feature = "diagnostics")]
+        {
+            if let Some(borrower) = self.last_borrow() {
+                return format!(" (last borrowed {})", borrower);
+            }
+        }
+
+        String::new()
+    }
+
+    /// Creates a shared borrow, the flag has to be acquired already.
+    fn shared(&self) -> Ref<T> {
         Ref {
             flag: &self.flag,
             value: unsafe { &*self.inner.get() },
         }
     }
 
-    pub fn borrow_mut(&self) -> RefMut<T> {
-        self.check_flag_write().expect("Already borrowed");
+    /// Creates an exclusive borrow, the flag
+    /// has to be acquired already.
+    fn exclusive(&self) -> RefMut<T> {
+        #[cfg(feature = "parking")]
+        self.owner.store(waiting::current(), Ordering::Release);
 
         RefMut {
             flag: &self.flag,
+            modified: false,
+            #[cfg(feature = "parking")]
+            owner: &self.owner,
+            poisoned: &self.poisoned,
             value: unsafe { &mut *self.inner.get() },
+            version: &self.version,
+        }
+    }
+
+    /// Calls `acquire` until it succeeds, spinning at first
+    /// and then sleeping with an exponential backoff.
+    #[cfg(feature = "parking")]
+    fn wait<F>(&self, timeout: Duration, acquire: F) -> Result<(), BlockingError>
+        where F: Fn() -> bool
+    {
+        use std::hint::spin_loop;
+        use std::time::Instant;
+
+        const SPINS: usize = 100;
+
+        if acquire() {
+            return Ok(());
+        }
+
+        if self.owner.load(Ordering::Acquire) == waiting::current() {
+            return Err(BlockingError::Deadlock);
+        }
+
+        let start = Instant::now();
+
+        for _ in 0..SPINS {
+            spin_loop();
+
+            if acquire() {
+                return Ok(());
+            }
+        }
+
+        let waiter = waiting::Waiter::new(&self.owner);
+        let max_delay = Duration::new(0, 1_000_000);
+        let mut delay = Duration::new(0, 1_000);
+
+        loop {
+            if acquire() {
+                return Ok(());
+            }
+
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Err(BlockingError::Timeout);
+            }
+
+            if waiter.deadlocked() {
+                return Err(BlockingError::Deadlock);
+            }
+
+            thread::sleep(delay.min(timeout - elapsed));
+            delay = (delay * 2).min(max_delay);
         }
     }
 
@@ -108,22 +547,104 @@ impl<T> TrustCell<T> {
                 return Err(InvalidBorrow);
             }
 
-            if self.flag.compare_and_swap(val, val + 1, Ordering::AcqRel) == val {
+            if self.flag
+                   .compare_exchange_weak(val, val + 1, Ordering::AcqRel, Ordering::Acquire)
+                   .is_ok() {
                 return Ok(());
             }
         }
     }
 
     fn check_flag_write(&self) -> Result<(), InvalidBorrow> {
-        match self.flag.compare_and_swap(0, !0, Ordering::AcqRel) {
-            0 => Ok(()),
-            _ => Err(InvalidBorrow),
+        match self.flag
+                  .compare_exchange(0, !0, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(InvalidBorrow),
         }
     }
 }
 
 unsafe impl<T> Sync for TrustCell<T> where T: Sync {}
 
+/// Tracks which threads wait for which cells, so
+/// blocking borrows can detect deadlocks.
+#[cfg(feature = "parking")]
+mod waiting {
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// The waiting threads and the owner
+    /// flags of the cells they wait for.
+    static WAITING: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+
+    thread_local!(static TOKEN: u8 = const { 0 });
+
+    /// Returns a non-zero token identifying the current thread,
+    /// which is unique among the running threads.
+    pub fn current() -> usize {
+        TOKEN.with(|x| x as *const u8 as usize)
+    }
+
+    /// Registers the current thread as waiting
+    /// for a cell until it's dropped.
+    pub struct Waiter {
+        owner: usize,
+        thread: usize,
+    }
+
+    impl Waiter {
+        pub fn new(owner: &AtomicUsize) -> Self {
+            let waiter = Waiter {
+                owner: owner as *const AtomicUsize as usize,
+                thread: current(),
+            };
+
+            WAITING
+                .lock()
+                .expect("Mutex poisoned")
+                .push((waiter.thread, waiter.owner));
+
+            waiter
+        }
+
+        /// Returns true if the chain of mutable borrowers
+        /// and the cells they wait for leads back here.
+        pub fn deadlocked(&self) -> bool {
+            let waiting = WAITING.lock().expect("Mutex poisoned");
+            let mut owner = self.owner;
+
+            // A cycle not containing this thread
+            // is detected by the threads in it.
+            for _ in 0..waiting.len() {
+                // Registered owner flags belong to cells which are
+                // borrowed by their waiters, so they are still alive.
+                let holder = unsafe { &*(owner as *const AtomicUsize) }.load(Ordering::Acquire);
+
+                if holder == self.thread {
+                    return true;
+                }
+
+                match waiting.iter().find(|&&(thread, _)| thread == holder) {
+                    Some(&(_, next)) => owner = next,
+                    None => return false,
+                }
+            }
+
+            false
+        }
+    }
+
+    impl Drop for Waiter {
+        fn drop(&mut self) {
+            let mut waiting = WAITING.lock().expect("Mutex poisoned");
+
+            if let Some(pos) = waiting.iter().position(|&(thread, _)| thread == self.thread) {
+                waiting.swap_remove(pos);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,6 +672,190 @@ mod tests {
         assert_eq!(10, *cell.borrow());
     }
 
+    #[test]
+    fn try_borrow() {
+        let cell: TrustCell<_> = TrustCell::new(5);
+
+        {
+            let _a = cell.try_borrow().unwrap();
+            assert!(cell.try_borrow().is_ok());
+            assert!(cell.try_borrow_mut().is_err());
+        }
+
+        {
+            let _a = cell.try_borrow_mut().unwrap();
+            assert!(cell.try_borrow().is_err());
+            assert!(cell.try_borrow_mut().is_err());
+        }
+
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn poison() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let cell: TrustCell<_> = TrustCell::new(5);
+
+        let _ = catch_unwind(AssertUnwindSafe(|| {
+            let _a = cell.borrow();
+            panic!("read");
+        }));
+        assert!(!cell.is_poisoned());
+
+        let _ = catch_unwind(AssertUnwindSafe(|| {
+            let mut a = cell.borrow_mut();
+            *a = 7;
+            panic!("write");
+        }));
+        assert!(cell.is_poisoned());
+        assert_eq!(7, *cell.borrow());
+
+        cell.clear_poison();
+        assert!(!cell.is_poisoned());
+    }
+
+    #[test]
+    fn map() {
+        let cell: TrustCell<_> = TrustCell::new((5, vec![1, 2, 3]));
+
+        {
+            let a = Ref::map(cell.borrow(), |x| &x.1[1..]);
+            assert_eq!(&[2, 3], &*a);
+            assert!(cell.try_borrow_mut().is_err());
+        }
+
+        {
+            let mut a = RefMut::map(cell.borrow_mut(), |x| &mut x.0);
+            *a += 1;
+            assert!(cell.try_borrow().is_err());
+        }
+
+        assert_eq!(6, cell.borrow().0);
+        assert_eq!(1, cell.version());
+    }
+
+    #[test]
+    fn map_panic() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let cell: TrustCell<_> = TrustCell::new((5, vec![1, 2, 3]));
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            Ref::map(cell.borrow(), |x| -> &[i32] { panic!("{:?}", x) });
+        }));
+        assert!(result.is_err());
+        assert!(cell.try_borrow_mut().is_ok());
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            RefMut::map(cell.borrow_mut(), |x| -> &mut i32 { panic!("{:?}", x) });
+        }));
+        assert!(result.is_err());
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn downgrade() {
+        let cell: TrustCell<_> = TrustCell::new(5);
+
+        let mut a = cell.borrow_mut();
+        *a = 7;
+
+        let a = a.downgrade();
+        let b = cell.borrow();
+        assert!(cell.try_borrow_mut().is_err());
+        assert_eq!(14, *a + *b);
+
+        drop(a);
+        assert!(cell.try_borrow_mut().is_err());
+        drop(b);
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn version() {
+        let cell: TrustCell<_> = TrustCell::new(5);
+
+        {
+            let _a = cell.borrow();
+            let _b = cell.borrow();
+        }
+        assert_eq!(0, cell.version());
+
+        {
+            let a = cell.borrow_mut();
+            assert_eq!(5, *a);
+        }
+        assert_eq!(0, cell.version());
+
+        {
+            let mut a = cell.borrow_mut();
+            *a += 1;
+            *a += 1;
+        }
+        assert_eq!(1, cell.version());
+    }
+
+    #[cfg(feature = "parking")]
+    #[test]
+    fn borrow_blocking() {
+        let cell: TrustCell<_> = TrustCell::new(5);
+        let timeout = Duration::new(1, 0);
+
+        let a = cell.borrow_mut();
+
+        thread::scope(|s| {
+            let reader = s.spawn(|| *cell.borrow_blocking(timeout).unwrap());
+
+            thread::sleep(Duration::new(0, 1_000_000));
+            drop(a);
+
+            assert_eq!(5, reader.join().unwrap());
+        });
+
+        let _b = cell.borrow();
+        assert_eq!(cell.borrow_mut_blocking(Duration::new(0, 1_000_000)).unwrap_err(),
+                   BlockingError::Timeout);
+    }
+
+    #[cfg(feature = "parking")]
+    #[test]
+    fn borrow_blocking_deadlock() {
+        use std::sync::Barrier;
+
+        let cell: TrustCell<_> = TrustCell::new(5);
+        let timeout = Duration::new(10, 0);
+
+        {
+            let _a = cell.borrow_mut();
+            assert_eq!(cell.borrow_blocking(timeout).unwrap_err(), BlockingError::Deadlock);
+        }
+
+        // Two threads each holding the cell the other one waits for
+        let other: TrustCell<_> = TrustCell::new(7);
+        let barrier = Barrier::new(2);
+
+        let results = thread::scope(|s| {
+            let a = s.spawn(|| {
+                let _a = cell.borrow_mut();
+                barrier.wait();
+                other.borrow_mut_blocking(timeout).map(|_| ())
+            });
+            let b = s.spawn(|| {
+                let _b = other.borrow_mut();
+                barrier.wait();
+                cell.borrow_blocking(timeout).map(|_| ())
+            });
+
+            (a.join().unwrap(), b.join().unwrap())
+        });
+
+        // At least one of them notices, which releases the other one
+        assert!(results.0 == Err(BlockingError::Deadlock) ||
+                results.1 == Err(BlockingError::Deadlock));
+    }
+
     #[cfg(debug_assertions)]
     #[test]
     #[should_panic(expected = "Already borrowed mutably")]
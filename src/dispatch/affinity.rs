@@ -0,0 +1,202 @@
+//! Hints on which worker threads of
+//! the pool a system should run.
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::cell::Cell;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::sync::{Arc, Condvar, Mutex};
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::time::{Duration, Instant};
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use rayon::Scope;
+
+/// How long workers wait for a pinned task to be picked up
+/// by an allowed worker, before they run it themselves.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+const MAX_WAIT_MILLIS: u64 = 10;
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+thread_local!(static WORKER: Cell<Option<usize>> = const { Cell::new(None) });
+
+/// A hint on which worker threads of the
+/// thread pool a system should be executed,
+/// set with `DispatcherBuilder::with_affinity`.
+///
+/// Workers are identified by their index in the pool. Mapping
+/// workers to cores, e.g. to keep them off the core handling
+/// the callbacks of the OS, can be done in the start handler
+/// of the pool (see `register_worker`).
+///
+/// The hints are applied to the whole group the system
+/// is in, using the first hint of a system in the group.
+/// They are ignored if the systems don't run in parallel,
+/// and if the pool has no matching worker.
+///
+/// Workers which aren't allowed wait for an allowed one to
+/// pick up the group for a few milliseconds, and then run it
+/// themselves. This is counted as a violation of the hint
+/// (see `Dispatcher::affinity_violations`).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Affinity {
+    /// The system may run on any worker.
+    #[default]
+    Any,
+    /// The system should run on the worker with this index.
+    Worker(usize),
+    /// The system should run on any worker
+    /// except the one with this index.
+    AvoidWorker(usize),
+}
+
+impl Affinity {
+    /// Returns true if a system with this affinity may run on
+    /// the worker `worker` of a pool with `num_workers` workers.
+    ///
+    /// Threads which aren't registered workers may run anything.
+    pub fn allows(&self, worker: Option<usize>, num_workers: usize) -> bool {
+        match (*self, worker) {
+            (Affinity::Any, _) |
+            (_, None) => true,
+            (Affinity::Worker(index), Some(worker)) => index >= num_workers || index == worker,
+            (Affinity::AvoidWorker(index), Some(worker)) => num_workers < 2 || index != worker,
+        }
+    }
+}
+
+/// Registers the current thread as the worker with
+/// the given index, which is what affinities refer to.
+///
+/// Thread pools created by the `DispatcherBuilder` do this
+/// already. A pool passed to `DispatcherBuilder::with_pool` has
+/// to call this in its start handler for affinities to work:
+///
+/// ```rust
+/// # extern crate rayon;
+/// # extern crate shred;
+/// # use std::sync::Arc;
+/// # use rayon::{Configuration, ThreadPool};
+/// # use shred::DispatcherBuilder;
+/// # fn main() {
+/// let config = Configuration::new().start_handler(|index| {
+///     // Pin the thread to a core here
+///     shred::register_worker(index);
+/// });
+/// let pool = Arc::new(ThreadPool::new(config).unwrap());
+///
+/// let builder = DispatcherBuilder::new().with_pool(pool);
+/// # }
+/// ```
+///
+/// Only available with the `parallel` feature.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+pub fn register_worker(index: usize) {
+    WORKER.with(|worker| worker.set(Some(index)));
+}
+
+/// Returns the index the current thread
+/// was registered with, if any.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+pub fn worker_index() -> Option<usize> {
+    WORKER.with(|worker| worker.get())
+}
+
+/// A task waiting for an allowed worker.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+struct PinnedTask<F> {
+    task: Mutex<Option<F>>,
+    /// Notified once the task has been taken.
+    taken: Condvar,
+}
+
+/// Runs `task` on `scope`, on a worker `affinity` allows.
+///
+/// Rayon can't send jobs to a specific worker, so one probe per
+/// worker is spawned instead. The first probe picked up by an
+/// allowed worker runs the task. Probes picked up by other
+/// workers park them until then, so the remaining probes end up
+/// on the remaining workers. If no allowed worker picks up a
+/// probe in time, a parked worker runs the task and increments
+/// `violations`.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+pub fn spawn_pinned<'s, F>(scope: &Scope<'s>,
+                           affinity: Affinity,
+                           violations: &'s AtomicUsize,
+                           task: F)
+    where F: FnOnce() + Send + 's
+{
+    use rayon::current_num_threads;
+
+    let num_workers = current_num_threads();
+    let task = Arc::new(PinnedTask {
+                            task: Mutex::new(Some(task)),
+                            taken: Condvar::new(),
+                        });
+
+    for _ in 0..num_workers {
+        let task = task.clone();
+
+        scope.spawn(move |_| probe(&task, affinity, num_workers, violations));
+    }
+}
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+fn probe<F>(pinned: &PinnedTask<F>,
+            affinity: Affinity,
+            num_workers: usize,
+            violations: &AtomicUsize)
+    where F: FnOnce()
+{
+    let mut task = pinned.task.lock().expect("Mutex poisoned");
+    let allowed = affinity.allows(worker_index(), num_workers);
+
+    if !allowed {
+        let start = Instant::now();
+        let max_wait = Duration::from_millis(MAX_WAIT_MILLIS);
+
+        while task.is_some() {
+            let elapsed = start.elapsed();
+            if elapsed >= max_wait {
+                break;
+            }
+
+            task = pinned
+                .taken
+                .wait_timeout(task, max_wait - elapsed)
+                .expect("Mutex poisoned")
+                .0;
+        }
+    }
+
+    let task = task.take();
+    pinned.taken.notify_all();
+
+    if let Some(task) = task {
+        if !allowed {
+            violations.fetch_add(1, Ordering::Relaxed);
+        }
+
+        task();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows() {
+        assert!(Affinity::Any.allows(Some(0), 4));
+        assert!(Affinity::Worker(1).allows(Some(1), 4));
+        assert!(!Affinity::Worker(1).allows(Some(2), 4));
+        assert!(!Affinity::AvoidWorker(0).allows(Some(0), 4));
+        assert!(Affinity::AvoidWorker(0).allows(Some(3), 4));
+
+        // Hints which can't be satisfied are ignored
+        assert!(Affinity::Worker(4).allows(Some(0), 4));
+        assert!(Affinity::AvoidWorker(0).allows(Some(0), 1));
+        assert!(Affinity::Worker(1).allows(None, 4));
+    }
+}
@@ -1,10 +1,21 @@
+#[cfg(feature = "future")]
+use std::future::Future;
+#[cfg(feature = "future")]
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+#[cfg(feature = "future")]
+use std::task::{Context, Poll, Waker};
 
 use pulse::Signal;
 use rayon::ThreadPool;
 
-use dispatch::ThreadLocal;
+use dispatch::{DispatchError, RunCondition, ThreadLocal, execute_stages};
+use dispatch::fallible::Failures;
+#[cfg(feature = "profiling")]
+use dispatch::profiled::Profiler;
+use dispatch::schedule::Schedule;
 use dispatch::stage::Stage;
+use par::ParallelContext;
 use res::Resources;
 
 const ERR_NO_DISPATCH: &str = "wait() called before dispatch or called twice";
@@ -12,24 +23,47 @@ const ERR_NO_DISPATCH: &str = "wait() called before dispatch or called twice";
 /// Like, `Dispatcher` but works
 /// asynchronously.
 pub struct AsyncDispatcher<'a> {
+    conditions: Arc<Vec<RunCondition>>,
+    error: Arc<Mutex<Option<DispatchError>>>,
+    failures: Failures,
+    flush_points: Arc<Vec<usize>>,
+    names: Arc<Vec<String>>,
+    #[cfg(feature = "profiling")]
+    profiler: Arc<Profiler>,
     res: Arc<Resources>,
     signal: Option<Signal>,
     stages: Arc<Mutex<Vec<Stage<'static>>>>,
     thread_local: ThreadLocal<'a>,
     thread_pool: Arc<ThreadPool>,
+    #[cfg(feature = "future")]
+    waker: Arc<Mutex<Option<Waker>>>,
 }
 
-pub fn new_async<'a>(res: Resources,
-                     stages: Vec<Stage<'static>>,
+pub fn new_async<'a>(mut res: Resources,
+                     schedule: Schedule<'static>,
+                     failures: Failures,
                      thread_local: ThreadLocal<'a>,
                      thread_pool: Arc<ThreadPool>)
                      -> AsyncDispatcher<'a> {
+    res.entry(0).or_insert_with(|| ParallelContext::new(thread_pool.clone()));
+
+    let names = schedule.systems.into_iter().map(|info| info.name).collect();
+
     AsyncDispatcher {
+        conditions: Arc::new(schedule.conditions),
+        error: Default::default(),
+        failures: failures,
+        flush_points: Arc::new(schedule.flush_points),
+        names: Arc::new(names),
+        #[cfg(feature = "profiling")]
+        profiler: Arc::new(schedule.profiler),
         res: Arc::new(res),
         signal: None,
-        stages: Arc::new(Mutex::new(stages)),
+        stages: Arc::new(Mutex::new(schedule.stages.build())),
         thread_local: thread_local,
         thread_pool: thread_pool,
+        #[cfg(feature = "future")]
+        waker: Default::default(),
     }
 }
 
@@ -43,28 +77,73 @@ impl<'a> AsyncDispatcher<'a> {
         let (signal, pulse) = Signal::new();
         self.signal = Some(signal);
 
+        let conditions = self.conditions.clone();
+        let error = self.error.clone();
+        let failures = self.failures.clone();
+        let flush_points = self.flush_points.clone();
+        let names = self.names.clone();
+        #[cfg(feature = "profiling")]
+        let profiler = self.profiler.clone();
         let stages = self.stages.clone();
         let res = self.res.clone();
+        #[cfg(feature = "future")]
+        let waker = self.waker.clone();
 
         self.thread_pool
             .spawn_async(move || {
                 {
+                    // Move the resources in here, so they're released
+                    // before the pulse and `mut_res` can get them back.
+                    let res = res;
                     let stages = stages;
                     let mut stages = stages.lock().expect("Mutex poisoned");
 
-                    let res = &*res;
+                    let result = execute_stages(&mut *stages,
+                                                &flush_points,
+                                                &*res,
+                                                |stage, res| stage.execute(res, &conditions));
 
-                    for stage in &mut *stages {
-                        stage.execute(res);
-                    }
+                    #[cfg(feature = "profiling")]
+                    profiler.end_frame(&res);
+
+                    let result = DispatchError::check(result, &failures, |id| names[id].clone());
+                    *error.lock().expect("Mutex poisoned") = result.err();
                 }
 
                 pulse.pulse();
+
+                #[cfg(feature = "future")]
+                {
+                    if let Some(waker) = waker.lock().expect("Mutex poisoned").take() {
+                        waker.wake();
+                    }
+                }
             })
     }
 
+    /// Returns a future which resolves once the asynchronously
+    /// dispatched systems are finished, so dispatching can be
+    /// awaited inside an async runtime.
+    ///
+    /// Like `try_wait_without_tl()`, this does not execute thread
+    /// local systems; call `dispatch_thread_local()` afterwards.
+    ///
+    /// Only available with the `future` feature.
+    #[cfg(feature = "future")]
+    pub fn finished(&mut self) -> Finished {
+        Finished {
+            error: &self.error,
+            signal: &mut self.signal,
+            waker: &self.waker,
+        }
+    }
+
     /// Waits for all the asynchronously dispatched systems to finish
     /// and executes thread local systems (if there are any).
+    ///
+    /// # Panics
+    ///
+    /// Panics if a system panicked (see `try_wait_without_tl`).
     pub fn wait(&mut self) {
         self.wait_without_tl();
 
@@ -77,12 +156,28 @@ impl<'a> AsyncDispatcher<'a> {
 
     /// Waits for all the asynchronously dispatched systems to finish
     /// without executing thread local systems.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a system panicked (see `try_wait_without_tl`).
     pub fn wait_without_tl(&mut self) {
+        if let Err(e) = self.try_wait_without_tl() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `wait_without_tl`, but returns an error listing the
+    /// systems which panicked instead of panicking.
+    ///
+    /// See `Dispatcher::try_dispatch` for how panics are handled.
+    pub fn try_wait_without_tl(&mut self) -> Result<(), DispatchError> {
         self.signal
             .take()
             .expect(ERR_NO_DISPATCH)
             .wait()
             .expect("The worker thread may have panicked");
+
+        take_error(&self.error)
     }
 
     /// Dispatch only thread local systems sequentially.
@@ -113,3 +208,49 @@ impl<'a> AsyncDispatcher<'a> {
         Arc::get_mut(&mut self.res).expect(ERR_NO_DISPATCH)
     }
 }
+
+fn take_error(error: &Mutex<Option<DispatchError>>) -> Result<(), DispatchError> {
+    match error.lock().expect("Mutex poisoned").take() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Future returned by [`AsyncDispatcher::finished`],
+/// resolving to an error if systems panicked.
+///
+/// # Panics
+///
+/// Panics when polled if `dispatch()` wasn't called before.
+///
+/// [`AsyncDispatcher::finished`]: struct.AsyncDispatcher.html#method.finished
+#[cfg(feature = "future")]
+pub struct Finished<'a> {
+    error: &'a Mutex<Option<DispatchError>>,
+    signal: &'a mut Option<Signal>,
+    waker: &'a Mutex<Option<Waker>>,
+}
+
+#[cfg(feature = "future")]
+impl<'a> Future for Finished<'a> {
+    type Output = Result<(), DispatchError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.signal.as_ref().expect(ERR_NO_DISPATCH).is_pending() {
+            *self.waker.lock().expect("Mutex poisoned") = Some(cx.waker().clone());
+
+            // The systems may have finished before the waker was stored.
+            if self.signal.as_ref().expect(ERR_NO_DISPATCH).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        self.signal
+            .take()
+            .expect(ERR_NO_DISPATCH)
+            .wait()
+            .expect("The worker thread may have panicked");
+
+        Poll::Ready(take_error(self.error))
+    }
+}
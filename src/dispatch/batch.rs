@@ -0,0 +1,142 @@
+use dispatch::{DispatchError, RunCondition, execute_stages};
+#[cfg(feature = "profiling")]
+use dispatch::profiled::Profiler;
+use dispatch::fallible::{Failures, take_failures};
+use dispatch::stage::Stage;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::Resources;
+use system::RunNow;
+
+/// A group of systems, which is executed as
+/// a single system of another dispatcher.
+pub struct Batch<'a, F> {
+    conditions: Vec<RunCondition>,
+    controller: F,
+    failures: Failures,
+    flush_points: Vec<usize>,
+    names: Vec<String>,
+    outer_failures: Failures,
+    #[cfg(feature = "profiling")]
+    profiler: Profiler,
+    stages: Vec<Stage<'a>>,
+}
+
+impl<'a, F> Batch<'a, F> {
+    pub fn new(conditions: Vec<RunCondition>,
+               controller: F,
+               failures: Failures,
+               flush_points: Vec<usize>,
+               names: Vec<String>,
+               outer_failures: Failures,
+               stages: Vec<Stage<'a>>)
+               -> Self {
+        Batch {
+            conditions: conditions,
+            controller: controller,
+            failures: failures,
+            flush_points: flush_points,
+            names: names,
+            outer_failures: outer_failures,
+            #[cfg(feature = "profiling")]
+            profiler: Profiler::default(),
+            stages: stages,
+        }
+    }
+
+    /// Sets the profiler the systems of the batch record to.
+    #[cfg(feature = "profiling")]
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = profiler;
+    }
+}
+
+impl<'a, 'b, F> RunNow<'b> for Batch<'a, F>
+    where F: FnMut(&mut BatchExecutor)
+{
+    fn run_now(&mut self, res: &'b Resources) {
+        let mut executor = BatchExecutor {
+            conditions: &self.conditions,
+            flush_points: &self.flush_points,
+            names: &self.names,
+            res: res,
+            stages: &mut self.stages,
+        };
+
+        (self.controller)(&mut executor);
+
+        // Errors of fallible systems are reported
+        // to the outer dispatcher.
+        let failures = take_failures(&self.failures);
+        self.outer_failures
+            .lock()
+            .expect("Mutex poisoned")
+            .extend(failures);
+
+        // The batch is profiled as a whole, so the
+        // samples of its systems are not needed.
+        #[cfg(feature = "profiling")]
+        self.profiler.clear();
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        for stage in &mut self.stages {
+            stage.setup(res);
+        }
+    }
+}
+
+/// Passed to the controller of a batch (see
+/// `DispatcherBuilder::add_batch`), allowing it
+/// to dispatch the systems of the batch.
+pub struct BatchExecutor<'r, 'a: 'r> {
+    conditions: &'r [RunCondition],
+    flush_points: &'r [usize],
+    names: &'r [String],
+    res: &'r Resources,
+    stages: &'r mut [Stage<'a>],
+}
+
+impl<'r, 'a> BatchExecutor<'r, 'a> {
+    /// Returns the resources the batch
+    /// is dispatched with.
+    ///
+    /// Only fetch resources the systems of the batch
+    /// access, and make sure to drop them before
+    /// calling `dispatch`.
+    pub fn res(&self) -> &Resources {
+        self.res
+    }
+
+    /// Dispatches all systems of the batch once.
+    ///
+    /// Runs the systems in parallel (on the thread pool of
+    /// the outer dispatcher) if supported.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a system of the batch panicked, after its
+    /// stage has finished. The outer dispatcher then reports
+    /// the batch as the panicking system.
+    pub fn dispatch(&mut self) {
+        let conditions = self.conditions;
+
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        let result = execute_stages(self.stages,
+                                    self.flush_points,
+                                    self.res,
+                                    |stage, res| stage.execute(res, conditions));
+
+        #[cfg(any(not(feature = "parallel"), target_os = "emscripten"))]
+        let result = execute_stages(self.stages,
+                                    self.flush_points,
+                                    self.res,
+                                    |stage, res| stage.execute_seq(res, conditions));
+
+        if let Err(panics) = result {
+            let names = self.names;
+
+            panic!("{}", DispatchError::new(panics, Vec::new(), |id| names[id].clone()));
+        }
+    }
+}
@@ -1,8 +1,19 @@
-use fnv::FnvHashMap;
+use std::error::Error;
+use std::fmt::{Display, Error as FormatError, Formatter};
 
-use dispatch::{Dispatcher, SystemId, ThreadLocal};
-use dispatch::stage::StagesBuilder;
-use system::System;
+use dispatch::{Dispatcher, RunCondition, ThreadLocal};
+use dispatch::affinity::Affinity;
+use dispatch::batch::{Batch, BatchExecutor};
+#[cfg(feature = "config")]
+use dispatch::config::{ConfigEntry, DispatcherConfig, SystemConfig, SystemRegistry};
+use dispatch::dynamic::Dynamic;
+use dispatch::fallible::{Fallible, Failures};
+use dispatch::schedule::{Group, Schedule};
+use dispatch::scheduler::Scheduler;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::{ResourceId, Resources};
+use system::{Accessor, DynamicSystem, FallibleSystem, RunNow, RunningTime, System, SystemData};
 
 /// Builder for the [`Dispatcher`].
 ///
@@ -13,6 +24,13 @@ use system::System;
 /// Barriers are a way of sequentializing parts of
 /// the system execution. See `add_barrier()`.
 ///
+/// ## Tracing
+///
+/// With the `tracing` feature enabled, every system runs
+/// inside a `system` span carrying its name, nested in
+/// `stage` and `group` spans recording where it ran.
+/// Every borrow of a resource emits a trace event.
+///
 /// ## Examples
 ///
 /// This is how you create a dispatcher with
@@ -21,9 +39,14 @@ use system::System;
 /// ```rust
 /// # #![allow(unused)]
 /// #
+/// # #[cfg(feature = "parallel")]
+/// # extern crate rayon;
 /// # extern crate shred;
 /// # #[macro_use]
 /// # extern crate shred_derive;
+/// # use std::sync::Arc;
+/// # #[cfg(feature = "parallel")]
+/// # use rayon::{Configuration, ThreadPool};
 /// # use shred::{Dispatcher, DispatcherBuilder, Fetch, System};
 /// # #[derive(Debug)] struct Res;
 /// # #[derive(SystemData)] #[allow(unused)] struct Data<'a> { a: Fetch<'a, Res> }
@@ -34,13 +57,20 @@ use system::System;
 /// #   fn run(&mut self, _: Data<'a>) {}
 /// # }
 /// #
+/// # #[cfg(not(feature = "parallel"))]
+/// # fn main() {}
+/// #
+/// # #[cfg(feature = "parallel")]
 /// # fn main() {
 /// # let system_a = Dummy;
 /// # let system_b = Dummy;
 /// # let system_c = Dummy;
 /// # let system_d = Dummy;
 /// # let system_e = Dummy;
+/// let pool = Arc::new(ThreadPool::new(Configuration::new()).unwrap());
+///
 /// let dispatcher: Dispatcher = DispatcherBuilder::new()
+///     .with_pool(pool.clone())
 ///     .add(system_a, "a", &[])
 ///     .add(system_b, "b", &["a"]) // b depends on a
 ///     .add(system_c, "c", &["a"]) // c also depends on a
@@ -52,11 +82,11 @@ use system::System;
 ///
 #[derive(Default)]
 pub struct DispatcherBuilder<'a, 'b> {
-    current_id: usize,
-    map: FnvHashMap<String, SystemId>,
-    stages_builder: StagesBuilder<'a>,
+    errors: Vec<BuildError>,
+    failures: Failures,
+    schedule: Schedule<'a>,
     thread_local: ThreadLocal<'b>,
-    #[cfg(not(target_os = "emscripten"))]
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     thread_pool: Option<::std::sync::Arc<::rayon::ThreadPool>>,
 }
 
@@ -75,42 +105,326 @@ impl<'a, 'b> DispatcherBuilder<'a, 'b> {
     /// you add the depending system.
     ///
     /// If you want to register systems which can not be specified as
-    /// dependencies, you can use `""` as their name, which will not panic
-    /// (using another name twice will).
+    /// dependencies, you can use `""` as their name, which may be
+    /// used multiple times (using another name twice is an error).
     ///
-    /// # Panics
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`)
     ///
     /// * if the specified dependency does not exist
     /// * if a system with the same name was already registered.
+    ///
+    /// # Thread-local resources
+    ///
+    /// The system data has to be `Send`, which rules out
+    /// [`FetchLocal`] and [`FetchLocalMut`]; systems using
+    /// them have to be added with `add_thread_local`, so
+    /// thread-local resources never reach a worker thread.
+    ///
+    /// [`FetchLocal`]: struct.FetchLocal.html
+    /// [`FetchLocalMut`]: struct.FetchLocalMut.html
     pub fn add<T>(mut self, system: T, name: &str, dep: &[&str]) -> Self
-        where T: for<'c> System<'c> + Send + 'a
+        where T: for<'c> System<'c> + Send + 'a,
+              for<'c> <T as System<'c>>::SystemData: Send
+    {
+        let reads = T::SystemData::reads(0);
+        let writes = T::SystemData::writes(0);
+        let running_time = system.running_time();
+
+        self.insert(system, name, dep, reads, writes, running_time);
+
+        self
+    }
+
+    /// Adds a fallible system with a given name and a list of
+    /// dependencies (see `add`).
+    ///
+    /// Errors returned by the system are collected while
+    /// dispatching and returned from `Dispatcher::try_dispatch`
+    /// with the name of the system.
+    pub fn add_fallible<T>(mut self, system: T, name: &str, dep: &[&str]) -> Self
+        where T: for<'c> FallibleSystem<'c> + Send + 'a,
+              for<'c> <T as FallibleSystem<'c>>::SystemData: Send
+    {
+        let reads = T::SystemData::reads(0);
+        let writes = T::SystemData::writes(0);
+        let running_time = system.running_time();
+        let system = Fallible::new(name, system, self.failures.clone());
+
+        self.insert(system, name, dep, reads, writes, running_time);
+
+        self
+    }
+
+    /// Adds a system whose resources are determined at runtime,
+    /// with a given name and a list of dependencies (see `add`).
+    ///
+    /// The system is scheduled using the resources
+    /// declared by its accessor when it's added.
+    pub fn add_dynamic<T>(mut self, system: T, name: &str, dep: &[&str]) -> Self
+        where T: for<'c> DynamicSystem<'c> + Send + 'a,
+              for<'c> <T as DynamicSystem<'c>>::SystemData: Send
     {
-        use std::collections::hash_map::Entry;
+        fn access<'c, T: DynamicSystem<'c>>(system: &T) -> (Vec<ResourceId>, Vec<ResourceId>) {
+            let accessor = system.accessor();
+
+            (accessor.reads(), accessor.writes())
+        }
+
+        let (reads, writes) = access(&system);
+        let running_time = system.running_time();
 
-        let id = self.next_id();
+        self.insert(Dynamic::new(system), name, dep, reads, writes, running_time);
 
-        let dependencies = dep.iter()
-            .map(|x| *self.map.get(*x).expect("No such system registered"))
+        self
+    }
+
+    /// Adds a batch, which is a group of systems executed as
+    /// a single system of this dispatcher. The `controller`
+    /// decides how often the systems of the batch run
+    /// (e.g. for fixed-timestep sub-loops), by calling
+    /// `BatchExecutor::dispatch` as many times as needed.
+    ///
+    /// The batch accesses all resources its systems
+    /// access, so it conflicts with every system any
+    /// of them would conflict with. Barriers, flush points
+    /// and run conditions of `batch` are respected.
+    ///
+    /// Errors of `batch` are reported when building
+    /// this dispatcher, next to the ones of `add`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` contains thread-local systems.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use shred::{DispatcherBuilder, FetchMut, System};
+    /// # struct Physics;
+    /// # impl<'a> System<'a> for Physics {
+    /// #     type SystemData = FetchMut<'a, u32>;
+    /// #     fn run(&mut self, _: Self::SystemData) {}
+    /// # }
+    /// let physics = DispatcherBuilder::new().add(Physics, "physics", &[]);
+    ///
+    /// let dispatcher = DispatcherBuilder::new()
+    ///     .add_batch(physics, |batch| for _ in 0..4 {
+    ///         batch.dispatch();
+    ///     }, "physics_loop", &[])
+    ///     .build();
+    /// ```
+    pub fn add_batch<'c, F>(mut self,
+                            batch: DispatcherBuilder<'a, 'c>,
+                            controller: F,
+                            name: &str,
+                            dep: &[&str])
+                            -> Self
+        where F: FnMut(&mut BatchExecutor) + Send + 'a
+    {
+        assert!(batch.thread_local.is_empty(),
+                "Batches can't contain thread-local systems");
+
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+
+        let schedule = batch.schedule;
+
+        for info in &schedule.systems {
+            reads.extend(info.reads.iter().cloned());
+            writes.extend(info.writes.iter().cloned());
+        }
+
+        writes.sort();
+        writes.dedup();
+
+        self.errors.extend(batch.errors);
+
+        let names = schedule.systems.into_iter().map(|info| info.name).collect();
+
+        #[cfg_attr(not(feature = "profiling"), allow(unused_mut))]
+        let mut batch = Batch::new(schedule.conditions,
+                                   controller,
+                                   batch.failures,
+                                   schedule.flush_points,
+                                   names,
+                                   self.failures.clone(),
+                                   schedule.stages.build());
+
+        #[cfg(feature = "profiling")]
+        batch.set_profiler(schedule.profiler);
+
+        self.insert(batch, name, dep, reads, writes, RunningTime::VeryLong);
+
+        self
+    }
+
+    fn insert<T>(&mut self,
+                 system: T,
+                 name: &str,
+                 dep: &[&str],
+                 reads: Vec<ResourceId>,
+                 writes: Vec<ResourceId>,
+                 running_time: RunningTime)
+        where T: for<'c> RunNow<'c> + Send + 'a
+    {
+        self.errors.extend(self.schedule.check(name, dep));
+        self.schedule
+            .insert(system, name, dep, reads, writes, running_time);
+    }
+
+    /// Adds the systems added by `f` to a group with the given
+    /// name, which can be enabled and disabled as a whole with
+    /// `Dispatcher::set_group_enabled`.
+    ///
+    /// All systems of the group depend on `dep`, and systems
+    /// depending on the name of the group depend on all (named)
+    /// systems of the group added before. Dependencies can
+    /// also name groups, so groups can be ordered without
+    /// listing their systems. If a group and a system have
+    /// the same name, the system is used.
+    ///
+    /// Groups nest; systems belong to the innermost group,
+    /// and get the dependencies of all surrounding groups.
+    ///
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`)
+    /// if a dependency doesn't exist.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use shred::{DispatcherBuilder, ReadDefault, System, WriteDefault};
+    /// # struct Sys;
+    /// # impl<'a> System<'a> for Sys {
+    /// #     type SystemData = ReadDefault<'a, u32>;
+    /// #     fn run(&mut self, _: Self::SystemData) {}
+    /// # }
+    /// let mut dispatcher = DispatcherBuilder::new()
+    ///     .with_group("input", &[], |b| b.add(Sys, "keyboard", &[]).add(Sys, "mouse", &[]))
+    ///     .with_group("physics", &["input"], |b| b.add(Sys, "collision", &[]))
+    ///     .build();
+    ///
+    /// assert_eq!(dispatcher.group_systems("input"), vec!["keyboard", "mouse"]);
+    /// dispatcher.set_group_enabled("physics", false);
+    /// ```
+    pub fn with_group<F>(mut self, group: &str, dep: &[&str], f: F) -> Self
+        where F: FnOnce(Self) -> Self
+    {
+        let schedule = &self.schedule;
+        let missing: Vec<_> = dep.iter()
+            .filter(|x| schedule.id(x).is_none() && !schedule.is_group(x))
+            .map(|x| {
+                     BuildError::MissingDependency {
+                         system: group.to_owned(),
+                         dependency: x.to_string(),
+                     }
+                 })
             .collect();
+        self.errors.extend(missing);
 
-        if name != "" {
-            if let Entry::Vacant(e) = self.map.entry(name.to_owned()) {
-                e.insert(id);
-            } else {
-                panic!("Cannot insert multiple systems with the same name (\"{}\")", name);
-            }
+        let dependencies = self.schedule.expand(dep);
+        let outer = self.schedule
+            .group
+            .replace(Group {
+                         name: group.to_owned(),
+                         dependencies: dependencies,
+                     });
+
+        let mut builder = f(self);
+        builder.schedule.group = outer;
+
+        builder
+    }
+
+    /// Adds a run condition to the system with the given name.
+    /// The system is only executed if all of its run conditions
+    /// return `true`; they're checked right before the system
+    /// would run.
+    ///
+    /// This is useful for systems which should only run in
+    /// certain states (e.g. not while the game is paused).
+    /// To switch systems on and off directly, see
+    /// `Dispatcher::set_enabled`.
+    ///
+    /// Because other systems may be running at the same time,
+    /// the condition should only fetch resources the system
+    /// itself reads from.
+    ///
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`)
+    /// if there is no system with the given name.
+    pub fn with_run_if<F>(mut self, name: &str, f: F) -> Self
+        where F: Fn(&Resources) -> bool + Send + Sync + 'static
+    {
+        if let Some(condition) = self.condition(name) {
+            condition.run_if.push(Box::new(f));
         }
 
-        self.stages_builder.insert(dependencies, id, system);
+        self
+    }
+
+    /// Makes the system with the given name interruptible,
+    /// so it's skipped once the `CancellationToken` resource
+    /// is cancelled (e.g. because the budget of
+    /// `Dispatcher::dispatch_with_budget` is exhausted).
+    ///
+    /// Systems which already started aren't stopped;
+    /// they have to check the token themselves.
+    ///
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`)
+    /// if there is no system with the given name.
+    pub fn with_interruptible(mut self, name: &str) -> Self {
+        if let Some(condition) = self.condition(name) {
+            condition.interruptible = true;
+        }
 
         self
     }
 
+    /// Sets the affinity of the system with the given name,
+    /// i.e. on which workers of the thread pool it should run.
+    ///
+    /// This is a hint applying to the whole group of the system;
+    /// please see `Affinity` for details.
+    ///
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`)
+    /// if there is no system with the given name.
+    pub fn with_affinity(mut self, name: &str, affinity: Affinity) -> Self {
+        if let Some(condition) = self.condition(name) {
+            condition.affinity = affinity;
+        }
+
+        self
+    }
+
+    /// Returns the run condition of the system with the given
+    /// name, recording an error if there is no such system.
+    fn condition(&mut self, name: &str) -> Option<&mut RunCondition> {
+        match self.schedule.id(name) {
+            Some(id) => Some(&mut self.schedule.conditions[id.0]),
+            None => {
+                self.errors.push(BuildError::UnknownSystem(name.to_owned()));
+
+                None
+            }
+        }
+    }
+
     /// Adds a new thread local system.
     ///
-    /// Please only use this if your struct is not `Send` and `Sync`.
+    /// Please only use this if your struct is not `Send` and `Sync`
+    /// or it needs to fetch thread-local resources.
     ///
-    /// Thread-local systems are dispatched in-order.
+    /// Thread-local systems are dispatched in-order
+    /// on the thread calling `dispatch`.
     pub fn add_thread_local<T>(mut self, system: T) -> Self
         where T: for<'c> System<'c> + 'b
     {
@@ -119,6 +433,58 @@ impl<'a, 'b> DispatcherBuilder<'a, 'b> {
         self
     }
 
+    /// Adds the systems, barriers and flush points described by
+    /// `config`, creating the systems with the constructors
+    /// of `registry` (see [`DispatcherConfig`]).
+    ///
+    /// Only available with the `config` feature.
+    ///
+    /// # Errors
+    ///
+    /// Building the dispatcher fails (see `try_build`) if a
+    /// constructor is missing, or for the same reasons as `add`.
+    ///
+    /// [`DispatcherConfig`]: struct.DispatcherConfig.html
+    #[cfg(feature = "config")]
+    pub fn with_config(mut self,
+                       config: &DispatcherConfig,
+                       registry: &SystemRegistry<'a, 'b>)
+                       -> Self {
+        let disabled: Vec<&SystemConfig> = config
+            .systems
+            .iter()
+            .filter_map(|entry| match *entry {
+                            ConfigEntry::System(ref sys) if !sys.enabled => Some(sys),
+                            _ => None,
+                        })
+            .collect();
+
+        for entry in &config.systems {
+            self = match *entry {
+                ConfigEntry::System(ref sys) if sys.enabled => {
+                    let dep = enabled_dependencies(&sys.dependencies, &disabled);
+                    let constructor = sys.system.as_ref().unwrap_or(&sys.name);
+
+                    match registry.construct(self, constructor, &sys.name, &dep, sys.thread_local) {
+                        Ok(builder) => builder,
+                        Err(mut builder) => {
+                            builder
+                                .errors
+                                .push(BuildError::UnknownConstructor(constructor.clone()));
+
+                            builder
+                        }
+                    }
+                }
+                ConfigEntry::System(_) => self,
+                ConfigEntry::Barrier => self.add_barrier(),
+                ConfigEntry::FlushPoint => self.with_flush_point(),
+            };
+        }
+
+        self
+    }
+
     /// Inserts a barrier which assures that all systems
     /// added before the barrier are executed before the ones
     /// after this barrier.
@@ -129,14 +495,42 @@ impl<'a, 'b> DispatcherBuilder<'a, 'b> {
     /// Thread-local systems are not affected by barriers;
     /// they're always executed at the end.
     pub fn add_barrier(mut self) -> Self {
-        self.stages_builder.add_barrier();
+        self.schedule.add_barrier();
+
+        self
+    }
+
+    /// Inserts a flush point, which acts like a barrier
+    /// (see `add_barrier()`), but additionally flushes all
+    /// flushable resources once the systems added before
+    /// the flush point are finished.
+    ///
+    /// See [`FlushableResource`] for details.
+    ///
+    /// [`FlushableResource`]: trait.FlushableResource.html
+    pub fn with_flush_point(mut self) -> Self {
+        self.schedule.add_flush_point();
+
+        self
+    }
+
+    /// Replaces the `DefaultScheduler`, which decides how the
+    /// systems are packed into stages (see [`Scheduler`]).
+    ///
+    /// Systems added already are rescheduled.
+    ///
+    /// [`Scheduler`]: trait.Scheduler.html
+    pub fn with_scheduler<S>(mut self, scheduler: S) -> Self
+        where S: Scheduler + 'static
+    {
+        self.schedule.set_scheduler(Box::new(scheduler));
 
         self
     }
 
     /// Attach a rayon thread pool to the builder
     /// and use that instead of creating one.
-    #[cfg(not(target_os = "emscripten"))]
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     pub fn with_pool(mut self, pool: ::std::sync::Arc<::rayon::ThreadPool>) -> Self {
         self.thread_pool = Some(pool);
 
@@ -148,51 +542,201 @@ impl<'a, 'b> DispatcherBuilder<'a, 'b> {
     /// In the future, this method will
     /// precompute useful information in
     /// order to speed up dispatching.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the systems were added incorrectly,
+    /// listing all problems (see `try_build`).
     pub fn build(self) -> Dispatcher<'a, 'b> {
-        #[cfg(not(target_os = "emscripten"))]
+        match self.try_build() {
+            Ok(d) => d,
+            Err(errors) => panic_on_errors(&errors),
+        }
+    }
+
+    /// Like `build`, but returns all problems found while
+    /// adding systems instead of panicking.
+    ///
+    /// Because dependencies have to be added before the
+    /// systems depending on them, there can't be any cycles;
+    /// depending on a system added later is reported as
+    /// a missing dependency.
+    pub fn try_build(self) -> Result<Dispatcher<'a, 'b>, Vec<BuildError>> {
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
         let d = Dispatcher {
-            stages: self.stages_builder.build(),
+            failures: self.failures,
+            schedule: self.schedule,
             thread_local: self.thread_local,
             thread_pool: self.thread_pool.unwrap_or_else(Self::create_thread_pool),
         };
 
-        #[cfg(target_os = "emscripten")]
+        #[cfg(any(not(feature = "parallel"), target_os = "emscripten"))]
         let d = Dispatcher {
-            stages: self.stages_builder.build(),
+            failures: self.failures,
+            schedule: self.schedule,
             thread_local: self.thread_local,
         };
 
-        d
+        Ok(d)
     }
 
-    fn next_id(&mut self) -> SystemId {
-        let id = self.current_id;
-        self.current_id += 1;
-
-        SystemId(id)
-    }
-
-    #[cfg(not(target_os = "emscripten"))]
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     fn create_thread_pool() -> ::std::sync::Arc<::rayon::ThreadPool> {
         use std::sync::Arc;
         use rayon::{Configuration, ThreadPool};
 
-        Arc::new(ThreadPool::new(Configuration::new()).expect("Invalid thread pool configuration"))
+        use dispatch::affinity::register_worker;
+
+        let config = Configuration::new().start_handler(register_worker);
+
+        Arc::new(ThreadPool::new(config).expect("Invalid thread pool configuration"))
     }
 }
 
-#[cfg(not(target_os = "emscripten"))]
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 impl<'b> DispatcherBuilder<'static, 'b> {
     /// Builds an async dispatcher.
     ///
     /// It does not allow non-static types and
-    /// accepts a `Resource` struct.
+    /// accepts a `Resource` struct. Like `Dispatcher::setup`,
+    /// it adds a `ParallelContext` if `res` doesn't contain one.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `build`.
     pub fn build_async(self, res: ::res::Resources) -> ::dispatch::async::AsyncDispatcher<'b> {
         use dispatch::async::new_async;
 
+        if !self.errors.is_empty() {
+            panic_on_errors(&self.errors);
+        }
+
         new_async(res,
-                  self.stages_builder.build(),
+                  self.schedule,
+                  self.failures,
                   self.thread_local,
                   self.thread_pool.unwrap_or_else(Self::create_thread_pool))
     }
 }
+
+/// A problem found while building a dispatcher,
+/// as returned by [`DispatcherBuilder::try_build`],
+/// or while adding and removing systems afterwards.
+///
+/// [`DispatcherBuilder::try_build`]: struct.DispatcherBuilder.html#method.try_build
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildError {
+    /// A system was added with a name
+    /// which was already registered.
+    DuplicateName(String),
+    /// A system depends on a name which wasn't
+    /// registered before the system was added.
+    MissingDependency {
+        /// The name of the depending system.
+        system: String,
+        /// The name of the missing dependency.
+        dependency: String,
+    },
+    /// There is no system with the name to
+    /// remove or to add a run condition to.
+    UnknownSystem(String),
+    /// A `DispatcherConfig` refers to a system
+    /// constructor which isn't registered.
+    UnknownConstructor(String),
+    /// A system can't be removed because
+    /// another system depends on it.
+    HasDependent {
+        /// The name of the system to remove.
+        system: String,
+        /// The name of the depending system.
+        dependent: String,
+    },
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            BuildError::DuplicateName(ref name) => {
+                write!(f,
+                       "Cannot insert multiple systems with the same name (\"{}\")",
+                       name)
+            }
+            BuildError::MissingDependency {
+                ref system,
+                ref dependency,
+            } => {
+                write!(f,
+                       "No such system registered: \"{}\" (dependency of \"{}\")",
+                       dependency,
+                       system)
+            }
+            BuildError::UnknownSystem(ref name) => {
+                write!(f, "No such system registered: \"{}\"", name)
+            }
+            BuildError::UnknownConstructor(ref name) => {
+                write!(f, "No such system constructor registered: \"{}\"", name)
+            }
+            BuildError::HasDependent {
+                ref system,
+                ref dependent,
+            } => {
+                write!(f,
+                       "Cannot remove \"{}\", \"{}\" depends on it",
+                       system,
+                       dependent)
+            }
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn description(&self) -> &str {
+        match *self {
+            BuildError::DuplicateName(_) => "Duplicate system name",
+            BuildError::MissingDependency { .. } => "Missing system dependency",
+            BuildError::UnknownSystem(_) => "Unknown system",
+            BuildError::UnknownConstructor(_) => "Unknown system constructor",
+            BuildError::HasDependent { .. } => "System has dependents",
+        }
+    }
+}
+
+/// Replaces dependencies on disabled systems by the dependencies
+/// of those systems, so they still order the systems around them.
+#[cfg(feature = "config")]
+fn enabled_dependencies<'c>(dependencies: &'c [String],
+                            disabled: &[&'c SystemConfig])
+                            -> Vec<&'c str> {
+    let mut pending: Vec<&str> = dependencies.iter().map(|x| x.as_str()).collect();
+    let mut dep = Vec::new();
+    let mut i = 0;
+
+    while i < pending.len() {
+        let name = pending[i];
+        i += 1;
+
+        match disabled.iter().find(|sys| sys.name == name) {
+            Some(sys) => {
+                for x in &sys.dependencies {
+                    if !pending.contains(&x.as_str()) {
+                        pending.push(x);
+                    }
+                }
+            }
+            None if !dep.contains(&name) => dep.push(name),
+            None => {}
+        }
+    }
+
+    dep
+}
+
+fn panic_on_errors(errors: &[BuildError]) -> ! {
+    let errors: Vec<String> = errors.iter().map(|x| x.to_string()).collect();
+
+    panic!("Invalid dispatcher:\n{}", errors.join("\n"));
+}
@@ -0,0 +1,287 @@
+//! Building dispatchers from a description loaded at
+//! runtime, only compiled with the `config` feature.
+
+use fnv::FnvHashMap;
+
+use dispatch::DispatcherBuilder;
+use system::System;
+
+type Constructor<'a, 'b> = Box<Fn(DispatcherBuilder<'a, 'b>, &str, &[&str], bool)
+                                  -> DispatcherBuilder<'a, 'b>>;
+
+/// A description of the systems of a dispatcher,
+/// passed to `DispatcherBuilder::with_config`.
+///
+/// This can be deserialized from any format supported by serde
+/// (e.g. RON or TOML), so systems can be reordered, disabled or
+/// moved across barriers without recompiling. The systems are
+/// created by the constructors of a `SystemRegistry`.
+///
+/// # Examples
+///
+/// A config in RON:
+///
+/// ```ron
+/// (systems: [
+///     System((name: "input")),
+///     System((name: "physics", dependencies: ["input"])),
+///     Barrier,
+///     System((name: "debug_draw", system: "draw", enabled: false)),
+///     System((name: "render", thread_local: true)),
+/// ])
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DispatcherConfig {
+    /// The systems, barriers and flush
+    /// points, in the order they are added.
+    pub systems: Vec<ConfigEntry>,
+}
+
+/// An entry of a `DispatcherConfig`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ConfigEntry {
+    /// Adds a system (see `DispatcherBuilder::add`).
+    System(SystemConfig),
+    /// Adds a barrier (see `DispatcherBuilder::add_barrier`).
+    Barrier,
+    /// Adds a flush point (see `DispatcherBuilder::with_flush_point`).
+    FlushPoint,
+}
+
+/// A system of a `DispatcherConfig`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SystemConfig {
+    /// The name the system is added with.
+    pub name: String,
+    /// The name of the constructor in the `SystemRegistry`,
+    /// if it differs from `name`.
+    #[serde(default)]
+    pub system: Option<String>,
+    /// The names of the systems this system depends on.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// If true, the system is added with `add_thread_local`.
+    ///
+    /// Thread-local systems have no name, so
+    /// other systems can't depend on them.
+    #[serde(default)]
+    pub thread_local: bool,
+    /// If false, the system is skipped. Systems depending
+    /// on it depend on its dependencies instead, so the
+    /// systems around it stay ordered.
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Maps names to constructors of systems,
+/// so `DispatcherConfig`s can refer to them.
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{ConfigEntry, DispatcherBuilder, DispatcherConfig, ReadDefault, System,
+/// #             SystemConfig, SystemRegistry};
+/// struct Physics {
+///     gravity: f32,
+/// }
+///
+/// impl<'a> System<'a> for Physics {
+///     type SystemData = ReadDefault<'a, u32>;
+///
+///     fn run(&mut self, _: Self::SystemData) {}
+/// }
+///
+/// let mut registry = SystemRegistry::new();
+/// registry.register("physics", || Physics { gravity: -9.81 });
+///
+/// // Usually deserialized from a file
+/// let config = DispatcherConfig {
+///     systems: vec![ConfigEntry::System(SystemConfig {
+///                                           name: "physics".to_owned(),
+///                                           system: None,
+///                                           dependencies: vec![],
+///                                           thread_local: false,
+///                                           enabled: true,
+///                                       })],
+/// };
+///
+/// let dispatcher = DispatcherBuilder::new()
+///     .with_config(&config, &registry)
+///     .build();
+/// ```
+pub struct SystemRegistry<'a, 'b> {
+    constructors: FnvHashMap<String, Constructor<'a, 'b>>,
+}
+
+impl<'a, 'b> SystemRegistry<'a, 'b> {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        SystemRegistry { constructors: Default::default() }
+    }
+
+    /// Registers `f` to create the system called `name`,
+    /// replacing any constructor registered before.
+    ///
+    /// The system can be added as a thread-local
+    /// system or as a normal one.
+    pub fn register<F, T>(&mut self, name: &str, f: F)
+        where F: Fn() -> T + 'static,
+              T: for<'c> System<'c> + Send + 'a + 'b,
+              for<'c> <T as System<'c>>::SystemData: Send
+    {
+        self.constructors
+            .insert(name.to_owned(),
+                    Box::new(move |builder, name, dep, thread_local| if thread_local {
+                                 builder.add_thread_local(f())
+                             } else {
+                                 builder.add(f(), name, dep)
+                             }));
+    }
+
+    /// Registers `f` to create the system called `name`, which
+    /// is always added as a thread-local system (e.g. because
+    /// it isn't `Send`).
+    pub fn register_thread_local<F, T>(&mut self, name: &str, f: F)
+        where F: Fn() -> T + 'static,
+              T: for<'c> System<'c> + 'b
+    {
+        self.constructors
+            .insert(name.to_owned(),
+                    Box::new(move |builder, _, _, _| builder.add_thread_local(f())));
+    }
+
+    /// Returns true if a constructor is registered as `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.constructors.contains_key(name)
+    }
+
+    /// Adds the system created by the constructor `system` to
+    /// `builder` (see `DispatcherBuilder::add`).
+    ///
+    /// # Errors
+    ///
+    /// Returns `builder` unchanged if there is no such constructor.
+    // The builder is handed back on purpose, so it can't be boxed
+    #[allow(clippy::result_large_err)]
+    pub fn construct(&self,
+                     builder: DispatcherBuilder<'a, 'b>,
+                     system: &str,
+                     name: &str,
+                     dep: &[&str],
+                     thread_local: bool)
+                     -> Result<DispatcherBuilder<'a, 'b>, DispatcherBuilder<'a, 'b>> {
+        match self.constructors.get(system) {
+            Some(constructor) => Ok(constructor(builder, name, dep, thread_local)),
+            None => Err(builder),
+        }
+    }
+}
+
+impl<'a, 'b> Default for SystemRegistry<'a, 'b> {
+    fn default() -> Self {
+        SystemRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dispatch::BuildError;
+    use res::Resources;
+    use WriteDefault;
+
+    struct Push(&'static str);
+
+    impl<'a> System<'a> for Push {
+        type SystemData = WriteDefault<'a, Vec<&'static str>>;
+
+        fn run(&mut self, mut list: Self::SystemData) {
+            list.push(self.0);
+        }
+    }
+
+    struct Nop;
+
+    impl<'a> System<'a> for Nop {
+        type SystemData = ();
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    fn registry() -> SystemRegistry<'static, 'static> {
+        let mut registry = SystemRegistry::new();
+        registry.register("nop", || Nop);
+        registry.register("a", || Push("a"));
+        registry.register("b", || Push("b"));
+        registry.register_thread_local("local", || Push("local"));
+
+        registry
+    }
+
+    #[test]
+    fn with_config() {
+        use serde_json::from_str;
+
+        let config: DispatcherConfig = from_str(r#"{"systems": [
+            {"System": {"name": "b"}},
+            {"System": {"name": "a", "dependencies": ["b", "c"]}},
+            {"System": {"name": "c", "system": "a", "enabled": false}},
+            "Barrier",
+            {"System": {"name": "local"}},
+            {"System": {"name": "second_b", "system": "b", "thread_local": true}}
+        ]}"#)
+            .unwrap();
+
+        let mut res = Resources::new();
+        let mut dispatcher = DispatcherBuilder::new()
+            .with_config(&config, &registry())
+            .build();
+        dispatcher.setup(&mut res);
+        dispatcher.dispatch(&mut res);
+
+        assert_eq!(*res.fetch::<Vec<&str>>(0), vec!["b", "a", "local", "b"]);
+    }
+
+    #[test]
+    fn disabled_dependency() {
+        use serde_json::from_str;
+
+        let config: DispatcherConfig = from_str(r#"{"systems": [
+            {"System": {"name": "a", "system": "nop"}},
+            {"System": {"name": "b", "system": "nop", "dependencies": ["a"], "enabled": false}},
+            {"System": {"name": "c", "system": "nop", "dependencies": ["b"]}}
+        ]}"#)
+            .unwrap();
+
+        let dispatcher = DispatcherBuilder::new()
+            .with_config(&config, &registry())
+            .build();
+
+        assert_eq!(dispatcher.stages(), vec![vec!["a"], vec!["c"]]);
+    }
+
+    #[test]
+    fn unknown_constructor() {
+        let config = DispatcherConfig {
+            systems: vec![ConfigEntry::System(SystemConfig {
+                                                  name: "missing".to_owned(),
+                                                  system: None,
+                                                  dependencies: vec![],
+                                                  thread_local: false,
+                                                  enabled: true,
+                                              })],
+        };
+
+        let errors = DispatcherBuilder::new()
+            .with_config(&config, &registry())
+            .try_build()
+            .err()
+            .unwrap();
+
+        assert_eq!(errors, vec![BuildError::UnknownConstructor("missing".to_owned())]);
+    }
+}
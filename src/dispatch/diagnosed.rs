@@ -0,0 +1,62 @@
+//! Tracking of the system running on the current thread,
+//! only compiled with the `diagnostics` feature.
+
+use std::cell::RefCell;
+use std::mem::replace;
+
+use res::Resources;
+use system::RunNow;
+
+thread_local! {
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Returns the name of the system running
+/// on the current thread, if any.
+pub fn current_system() -> Option<String> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Wraps a system, so borrows it makes
+/// can be attributed to it.
+pub struct Diagnosed<T> {
+    inner: T,
+    name: String,
+}
+
+impl<T> Diagnosed<T> {
+    pub fn new(name: &str, inner: T) -> Self {
+        Diagnosed {
+            inner: inner,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl<'a, T> RunNow<'a> for Diagnosed<T>
+    where T: RunNow<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        // Systems can run nested dispatchers (see `add_batch`)
+        let name = Some(self.name.clone());
+        let outer = CURRENT.with(|current| replace(&mut *current.borrow_mut(), name));
+
+        // Restore the outer system even if this one panics
+        struct Restore(Option<Option<String>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                let outer = self.0.take().unwrap();
+                CURRENT.with(|current| *current.borrow_mut() = outer);
+            }
+        }
+
+        let _restore = Restore(Some(outer));
+
+        self.inner.run_now(res);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
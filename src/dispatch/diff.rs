@@ -0,0 +1,137 @@
+//! Comparison of two dispatcher schedules.
+
+use dispatch::SystemInfo;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// The differences between two dispatcher schedules,
+/// as returned by [`Dispatcher::diff`].
+///
+/// All lists contain system names and are sorted.
+/// This is useful for detecting accidental schedule
+/// changes, e.g. in a regression test.
+///
+/// [`Dispatcher::diff`]: struct.Dispatcher.html#method.diff
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ScheduleDiff {
+    /// Systems which only exist in the new schedule.
+    pub added: Vec<String>,
+    /// Systems which only exist in the old schedule.
+    pub removed: Vec<String>,
+    /// Systems existing in both schedules, but with
+    /// different explicit dependencies.
+    pub changed_dependencies: Vec<String>,
+    /// Systems existing in both schedules, but
+    /// assigned to a different stage.
+    pub changed_stages: Vec<String>,
+}
+
+impl ScheduleDiff {
+    /// Returns `true` if both schedules are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() &&
+        self.changed_dependencies.is_empty() && self.changed_stages.is_empty()
+    }
+}
+
+pub fn diff(old: &[SystemInfo], new: &[SystemInfo]) -> ScheduleDiff {
+    let mut diff = ScheduleDiff::default();
+
+    for info in old.iter().filter(|x| !x.name.is_empty()) {
+        match new.iter().find(|x| x.name == info.name) {
+            Some(other) => {
+                if sorted(&info.dependencies) != sorted(&other.dependencies) {
+                    diff.changed_dependencies.push(info.name.clone());
+                }
+
+                if info.stage != other.stage {
+                    diff.changed_stages.push(info.name.clone());
+                }
+            }
+            None => diff.removed.push(info.name.clone()),
+        }
+    }
+
+    for info in new.iter().filter(|x| !x.name.is_empty()) {
+        if !old.iter().any(|x| x.name == info.name) {
+            diff.added.push(info.name.clone());
+        }
+    }
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed_dependencies.sort();
+    diff.changed_stages.sort();
+
+    diff
+}
+
+fn sorted(names: &[String]) -> Vec<&str> {
+    let mut names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+    names.sort();
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use dispatch::DispatcherBuilder;
+    use res::{Fetch, FetchMut};
+    use system::System;
+
+    struct ResA;
+
+    struct Read;
+
+    impl<'a> System<'a> for Read {
+        type SystemData = Fetch<'a, ResA>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    struct Write;
+
+    impl<'a> System<'a> for Write {
+        type SystemData = FetchMut<'a, ResA>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    #[test]
+    fn equal() {
+        let a = DispatcherBuilder::new()
+            .add(Read, "a", &[])
+            .add(Write, "b", &["a"])
+            .build();
+        let b = DispatcherBuilder::new()
+            .add(Read, "a", &[])
+            .add(Write, "b", &["a"])
+            .build();
+
+        assert!(a.diff(&b).is_empty());
+    }
+
+    #[test]
+    fn changes() {
+        let old = DispatcherBuilder::new()
+            .add(Read, "a", &[])
+            .add(Read, "b", &[])
+            .add(Read, "c", &[])
+            .add(Read, "d", &[])
+            .build();
+        let new = DispatcherBuilder::new()
+            .add(Read, "a", &[])
+            .add(Write, "b", &[])
+            .add(Read, "c", &["a"])
+            .add(Read, "e", &[])
+            .build();
+
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.added, vec!["e".to_owned()]);
+        assert_eq!(diff.removed, vec!["d".to_owned()]);
+        assert_eq!(diff.changed_dependencies, vec!["c".to_owned()]);
+        assert_eq!(diff.changed_stages,
+                   vec!["b".to_owned(), "c".to_owned()]);
+    }
+}
@@ -0,0 +1,111 @@
+//! Export of the system graph in the Graphviz DOT format.
+
+use std::io::{Result, Write};
+
+use dispatch::SystemInfo;
+
+pub fn write_dot<W>(systems: &[SystemInfo], out: &mut W) -> Result<()>
+    where W: Write
+{
+    writeln!(out, "digraph dispatcher {{")?;
+
+    let num_stages = systems.iter().map(|x| x.stage + 1).max().unwrap_or(0);
+
+    for stage in 0..num_stages {
+        writeln!(out, "    subgraph cluster_{} {{", stage)?;
+        writeln!(out, "        label = \"stage {}\";", stage)?;
+
+        for (index, info) in systems.iter().enumerate().filter(|&(_, x)| x.stage == stage) {
+            writeln!(out,
+                     "        s{} [label = \"{}\"];",
+                     index,
+                     info.name.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+
+        writeln!(out, "    }}")?;
+    }
+
+    // Explicit dependencies
+    for (index, info) in systems.iter().enumerate() {
+        for dep in &info.dependencies {
+            if let Some(dep) = systems.iter().position(|x| x.name == *dep) {
+                writeln!(out, "    s{} -> s{};", dep, index)?;
+            }
+        }
+    }
+
+    // Conflicts, pointing from the system which was added first
+    for (index, info) in systems.iter().enumerate() {
+        for (other_index, other) in systems.iter().enumerate().skip(index + 1) {
+            if conflicts(info, other) {
+                writeln!(out, "    s{} -> s{} [style = dashed];", index, other_index)?;
+            }
+        }
+    }
+
+    writeln!(out, "}}")
+}
+
+fn conflicts(a: &SystemInfo, b: &SystemInfo) -> bool {
+    a.writes
+        .iter()
+        .any(|x| b.reads.contains(x) || b.writes.contains(x)) ||
+    b.writes.iter().any(|x| a.reads.contains(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use dispatch::DispatcherBuilder;
+    use res::{Fetch, FetchMut};
+    use system::System;
+
+    struct ResA;
+
+    struct Read;
+
+    impl<'a> System<'a> for Read {
+        type SystemData = Fetch<'a, ResA>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    struct Write;
+
+    impl<'a> System<'a> for Write {
+        type SystemData = FetchMut<'a, ResA>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    #[test]
+    fn dot() {
+        let d = DispatcherBuilder::new()
+            .add(Read, "a", &[])
+            .add(Read, "b\"", &["a"])
+            .add(Write, "c", &[])
+            .build();
+
+        let mut out = Vec::new();
+        d.write_dot(&mut out).unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "digraph dispatcher {
+    subgraph cluster_0 {
+        label = \"stage 0\";
+        s0 [label = \"a\"];
+    }
+    subgraph cluster_1 {
+        label = \"stage 1\";
+        s1 [label = \"b\\\"\"];
+    }
+    subgraph cluster_2 {
+        label = \"stage 2\";
+        s2 [label = \"c\"];
+    }
+    s0 -> s1;
+    s0 -> s2 [style = dashed];
+    s1 -> s2 [style = dashed];
+}
+");
+    }
+}
@@ -0,0 +1,30 @@
+//! Running systems with resources
+//! determined at runtime.
+
+use res::Resources;
+use system::{DynamicSystem, DynamicSystemData, RunNow};
+
+/// Wraps a dynamic system, fetching its
+/// data using its accessor.
+pub struct Dynamic<T> {
+    inner: T,
+}
+
+impl<T> Dynamic<T> {
+    pub fn new(inner: T) -> Self {
+        Dynamic { inner: inner }
+    }
+}
+
+impl<'a, T> RunNow<'a> for Dynamic<T>
+    where T: DynamicSystem<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        let data = T::SystemData::fetch(self.inner.accessor(), res);
+        self.inner.run(data);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
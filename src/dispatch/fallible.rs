@@ -0,0 +1,56 @@
+//! Collecting the errors of fallible systems.
+
+use std::error::Error;
+use std::mem::take;
+use std::sync::{Arc, Mutex};
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::Resources;
+use system::{FallibleSystem, RunNow, SystemData};
+
+/// The errors returned by fallible systems,
+/// together with the names of the systems.
+pub type Failures = Arc<Mutex<Vec<(String, Box<Error + Send + Sync>)>>>;
+
+/// Takes all errors collected so far.
+pub fn take_failures(failures: &Failures) -> Vec<(String, Box<Error + Send + Sync>)> {
+    take(&mut *failures.lock().expect("Mutex poisoned"))
+}
+
+/// Wraps a fallible system, storing
+/// its errors in `Failures`.
+pub struct Fallible<T> {
+    failures: Failures,
+    inner: T,
+    name: String,
+}
+
+impl<T> Fallible<T> {
+    pub fn new(name: &str, inner: T, failures: Failures) -> Self {
+        Fallible {
+            failures: failures,
+            inner: inner,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl<'a, T> RunNow<'a> for Fallible<T>
+    where T: FallibleSystem<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        let data = T::SystemData::fetch(res, 0);
+
+        if let Err(e) = self.inner.run(data) {
+            self.failures
+                .lock()
+                .expect("Mutex poisoned")
+                .push((self.name.clone(), Box::new(e)));
+        }
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
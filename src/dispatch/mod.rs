@@ -1,29 +1,178 @@
-pub use self::builder::DispatcherBuilder;
-#[cfg(not(target_os = "emscripten"))]
+pub use self::affinity::Affinity;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+pub use self::affinity::register_worker;
+pub use self::batch::BatchExecutor;
+#[cfg(feature = "diagnostics")]
+pub use self::diagnosed::current_system;
+pub use self::builder::{BuildError, DispatcherBuilder};
+#[cfg(feature = "config")]
+pub use self::config::{ConfigEntry, DispatcherConfig, SystemConfig, SystemRegistry};
+pub use self::diff::ScheduleDiff;
+pub use self::par_seq::{Nil, Par, ParSeq, RunWithPool, Seq};
+pub use self::scheduler::{DefaultScheduler, DeterministicScheduler, NewSystem, Placement,
+                          Scheduler};
+pub use self::stage::Layout;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 pub use self::async::AsyncDispatcher;
+#[cfg(all(feature = "future", feature = "parallel", not(target_os = "emscripten")))]
+pub use self::async::Finished;
+
+#[cfg(feature = "std")]
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{Display, Error as FormatError, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use smallvec::SmallVec;
 
-use res::Resources;
-use system::RunNow;
+use cancel::CancellationToken;
+use par::ParallelContext;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::{ResourceId, Resources};
+use system::{RunNow, RunningTime, System, SystemData};
 
-use self::stage::Stage;
+use self::fallible::{Failures, take_failures};
+use self::schedule::Schedule;
+use self::stage::{Panics, Stage};
 
-#[cfg(not(target_os = "emscripten"))]
+mod affinity;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 mod async;
+mod batch;
 mod builder;
+#[cfg(feature = "config")]
+mod config;
+#[cfg(feature = "diagnostics")]
+mod diagnosed;
+mod diff;
+#[cfg(feature = "std")]
+mod dot;
+mod dynamic;
+mod fallible;
+mod par_seq;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+mod pipeline;
+#[cfg(feature = "profiling")]
+mod profiled;
+#[cfg(feature = "std")]
+mod resolved;
+mod schedule;
+mod scheduler;
 mod stage;
+#[cfg(feature = "tracing")]
+mod traced;
 
 /// The dispatcher struct, allowing
 /// systems to be executed in parallel.
 pub struct Dispatcher<'a, 'b> {
-    stages: Vec<Stage<'a>>,
+    failures: Failures,
+    schedule: Schedule<'a>,
     thread_local: ThreadLocal<'b>,
-    #[cfg(not(target_os = "emscripten"))]
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     thread_pool: ::std::sync::Arc<::rayon::ThreadPool>,
 }
 
 impl<'a, 'b> Dispatcher<'a, 'b> {
+    /// Sets up all the systems, including thread local systems,
+    /// so they can add the resources they require.
+    ///
+    /// The systems are set up stage by stage and thread local
+    /// systems last, so dependencies are set up before their
+    /// dependents. This allows building a dispatcher
+    /// against an empty `Resources` container.
+    ///
+    /// A `ParallelContext` for the thread pool of this dispatcher
+    /// is added first, unless `res` already contains one (e.g.
+    /// added by another dispatcher sharing the resources). To
+    /// switch pools, replace it with `parallel_context`.
+    pub fn setup(&mut self, res: &mut Resources) {
+        res.entry(0).or_insert_with(|| self.parallel_context());
+
+        for stage in self.schedule.stages.stages_mut() {
+            stage.setup(res);
+        }
+
+        for sys in &mut self.thread_local {
+            sys.setup(res);
+        }
+    }
+
+    /// Returns a `ParallelContext` for the thread pool of this
+    /// dispatcher, which runs everything on the current thread
+    /// without the `parallel` feature.
+    pub fn parallel_context(&self) -> ParallelContext {
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        {
+            ParallelContext::new(self.thread_pool.clone())
+        }
+
+        #[cfg(any(not(feature = "parallel"), target_os = "emscripten"))]
+        {
+            ParallelContext::sequential()
+        }
+    }
+
+    /// Adds a new system with a given name and a list of
+    /// dependencies to the built dispatcher, like
+    /// `DispatcherBuilder::add`.
+    ///
+    /// Only the stages the system can be inserted into are
+    /// affected; it's scheduled after all barriers and isn't
+    /// set up automatically (see `setup`).
+    ///
+    /// Only plain systems can be added this way; fallible,
+    /// dynamic, batch and thread local systems have to be
+    /// added with the `DispatcherBuilder`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the problems `DispatcherBuilder::try_build`
+    /// would report for the system, in which case
+    /// it isn't added.
+    pub fn add_system<T>(&mut self,
+                         system: T,
+                         name: &str,
+                         dep: &[&str])
+                         -> Result<(), Vec<BuildError>>
+        where T: for<'c> System<'c> + Send + 'a,
+              for<'c> <T as System<'c>>::SystemData: Send
+    {
+        let errors = self.schedule.check(name, dep);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let reads = T::SystemData::reads(0);
+        let writes = T::SystemData::writes(0);
+        let running_time = system.running_time();
+
+        self.schedule
+            .insert(system, name, dep, reads, writes, running_time);
+
+        Ok(())
+    }
+
+    /// Removes the system with the given name.
+    ///
+    /// The stages are recomputed from the remaining systems,
+    /// which keep their state, run conditions and order,
+    /// so this is useful e.g. for swapping systems
+    /// while live-reloading.
+    ///
+    /// This isn't incremental: all systems are scheduled
+    /// again, which costs about as much as building the
+    /// dispatcher, so it shouldn't be done every frame.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no system with the given name
+    /// or another system depends on it.
+    pub fn remove_system(&mut self, name: &str) -> Result<(), BuildError> {
+        self.schedule.remove(name)
+    }
+
     /// Dispatch all the systems with given resources and context
     /// and then run thread local systems.
     ///
@@ -34,16 +183,95 @@ impl<'a, 'b> Dispatcher<'a, 'b> {
     ///
     /// and runs `dispatch_thread_local` afterwards.
     ///
+    /// # Panics
+    ///
+    /// Panics if a system panicked, after the stage of the
+    /// system has finished, or if a fallible system returned
+    /// an error, after all systems have run (see `try_dispatch`).
+    ///
     /// [`dispatch_par`]: struct.Dispatcher.html#method.dispatch_par
     /// [`dispatch_seq`]: struct.Dispatcher.html#method.dispatch_seq
     pub fn dispatch(&mut self, res: &mut Resources) {
-        #[cfg(not(target_os = "emscripten"))]
-        self.dispatch_par(res);
+        if let Err(e) = self.try_dispatch(res) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `dispatch`, but returns an error listing the
+    /// systems which panicked or failed instead of panicking.
+    ///
+    /// Panics are caught per system, so the other systems of
+    /// the stage still finish. The following stages and thread
+    /// local systems are not dispatched then. Resources which
+    /// the panicking systems were writing to are poisoned
+    /// (see `Resources::is_poisoned`), so the application can
+    /// decide whether to repair them or to abort.
+    ///
+    /// Errors returned by fallible systems (see
+    /// `DispatcherBuilder::add_fallible`) don't stop the
+    /// dispatch, including the thread local systems; all
+    /// of them are collected in the error.
+    ///
+    /// Panics of thread local systems are not caught.
+    ///
+    /// Without the `std` feature, panics can't be caught at all:
+    /// they unwind through this method (or abort, depending on the
+    /// target), so the error only ever lists failed systems.
+    pub fn try_dispatch(&mut self, res: &mut Resources) -> Result<(), DispatchError> {
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        let result = self.try_dispatch_par(res);
+
+        #[cfg(any(not(feature = "parallel"), target_os = "emscripten"))]
+        let result = self.try_dispatch_seq(res);
+
+        match result {
+            Err(ref e) if !e.panicked().is_empty() => {}
+            _ => self.dispatch_thread_local(res),
+        }
+
+        result
+    }
+
+    /// Like `dispatch`, but cancels the dispatch
+    /// once `budget` has passed.
+    ///
+    /// This adds a `CancellationToken` to the resources if there
+    /// is none, and sets its deadline only for this dispatch.
+    /// A token already cancelled with `cancel` stays cancelled.
+    /// Interruptible systems (see `DispatcherBuilder::with_interruptible`)
+    /// which haven't started yet are skipped after the deadline,
+    /// and systems fetching the token can stop early.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `dispatch`.
+    #[cfg(feature = "std")]
+    pub fn dispatch_with_budget(&mut self, res: &mut Resources, budget: Duration) {
+        if let Err(e) = self.try_dispatch_with_budget(res, budget) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `dispatch_with_budget`, but returns an error
+    /// instead of panicking (see `try_dispatch`).
+    #[cfg(feature = "std")]
+    pub fn try_dispatch_with_budget(&mut self,
+                                    res: &mut Resources,
+                                    budget: Duration)
+                                    -> Result<(), DispatchError> {
+        use std::time::Instant;
+
+        if !res.has_value(ResourceId::new::<CancellationToken>()) {
+            res.add(CancellationToken::new());
+        }
 
-        #[cfg(target_os = "emscripten")]
-        self.dispatch_seq(res);
+        let token = res.fetch::<CancellationToken>(0).clone();
+        token.set_deadline(Some(Instant::now() + budget));
 
-        self.dispatch_thread_local(res);
+        let result = self.try_dispatch(res);
+        token.set_deadline(None);
+
+        result
     }
 
     /// Dispatches the systems (except thread local systems)
@@ -53,24 +281,260 @@ impl<'a, 'b> Dispatcher<'a, 'b> {
     /// executing thread.
     ///
     /// Only available on platforms with
-    /// multithreading support (so not on emscripten).
-    #[cfg(not(target_os = "emscripten"))]
+    /// multithreading support (so only with the `parallel`
+    /// feature and not on emscripten).
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `dispatch`.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     pub fn dispatch_par(&mut self, res: &mut Resources) {
-        let stages = &mut self.stages;
+        if let Err(e) = self.try_dispatch_par(res) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `dispatch_par`, but returns an error
+    /// instead of panicking (see `try_dispatch`).
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn try_dispatch_par(&mut self, res: &mut Resources) -> Result<(), DispatchError> {
+        let schedule = &mut self.schedule;
+        let conditions = &schedule.conditions;
+        let stages = schedule.stages.stages_mut();
+        let flush_points = &schedule.flush_points;
+        #[cfg(feature = "profiling")]
+        let profiler = &schedule.profiler;
+
+        let result = self.thread_pool
+            .install(move || {
+                let result = execute_stages(stages,
+                                            flush_points,
+                                            res,
+                                            |stage, res| stage.execute(res, conditions));
+
+                #[cfg(feature = "profiling")]
+                profiler.end_frame(res);
+
+                result
+            });
+
+        let systems = &self.schedule.systems;
+
+        DispatchError::check(result, &self.failures, |id| systems[id].name.clone())
+    }
+
+    /// Dispatches `frames` frames in parallel, running the last stage
+    /// of every frame together with the first stage of the next one.
+    ///
+    /// This only happens if the systems of these two stages don't
+    /// conflict, which can be checked with `is_pipelined`, and if
+    /// there are no thread local systems. Otherwise, this is the same
+    /// as calling `dispatch` `frames` times.
+    ///
+    /// Only available on platforms with
+    /// multithreading support (so only with the `parallel`
+    /// feature and not on emscripten).
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `dispatch`.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn dispatch_pipelined(&mut self, res: &mut Resources, frames: usize) {
+        if let Err(e) = self.try_dispatch_pipelined(res, frames) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `dispatch_pipelined`, but returns an error
+    /// instead of panicking (see `try_dispatch`).
+    ///
+    /// No further frames are dispatched after an error.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn try_dispatch_pipelined(&mut self,
+                                  res: &mut Resources,
+                                  frames: usize)
+                                  -> Result<(), DispatchError> {
+        if !self.is_pipelined() {
+            for _ in 0..frames {
+                self.try_dispatch(res)?;
+            }
+
+            return Ok(());
+        }
+
+        let schedule = &mut self.schedule;
+        let conditions = &schedule.conditions;
+        let stages = schedule.stages.stages_mut();
+        let flush_points = &schedule.flush_points;
+        #[cfg(feature = "profiling")]
+        let profiler = &schedule.profiler;
+
+        let result = self.thread_pool
+            .install(move || {
+                pipeline::execute_pipelined(stages,
+                                            flush_points,
+                                            conditions,
+                                            res,
+                                            frames,
+                                            |_res| {
+                                                #[cfg(feature = "profiling")]
+                                                profiler.end_frame(_res);
+                                            })
+            });
+
+        let systems = &self.schedule.systems;
+
+        DispatchError::check(result, &self.failures, |id| systems[id].name.clone())
+    }
 
-        self.thread_pool
-            .install(move || for stage in stages {
-                         stage.execute(res);
-                     });
+    /// Returns true if `dispatch_pipelined` overlaps successive frames.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn is_pipelined(&self) -> bool {
+        let schedule = &self.schedule;
+
+        self.thread_local.is_empty() &&
+        pipeline::can_pipeline(&schedule.systems,
+                               schedule.stages.num_stages(),
+                               &schedule.flush_points)
     }
 
     /// Dispatches the systems (except thread local systems) sequentially.
     ///
     /// This is useful if parallel overhead is
     /// too big or the platform does not support multithreading.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `dispatch`.
     pub fn dispatch_seq(&mut self, res: &mut Resources) {
-        for stage in &mut self.stages {
-            stage.execute_seq(res);
+        if let Err(e) = self.try_dispatch_seq(res) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Like `dispatch_seq`, but returns an error
+    /// instead of panicking (see `try_dispatch`).
+    pub fn try_dispatch_seq(&mut self, res: &mut Resources) -> Result<(), DispatchError> {
+        let schedule = &mut self.schedule;
+        let conditions = &schedule.conditions;
+
+        let result = execute_stages(schedule.stages.stages_mut(),
+                                    &schedule.flush_points,
+                                    res,
+                                    |stage, res| stage.execute_seq(res, conditions));
+
+        #[cfg(feature = "profiling")]
+        schedule.profiler.end_frame(res);
+
+        let systems = &schedule.systems;
+
+        DispatchError::check(result, &self.failures, |id| systems[id].name.clone())
+    }
+
+    /// Enables or disables the system with the given name.
+    ///
+    /// Disabled systems are skipped when dispatching,
+    /// regardless of their run conditions (see
+    /// `DispatcherBuilder::with_run_if`).
+    /// Systems are enabled by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no system with the given name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        let index = self.schedule
+            .systems
+            .iter()
+            .position(|info| info.name == name)
+            .expect("No such system registered");
+
+        self.schedule.conditions[index].disabled = !enabled;
+    }
+
+    /// Returns the average running time of the system with the
+    /// given name, weighting recent dispatches the most.
+    ///
+    /// Returns `None` if there is no such system or if it
+    /// hasn't run yet. The groups of every stage are started
+    /// in the order of these times, longest first.
+    ///
+    /// Running times are only measured if systems can run in
+    /// parallel, so this always returns `None` without the
+    /// `parallel` feature.
+    pub fn average_time(&self, name: &str) -> Option<Duration> {
+        let id = self.schedule.id(name)?;
+
+        match self.schedule.conditions[id.0].average() {
+            0 => None,
+            nanos => Some(from_nanos(nanos as u64)),
+        }
+    }
+
+    /// Returns how often the affinity of the system with the given
+    /// name couldn't be satisfied, so its group ran on a worker
+    /// the hint doesn't allow (see `Affinity`).
+    ///
+    /// Returns `None` if there is no such system.
+    pub fn affinity_violations(&self, name: &str) -> Option<usize> {
+        self.schedule
+            .id(name)
+            .map(|id| {
+                     self.schedule.conditions[id.0]
+                         .affinity_violations
+                         .load(Ordering::Relaxed)
+                 })
+    }
+
+    /// Enables or disables all systems of the given group
+    /// (see `DispatcherBuilder::with_group` and `set_enabled`).
+    ///
+    /// This is tracked separately from `set_enabled`: a system
+    /// only runs if both it and its group are enabled, so a system
+    /// disabled on its own stays disabled when its group is enabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no group with the given name.
+    pub fn set_group_enabled(&mut self, group: &str, enabled: bool) {
+        assert!(self.schedule.is_group(group), "No such group registered");
+
+        let schedule = &mut self.schedule;
+
+        for (info, condition) in schedule.systems.iter().zip(&mut schedule.conditions) {
+            if info.group.as_ref().is_some_and(|x| x == group) {
+                condition.group_disabled = !enabled;
+            }
+        }
+    }
+
+    /// Returns the names of the systems in the given group,
+    /// in the order they were added.
+    pub fn group_systems(&self, group: &str) -> Vec<&str> {
+        self.schedule
+            .systems
+            .iter()
+            .filter(|info| info.group.as_ref().is_some_and(|x| x == group))
+            .map(|info| info.name.as_str())
+            .collect()
+    }
+
+    /// Returns the sum of the average running times of the
+    /// systems in the given group (see `average_time`).
+    ///
+    /// Returns `None` if there is no such group
+    /// or none of its systems has run yet.
+    pub fn group_time(&self, group: &str) -> Option<Duration> {
+        let schedule = &self.schedule;
+        let nanos = schedule
+            .systems
+            .iter()
+            .zip(&schedule.conditions)
+            .filter(|&(info, _)| info.group.as_ref().is_some_and(|x| x == group))
+            .fold(0u64, |sum, (_, condition)| sum.saturating_add(condition.average() as u64));
+
+        match nanos {
+            0 => None,
+            nanos => Some(from_nanos(nanos)),
         }
     }
 
@@ -80,11 +544,344 @@ impl<'a, 'b> Dispatcher<'a, 'b> {
             sys.run_now(res);
         }
     }
+
+    /// Returns an iterator over all systems (except thread local systems)
+    /// in the order they were added, together with the resources
+    /// they read from and write to.
+    ///
+    /// This only reads metadata computed when the dispatcher was
+    /// built, so it's useful e.g. for generating documentation of
+    /// the system architecture.
+    pub fn systems(&self) -> Systems {
+        Systems { inner: self.schedule.systems.iter() }
+    }
+
+    /// Returns the names of the systems (except thread local systems)
+    /// assigned to each stage, in execution order.
+    ///
+    /// Systems of the same stage may run in parallel, while
+    /// stages are executed one after another.
+    pub fn stages(&self) -> Vec<Vec<&str>> {
+        let mut stages = vec![Vec::new(); self.schedule.stages.num_stages()];
+
+        for info in &self.schedule.systems {
+            stages[info.stage].push(info.name.as_str());
+        }
+
+        stages
+    }
+
+    /// Returns the names of all systems (except thread local systems)
+    /// reading from the resource `id`, in the order they were added.
+    pub fn readers(&self, id: ResourceId) -> Vec<&str> {
+        self.schedule
+            .systems
+            .iter()
+            .filter(|info| info.reads.contains(&id))
+            .map(|info| info.name.as_str())
+            .collect()
+    }
+
+    /// Returns the names of all systems (except thread local systems)
+    /// writing to the resource `id`, in the order they were added.
+    ///
+    /// These systems can neither run in parallel with each other
+    /// nor with the systems returned by `readers`.
+    pub fn writers(&self, id: ResourceId) -> Vec<&str> {
+        self.schedule
+            .systems
+            .iter()
+            .filter(|info| info.writes.contains(&id))
+            .map(|info| info.name.as_str())
+            .collect()
+    }
+
+    /// Compares the schedule of this dispatcher with `other`,
+    /// describing the changes from `self` to `other`.
+    ///
+    /// Systems are matched by name, so systems which were
+    /// added with `""` as name are ignored.
+    /// This only reads metadata computed when the
+    /// dispatchers were built.
+    ///
+    /// See [`ScheduleDiff`] for details.
+    ///
+    /// [`ScheduleDiff`]: struct.ScheduleDiff.html
+    pub fn diff(&self, other: &Dispatcher) -> ScheduleDiff {
+        diff::diff(&self.schedule.systems, &other.schedule.systems)
+    }
+
+    /// Writes the graph of all systems (except thread local systems)
+    /// in the Graphviz DOT format to `out`.
+    ///
+    /// Systems are grouped by the stage they were assigned to.
+    /// Solid edges denote explicit dependencies, dashed ones
+    /// conflicting resource accesses, which explain why two
+    /// systems don't run in parallel.
+    /// This only reads metadata computed when the
+    /// dispatcher was built.
+    #[cfg(feature = "std")]
+    pub fn write_dot<W>(&self, out: &mut W) -> ::std::io::Result<()>
+        where W: ::std::io::Write
+    {
+        dot::write_dot(&self.schedule.systems, out)
+    }
+}
+
+/// Executes the stages in order, flushing the
+/// resources before the stages at `flush_points`.
+///
+/// Stops after the first stage in which systems panicked.
+fn execute_stages<'a, F>(stages: &mut [Stage<'a>],
+                         flush_points: &[usize],
+                         res: &Resources,
+                         mut execute: F)
+                         -> Result<(), Panics>
+    where F: FnMut(&mut Stage<'a>, &Resources) -> Panics
+{
+    let num_stages = stages.len();
+
+    for (index, stage) in stages.iter_mut().enumerate() {
+        if flush_points.contains(&index) {
+            res.flush();
+        }
+
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::trace_span!("stage", index = index).entered();
+
+        let panics = execute(stage, res);
+
+        if !panics.is_empty() {
+            return Err(panics);
+        }
+    }
+
+    if flush_points.contains(&num_stages) {
+        res.flush();
+    }
+
+    Ok(())
+}
+
+/// Extracts the message of a panic, which is
+/// either a `&str` or a `String` for `panic!`.
+#[cfg(feature = "std")]
+fn panic_message(payload: Box<Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => {
+            payload
+                .downcast_ref::<&str>()
+                .map_or("Box<Any>", |message| message)
+                .to_owned()
+        }
+    }
 }
 
+/// Error returned if systems panicked or failed
+/// while dispatching (see `Dispatcher::try_dispatch`).
+#[derive(Debug)]
+pub struct DispatchError {
+    errors: Vec<(String, Box<Error + Send + Sync>)>,
+    panicked: Vec<(String, String)>,
+}
+
+impl DispatchError {
+    /// Combines the result of executing the stages with the
+    /// errors collected from fallible systems, which are
+    /// taken from `failures`.
+    fn check<F>(result: Result<(), Panics>, failures: &Failures, name: F) -> Result<(), Self>
+        where F: Fn(usize) -> String
+    {
+        let panics = result.err().unwrap_or_default();
+        let errors = take_failures(failures);
+
+        if panics.is_empty() && errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DispatchError::new(panics, errors, name))
+        }
+    }
+
+    fn new<F>(panics: Panics, errors: Vec<(String, Box<Error + Send + Sync>)>, name: F) -> Self
+        where F: Fn(usize) -> String
+    {
+        DispatchError {
+            errors: errors,
+            panicked: panics
+                .into_iter()
+                .map(|(id, message)| (name(id.0), message))
+                .collect(),
+        }
+    }
+
+    /// Returns the names of the fallible systems which
+    /// failed, together with the errors they returned.
+    pub fn errors(&self) -> &[(String, Box<Error + Send + Sync>)] {
+        &self.errors
+    }
+
+    /// Consumes the error, returning the errors of the
+    /// fallible systems (see `errors`), e.g. in order
+    /// to downcast them.
+    pub fn into_errors(self) -> Vec<(String, Box<Error + Send + Sync>)> {
+        self.errors
+    }
+
+    /// Returns the names of the systems which
+    /// panicked, together with their panic messages.
+    ///
+    /// This is always empty without the `std` feature.
+    pub fn panicked(&self) -> &[(String, String)] {
+        &self.panicked
+    }
+}
+
+impl Display for DispatchError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        write!(f, "Systems failed during dispatch:")?;
+
+        for (name, message) in &self.panicked {
+            write!(f, " \"{}\" panicked ({})", name, message)?;
+        }
+
+        for (name, error) in &self.errors {
+            write!(f, " \"{}\" returned an error ({})", name, error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for DispatchError {
+    fn description(&self) -> &str {
+        "Systems failed during dispatch"
+    }
+}
+
+/// Identifies a system of a dispatcher by
+/// the index of the order it was added in.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct SystemId(pub usize);
 
+/// Decides whether a system is executed when
+/// dispatching, and measures how long it runs.
+#[derive(Default)]
+struct RunCondition {
+    /// Where the system should run, see `Affinity`.
+    affinity: Affinity,
+    /// How often the affinity couldn't be satisfied.
+    affinity_violations: AtomicUsize,
+    /// Exponentially weighted average of the
+    /// running time of the system, in nanoseconds.
+    average: AtomicUsize,
+    /// Set with `Dispatcher::set_enabled`.
+    disabled: bool,
+    /// Set with `Dispatcher::set_group_enabled`.
+    group_disabled: bool,
+    /// True if the system is skipped once
+    /// the `CancellationToken` is cancelled.
+    interruptible: bool,
+    run_if: Vec<RunIf>,
+}
+
+/// A condition added with `DispatcherBuilder::with_run_if`.
+type RunIf = Box<Fn(&Resources) -> bool + Send + Sync>;
+
+impl RunCondition {
+    fn should_run(&self, res: &Resources) -> bool {
+        if self.disabled || self.group_disabled || (self.interruptible && is_cancelled(res)) {
+            return false;
+        }
+
+        self.run_if.iter().all(|f| f(res))
+    }
+
+    /// Returns the average running time, which is zero
+    /// until the system has been executed.
+    fn average(&self) -> usize {
+        self.average.load(Ordering::Relaxed)
+    }
+
+    /// Adds a sample to the average, weighting the
+    /// previous average with `7 / 8`.
+    ///
+    /// The average saturates at `usize::MAX` nanoseconds,
+    /// which is about 4 seconds on 32 bit targets.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    fn record(&self, elapsed: Duration) {
+        let nanos = elapsed
+            .as_secs()
+            .saturating_mul(1_000_000_000)
+            .saturating_add(elapsed.subsec_nanos() as u64);
+        // Zero means the system hasn't run yet
+        let nanos = nanos.max(1);
+        let old = self.average() as u64;
+        let new = if old == 0 {
+            nanos
+        } else {
+            (old - old / 8).saturating_add(nanos / 8)
+        };
+
+        self.average.store(new.min(usize::MAX as u64) as usize, Ordering::Relaxed);
+    }
+}
+
+/// Converts a number of nanoseconds to a `Duration`.
+fn from_nanos(nanos: u64) -> Duration {
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+/// Returns true if there is a `CancellationToken`
+/// which has been cancelled.
+fn is_cancelled(res: &Resources) -> bool {
+    res.try_fetch::<CancellationToken>(0)
+        .is_some_and(|token| token.is_cancelled())
+}
+
+/// Metadata about a system, collected
+/// by the builder.
+#[derive(Clone, Debug)]
+pub struct SystemInfo {
+    pub name: String,
+    pub dependencies: Vec<String>,
+    /// The group the system was added to.
+    pub group: Option<String>,
+    pub reads: Vec<ResourceId>,
+    pub writes: Vec<ResourceId>,
+    pub stage: usize,
+    /// The number of barriers added before the system.
+    pub barriers: usize,
+    pub running_time: RunningTime,
+    #[cfg(feature = "profiling")]
+    pub profiler_index: usize,
+}
+
+/// Iterator over the systems of a dispatcher,
+/// returned by [`Dispatcher::systems`].
+///
+/// Yields the name of each system, followed by the
+/// resources it reads from and writes to.
+///
+/// [`Dispatcher::systems`]: struct.Dispatcher.html#method.systems
+pub struct Systems<'a> {
+    inner: ::std::slice::Iter<'a, SystemInfo>,
+}
+
+impl<'a> Iterator for Systems<'a> {
+    type Item = (&'a str, &'a [ResourceId], &'a [ResourceId]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|info| (info.name.as_str(), &*info.reads, &*info.writes))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
 type SystemExecSend<'b> = Box<for<'a> RunNow<'a> + Send + 'b>;
 type ThreadLocal<'a> = SmallVec<[Box<for<'b> RunNow<'b> + 'a>; 4]>;
 
@@ -153,6 +950,59 @@ mod tests {
             .dispatch(&mut new_resources())
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_dispatch_panics() {
+        struct Other(i32);
+
+        struct PanicWrite;
+
+        impl<'a> System<'a> for PanicWrite {
+            type SystemData = FetchMut<'a, Res>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0 = 42;
+                panic!("Isolated panic");
+            }
+        }
+
+        struct Inc;
+
+        impl<'a> System<'a> for Inc {
+            type SystemData = FetchMut<'a, Other>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0 += 1;
+            }
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(PanicWrite, "p", &[])
+            .add(Inc, "inc", &[])
+            .add(Inc, "after", &["p", "inc"])
+            .build();
+
+        let mut res = new_resources();
+        res.add(Other(0));
+
+        let e = d.try_dispatch_seq(&mut res).unwrap_err();
+        assert_eq!(e.panicked(),
+                   &[("p".to_owned(), "Isolated panic".to_owned())]);
+        assert_eq!(res.fetch::<Other>(0).0, 1);
+
+        let e = d.try_dispatch(&mut res).unwrap_err();
+        assert_eq!(e.to_string(),
+                   "Systems failed during dispatch: \"p\" panicked (Isolated panic)");
+        assert_eq!(res.fetch::<Other>(0).0, 2);
+
+        assert!(res.is_poisoned(ResourceId::new::<Res>()));
+        assert!(!res.is_poisoned(ResourceId::new::<Other>()));
+        assert_eq!(res.fetch::<Res>(0).0, 42);
+
+        res.clear_poison(ResourceId::new::<Res>());
+        assert!(!res.is_poisoned(ResourceId::new::<Res>()));
+    }
+
     #[test]
     fn stages() {
         let mut d = new_builder().build();
@@ -160,6 +1010,379 @@ mod tests {
         d.dispatch(&mut new_resources());
     }
 
+    #[test]
+    fn dispatch_seq_order() {
+        struct Order(Vec<&'static str>);
+
+        struct Record(&'static str);
+
+        impl<'a> System<'a> for Record {
+            type SystemData = FetchMut<'a, Order>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0.push(self.0);
+            }
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(Record("a"), "a", &[])
+            .add(Record("b"), "b", &["a"])
+            .add_barrier()
+            .add(Record("c"), "c", &[])
+            .add(Record("d"), "d", &["b"])
+            .build();
+
+        let mut res = Resources::new();
+        res.add(Order(Vec::new()));
+
+        d.dispatch_seq(&mut res);
+        d.dispatch_seq(&mut res);
+
+        assert_eq!(res.fetch::<Order>(0).0,
+                   vec!["a", "b", "c", "d", "a", "b", "c", "d"]);
+    }
+
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    #[test]
+    fn record_saturates() {
+        use std::usize;
+
+        let condition = RunCondition::default();
+        condition.record(Duration::new(100, 0));
+        condition.record(Duration::new(!0, 999_999_999));
+
+        assert!(condition.average() > 0);
+        assert_eq!(from_nanos(usize::MAX as u64).as_secs(), usize::MAX as u64 / 1_000_000_000);
+    }
+
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    #[test]
+    fn dispatch_affinity() {
+        use std::sync::Arc;
+
+        use rayon::{Configuration, ThreadPool};
+
+        struct Pinned(Vec<Option<usize>>);
+
+        struct Avoiding(Vec<Option<usize>>);
+
+        struct Pin;
+
+        impl<'a> System<'a> for Pin {
+            type SystemData = FetchMut<'a, Pinned>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0.push(affinity::worker_index());
+            }
+        }
+
+        struct Avoid;
+
+        impl<'a> System<'a> for Avoid {
+            type SystemData = FetchMut<'a, Avoiding>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0.push(affinity::worker_index());
+            }
+        }
+
+        let config = Configuration::new()
+            .num_threads(3)
+            .start_handler(register_worker);
+        let pool = Arc::new(ThreadPool::new(config).unwrap());
+
+        let mut d = DispatcherBuilder::new()
+            .with_pool(pool)
+            .add(Pin, "pin", &[])
+            .add(Avoid, "avoid", &[])
+            .add(Dummy(1), "dummy", &[])
+            .with_affinity("pin", Affinity::Worker(2))
+            .with_affinity("avoid", Affinity::AvoidWorker(0))
+            .build();
+
+        let mut res = new_resources();
+        res.add(Pinned(Vec::new()));
+        res.add(Avoiding(Vec::new()));
+
+        for _ in 0..20 {
+            d.dispatch_par(&mut res);
+        }
+
+        assert_eq!(res.fetch::<Pinned>(0).0, vec![Some(2); 20]);
+        assert_eq!(d.affinity_violations("pin"), Some(0));
+        assert_eq!(d.affinity_violations("dummy"), Some(0));
+        assert!(res.fetch::<Avoiding>(0).0.iter().all(|&x| x.is_some() && x != Some(0)));
+    }
+
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    #[test]
+    fn dispatch_pipelined() {
+        struct Seen(Vec<i32>);
+
+        struct Count(i32);
+
+        struct Head;
+
+        impl<'a> System<'a> for Head {
+            type SystemData = FetchMut<'a, Res>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0 += 1;
+            }
+        }
+
+        struct Middle;
+
+        impl<'a> System<'a> for Middle {
+            type SystemData = (Fetch<'a, Res>, FetchMut<'a, Seen>);
+
+            fn run(&mut self, (data, mut seen): Self::SystemData) {
+                seen.0.push(data.0);
+            }
+        }
+
+        struct Tail;
+
+        impl<'a> System<'a> for Tail {
+            type SystemData = FetchMut<'a, Count>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0 += 1;
+            }
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(Head, "head", &[])
+            .add(Middle, "middle", &["head"])
+            .add_barrier()
+            .add(Tail, "tail", &[])
+            .build();
+
+        let mut res = new_resources();
+        res.add(Seen(Vec::new()));
+        res.add(Count(0));
+
+        assert!(d.is_pipelined());
+        d.dispatch_pipelined(&mut res, 3);
+
+        assert_eq!(res.fetch::<Res>(0).0, 3);
+        assert_eq!(res.fetch::<Seen>(0).0, vec![1, 2, 3]);
+        assert_eq!(res.fetch::<Count>(0).0, 3);
+
+        // The tail writes `Res` as well, so the frames can't overlap
+        let mut d = DispatcherBuilder::new()
+            .add(Head, "head", &[])
+            .add(Middle, "middle", &["head"])
+            .add_barrier()
+            .add(Head, "tail", &[])
+            .build();
+
+        let mut res = new_resources();
+        res.add(Seen(Vec::new()));
+
+        assert!(!d.is_pipelined());
+        d.dispatch_pipelined(&mut res, 2);
+
+        assert_eq!(res.fetch::<Res>(0).0, 4);
+        assert_eq!(res.fetch::<Seen>(0).0, vec![1, 3]);
+    }
+
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    #[test]
+    fn average_time() {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        struct Sleep;
+
+        impl<'a> System<'a> for Sleep {
+            type SystemData = ();
+
+            fn run(&mut self, _: Self::SystemData) {
+                sleep(Duration::from_millis(2));
+            }
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(Sleep, "sleep", &[])
+            .add(Sleep, "disabled", &[])
+            .build();
+        d.set_enabled("disabled", false);
+
+        let mut res = Resources::new();
+        assert_eq!(d.average_time("sleep"), None);
+
+        d.dispatch(&mut res);
+        d.dispatch(&mut res);
+
+        assert!(d.average_time("sleep").unwrap() >= Duration::from_millis(2));
+        assert_eq!(d.average_time("disabled"), None);
+        assert_eq!(d.average_time("unknown"), None);
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn borrow_diagnostics() {
+        use std::mem::forget;
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        struct Leak;
+
+        impl<'a> System<'a> for Leak {
+            type SystemData = FetchMut<'a, Res>;
+
+            fn run(&mut self, data: Self::SystemData) {
+                forget(data);
+            }
+        }
+
+        let mut d = DispatcherBuilder::new().add(Leak, "leak", &[]).build();
+        let mut res = new_resources();
+        d.dispatch(&mut res);
+
+        let payload = catch_unwind(AssertUnwindSafe(|| { res.fetch::<Res>(0); })).unwrap_err();
+        let message = payload.downcast::<String>().unwrap();
+
+        assert!(message.contains("last borrowed mutably by system \"leak\""));
+    }
+
+    #[test]
+    fn systems() {
+        let d = new_builder().build();
+
+        let names: Vec<&str> = d.systems().map(|(name, _, _)| name).collect();
+        assert_eq!(names, vec!["0", "1", "2", "3", "4", "5"]);
+
+        for (_, reads, writes) in d.systems() {
+            assert!(reads.is_empty());
+            assert_eq!(writes, &[ResourceId::new::<Res>()]);
+        }
+    }
+
+    #[test]
+    fn introspection() {
+        struct Read;
+
+        impl<'a> System<'a> for Read {
+            type SystemData = Fetch<'a, Res>;
+
+            fn run(&mut self, _: Self::SystemData) {}
+        }
+
+        let d = new_builder().add(Read, "read", &[]).build();
+
+        // All systems conflict, so there's one stage per system
+        assert_eq!(d.stages(),
+                   vec![vec!["0"], vec!["1"], vec!["2"], vec!["3"], vec!["4"], vec!["5"],
+                        vec!["read"]]);
+        assert_eq!(d.readers(ResourceId::new::<Res>()), vec!["read"]);
+        assert_eq!(d.writers(ResourceId::new::<Res>()),
+                   vec!["0", "1", "2", "3", "4", "5"]);
+        assert!(d.writers(ResourceId::new_with_id::<Res>(1)).is_empty());
+    }
+
+    #[test]
+    fn add_remove_systems() {
+        struct Count(i32);
+
+        impl<'a> System<'a> for Count {
+            type SystemData = FetchMut<'a, Res>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                self.0 += 1;
+                data.0 = self.0;
+            }
+        }
+
+        struct Read;
+
+        impl<'a> System<'a> for Read {
+            type SystemData = Fetch<'a, Res>;
+
+            fn run(&mut self, _: Self::SystemData) {}
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(Count(0), "count", &[])
+            .add(Read, "read", &["count"])
+            .add_barrier()
+            .add(Read, "after", &[])
+            .build();
+        let mut res = new_resources();
+
+        d.dispatch(&mut res);
+        assert_eq!(d.stages(), vec![vec!["count"], vec!["read"], vec!["after"]]);
+
+        assert_eq!(d.remove_system("count"),
+                   Err(BuildError::HasDependent {
+                           system: "count".to_owned(),
+                           dependent: "read".to_owned(),
+                       }));
+        assert_eq!(d.remove_system("missing"),
+                   Err(BuildError::UnknownSystem("missing".to_owned())));
+
+        d.remove_system("read").unwrap();
+        assert_eq!(d.stages(), vec![vec!["count"], vec!["after"]]);
+
+        d.add_system(Read, "read", &["count"]).unwrap();
+        assert_eq!(d.stages(), vec![vec!["count"], vec!["after", "read"]]);
+        assert_eq!(d.add_system(Read, "read", &["missing"]).unwrap_err().len(),
+                   2);
+
+        // The remaining systems keep their state
+        d.dispatch(&mut res);
+        assert_eq!(res.fetch::<Res>(0).0, 2);
+    }
+
+    #[test]
+    fn flush_points() {
+        struct Pending(i32);
+
+        impl FlushableResource for Pending {
+            fn flush(&mut self, res: &Resources) {
+                res.fetch_mut::<Res>(0).0 += self.0;
+                self.0 = 0;
+            }
+        }
+
+        struct Push;
+
+        impl<'a> System<'a> for Push {
+            type SystemData = FetchMut<'a, Pending>;
+
+            fn run(&mut self, mut data: Self::SystemData) {
+                data.0 += 1;
+            }
+        }
+
+        struct Check(i32);
+
+        impl<'a> System<'a> for Check {
+            type SystemData = Fetch<'a, Res>;
+
+            fn run(&mut self, data: Self::SystemData) {
+                assert_eq!(data.0, self.0);
+            }
+        }
+
+        let mut d = DispatcherBuilder::new()
+            .add(Push, "push", &[])
+            .add(Check(0), "check_before", &[])
+            .with_flush_point()
+            .add(Check(1), "check_after", &[])
+            .add(Push, "push_again", &[])
+            .with_flush_point()
+            .build();
+
+        let mut res = new_resources();
+        res.add(Pending(0));
+        res.register_flushable::<Pending>(0);
+
+        d.dispatch_seq(&mut res);
+        assert_eq!(res.fetch::<Res>(0).0, 2);
+    }
+
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
     #[test]
     fn stages_async() {
         let mut d = new_builder().build_async(new_resources());
@@ -0,0 +1,350 @@
+//! Execution graphs fixed at compile time,
+//! built with the `par!` and `seq!` macros.
+
+use par::ParallelContext;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::{ResourceId, Resources};
+use system::{RunNow, System, SystemData};
+
+/// Something which can be run by a `ParSeq`, which is implemented
+/// for all systems and for the `Par` and `Seq` combinators.
+pub trait RunWithPool<'a> {
+    /// Runs the system(s), executing the branches
+    /// of a `Par` on the workers of `par`.
+    fn run(&mut self, res: &'a Resources, par: &ParallelContext);
+
+    /// Sets up the system(s) (see `System::setup`).
+    fn setup(&mut self, res: &mut Resources);
+
+    /// Appends the resources read by the system(s) to `reads`.
+    fn reads(&self, reads: &mut Vec<ResourceId>);
+
+    /// Appends the resources written by the system(s) to `writes`.
+    fn writes(&self, writes: &mut Vec<ResourceId>);
+}
+
+impl<'a, T> RunWithPool<'a> for T
+    where T: System<'a>
+{
+    fn run(&mut self, res: &'a Resources, _: &ParallelContext) {
+        RunNow::run_now(self, res);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        System::setup(self, res);
+    }
+
+    fn reads(&self, reads: &mut Vec<ResourceId>) {
+        reads.extend(T::SystemData::reads(0));
+    }
+
+    fn writes(&self, writes: &mut Vec<ResourceId>) {
+        writes.extend(T::SystemData::writes(0));
+    }
+}
+
+/// The end of a `Par` or `Seq` list, which does nothing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Nil;
+
+impl<'a> RunWithPool<'a> for Nil {
+    fn run(&mut self, _: &'a Resources, _: &ParallelContext) {}
+
+    fn setup(&mut self, _: &mut Resources) {}
+
+    fn reads(&self, _: &mut Vec<ResourceId>) {}
+
+    fn writes(&self, _: &mut Vec<ResourceId>) {}
+}
+
+/// Runs two systems (or combinators) in parallel,
+/// usually created with the `par!` macro.
+#[derive(Debug)]
+pub struct Par<H, T> {
+    head: H,
+    tail: T,
+}
+
+impl<H> Par<H, Nil> {
+    /// Creates a `Par` containing only `head`.
+    pub fn new(head: H) -> Self {
+        Par {
+            head: head,
+            tail: Nil,
+        }
+    }
+}
+
+impl<H, T> Par<H, T> {
+    /// Adds `w`, running in parallel to
+    /// the systems added before.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `w` accesses a resource the previous
+    /// systems write, or writes one they access.
+    pub fn with<W>(self, w: W) -> Par<Self, W>
+        where Self: for<'a> RunWithPool<'a>,
+              W: for<'a> RunWithPool<'a>
+    {
+        let (mut reads, mut writes) = (Vec::new(), Vec::new());
+        RunWithPool::reads(&self, &mut reads);
+        RunWithPool::writes(&self, &mut writes);
+
+        let (mut w_reads, mut w_writes) = (Vec::new(), Vec::new());
+        w.reads(&mut w_reads);
+        w.writes(&mut w_writes);
+
+        if writes
+               .iter()
+               .any(|x| w_reads.contains(x) || w_writes.contains(x)) ||
+           w_writes.iter().any(|x| reads.contains(x)) {
+            panic!("Tried to add a system to `Par` which conflicts with the other systems");
+        }
+
+        Par {
+            head: self,
+            tail: w,
+        }
+    }
+}
+
+impl<'a, H, T> RunWithPool<'a> for Par<H, T>
+    where H: RunWithPool<'a> + Send,
+          T: RunWithPool<'a> + Send
+{
+    fn run(&mut self, res: &'a Resources, par: &ParallelContext) {
+        let (head, tail) = (&mut self.head, &mut self.tail);
+
+        par.join(move || head.run(res, par), move || tail.run(res, par));
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.head.setup(res);
+        self.tail.setup(res);
+    }
+
+    fn reads(&self, reads: &mut Vec<ResourceId>) {
+        self.head.reads(reads);
+        self.tail.reads(reads);
+    }
+
+    fn writes(&self, writes: &mut Vec<ResourceId>) {
+        self.head.writes(writes);
+        self.tail.writes(writes);
+    }
+}
+
+/// Runs two systems (or combinators) one after
+/// another, usually created with the `seq!` macro.
+#[derive(Debug)]
+pub struct Seq<H, T> {
+    head: H,
+    tail: T,
+}
+
+impl<H> Seq<H, Nil> {
+    /// Creates a `Seq` containing only `head`.
+    pub fn new(head: H) -> Self {
+        Seq {
+            head: head,
+            tail: Nil,
+        }
+    }
+}
+
+impl<H, T> Seq<H, T> {
+    /// Adds `w`, running after the systems added before.
+    pub fn with<W>(self, w: W) -> Seq<Self, W> {
+        Seq {
+            head: self,
+            tail: w,
+        }
+    }
+}
+
+impl<'a, H, T> RunWithPool<'a> for Seq<H, T>
+    where H: RunWithPool<'a>,
+          T: RunWithPool<'a>
+{
+    fn run(&mut self, res: &'a Resources, par: &ParallelContext) {
+        self.head.run(res, par);
+        self.tail.run(res, par);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.head.setup(res);
+        self.tail.setup(res);
+    }
+
+    fn reads(&self, reads: &mut Vec<ResourceId>) {
+        self.head.reads(reads);
+        self.tail.reads(reads);
+    }
+
+    fn writes(&self, writes: &mut Vec<ResourceId>) {
+        self.head.writes(writes);
+        self.tail.writes(writes);
+    }
+}
+
+/// A dispatcher with an execution graph fixed at compile time,
+/// built from `Par` and `Seq` (see the `par!` and `seq!` macros).
+///
+/// Compared to the `Dispatcher`, there are no stages to schedule
+/// and no boxed systems, which makes this faster for small sets
+/// of systems that don't change. Run conditions, thread local
+/// systems and the other features of the `DispatcherBuilder` are
+/// not supported.
+///
+/// Without the `parallel` feature (or with a sequential
+/// `ParallelContext`), everything runs on the current thread.
+///
+/// # Examples
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate shred;
+///
+/// # use shred::{ParSeq, ParallelContext, ReadDefault, Resources, System, WriteDefault};
+/// struct Count;
+///
+/// impl<'a> System<'a> for Count {
+///     type SystemData = WriteDefault<'a, u32>;
+///
+///     fn run(&mut self, mut count: Self::SystemData) {
+///         *count += 1;
+///     }
+/// }
+///
+/// struct Double;
+///
+/// impl<'a> System<'a> for Double {
+///     type SystemData = WriteDefault<'a, u32>;
+///
+///     fn run(&mut self, mut count: Self::SystemData) {
+///         *count *= 2;
+///     }
+/// }
+///
+/// struct Log;
+///
+/// impl<'a> System<'a> for Log {
+///     type SystemData = ReadDefault<'a, String>;
+///
+///     fn run(&mut self, _: Self::SystemData) {}
+/// }
+///
+/// # fn main() {
+/// let mut res = Resources::new();
+/// let mut dispatcher = ParSeq::new(par![seq![Count, Double], Log],
+///                                  ParallelContext::sequential());
+///
+/// dispatcher.setup(&mut res);
+/// dispatcher.dispatch(&res);
+///
+/// assert_eq!(*res.fetch::<u32>(0), 2);
+/// # }
+/// ```
+pub struct ParSeq<T> {
+    par: ParallelContext,
+    run: T,
+}
+
+impl<T> ParSeq<T>
+    where T: for<'a> RunWithPool<'a>
+{
+    /// Creates a `ParSeq` running the
+    /// parallel branches of `run` on `par`.
+    pub fn new(run: T, par: ParallelContext) -> Self {
+        ParSeq { par: par, run: run }
+    }
+
+    /// Sets up all the systems (see `Dispatcher::setup`).
+    pub fn setup(&mut self, res: &mut Resources) {
+        self.run.setup(res);
+    }
+
+    /// Runs all the systems.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a system panics or tries to fetch a missing resource.
+    pub fn dispatch(&mut self, res: &Resources) {
+        self.run.run(res, &self.par);
+    }
+}
+
+/// Creates a `Par`, running the given systems
+/// (or `seq!`s) in parallel.
+///
+/// See `ParSeq` for an example.
+///
+/// # Panics
+///
+/// Panics if the systems conflict (see `Par::with`).
+#[macro_export]
+macro_rules! par {
+    ($head:expr $( , $tail:expr )* $(,)*) => {
+        $crate::Par::new($head) $( .with($tail) )*
+    };
+}
+
+/// Creates a `Seq`, running the given
+/// systems (or `par!`s) one after another.
+///
+/// See `ParSeq` for an example.
+#[macro_export]
+macro_rules! seq {
+    ($head:expr $( , $tail:expr )* $(,)*) => {
+        $crate::Seq::new($head) $( .with($tail) )*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use system::System;
+    use WriteDefault;
+
+    struct Push(u32);
+
+    impl<'a> System<'a> for Push {
+        type SystemData = WriteDefault<'a, Vec<u32>>;
+
+        fn run(&mut self, mut list: Self::SystemData) {
+            list.push(self.0);
+        }
+    }
+
+    struct Count;
+
+    impl<'a> System<'a> for Count {
+        type SystemData = WriteDefault<'a, usize>;
+
+        fn run(&mut self, mut count: Self::SystemData) {
+            *count += 1;
+        }
+    }
+
+    #[test]
+    fn par_seq() {
+        let mut res = Resources::new();
+        let mut dispatcher = ParSeq::new(par![seq![Push(1), Push(2), Push(3)], Count],
+                                         ParallelContext::sequential());
+        dispatcher.setup(&mut res);
+
+        dispatcher.dispatch(&res);
+        dispatcher.dispatch(&res);
+
+        assert_eq!(*res.fetch::<Vec<u32>>(0), vec![1, 2, 3, 1, 2, 3]);
+        assert_eq!(*res.fetch::<usize>(0), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicts with the other systems")]
+    fn par_conflict() {
+        par![Push(1), Count, Push(2)];
+    }
+}
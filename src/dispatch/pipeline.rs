@@ -0,0 +1,92 @@
+//! Pipelined dispatching, which overlaps the last stage
+//! of a frame with the first stage of the next one.
+
+use dispatch::{RunCondition, SystemInfo, execute_stages};
+use dispatch::stage::{Panics, Stage};
+use res::Resources;
+
+/// Returns true if the last and the first stage can run in
+/// parallel, which requires that their systems don't conflict
+/// and that resources aren't flushed in between.
+pub fn can_pipeline(systems: &[SystemInfo], num_stages: usize, flush_points: &[usize]) -> bool {
+    if num_stages < 2 || flush_points.contains(&0) || flush_points.contains(&num_stages) {
+        return false;
+    }
+
+    let head = systems.iter().filter(|info| info.stage == 0);
+
+    !head.clone().any(|a| {
+        systems
+            .iter()
+            .filter(|info| info.stage == num_stages - 1)
+            .any(|b| conflicts(a, b))
+    })
+}
+
+fn conflicts(a: &SystemInfo, b: &SystemInfo) -> bool {
+    a.writes
+        .iter()
+        .any(|x| b.reads.contains(x) || b.writes.contains(x)) ||
+    b.writes.iter().any(|x| a.reads.contains(x))
+}
+
+/// Executes `frames` frames, running the last stage of every
+/// frame in parallel with the first stage of the next frame.
+///
+/// Requires `can_pipeline` to be true.
+pub fn execute_pipelined<'a, F>(stages: &mut [Stage<'a>],
+                                flush_points: &[usize],
+                                conditions: &[RunCondition],
+                                res: &Resources,
+                                frames: usize,
+                                end_frame: F)
+                                -> Result<(), Panics>
+    where F: Fn(&Resources)
+{
+    use rayon::join;
+
+    if frames == 0 {
+        return Ok(());
+    }
+
+    let (head, rest) = stages.split_first_mut().expect("Too few stages");
+    let (tail, middle) = rest.split_last_mut().expect("Too few stages");
+
+    // Relative to the middle stages, which start at index 1
+    let middle_flush_points: Vec<usize> = flush_points
+        .iter()
+        .filter(|&&x| x > 0)
+        .map(|x| x - 1)
+        .collect();
+
+    check(head.execute(res, conditions))?;
+
+    for frame in 0..frames {
+        execute_stages(middle,
+                       &middle_flush_points,
+                       res,
+                       |stage, res| stage.execute(res, conditions))?;
+
+        if frame + 1 < frames {
+            let (mut a, b) = join(|| tail.execute(res, conditions),
+                                  || head.execute(res, conditions));
+            a.extend(b);
+
+            check(a)?;
+        } else {
+            check(tail.execute(res, conditions))?;
+        }
+
+        end_frame(res);
+    }
+
+    Ok(())
+}
+
+fn check(panics: Panics) -> Result<(), Panics> {
+    if panics.is_empty() {
+        Ok(())
+    } else {
+        Err(panics)
+    }
+}
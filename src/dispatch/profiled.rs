@@ -0,0 +1,148 @@
+//! Recording of the execution times of systems,
+//! only compiled with the `profiling` feature.
+
+use std::mem::take;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use profiling::{SystemSample, SystemStats};
+use res::Resources;
+use system::RunNow;
+
+type Samples = Arc<Mutex<Vec<(usize, ThreadId, Duration)>>>;
+
+/// Collects the samples recorded by the
+/// `Profiled` systems of a dispatcher.
+#[derive(Default)]
+pub struct Profiler {
+    samples: Samples,
+    /// The names and stages of the systems, `None`
+    /// for slots freed with `unregister`.
+    systems: Vec<Option<(String, usize)>>,
+}
+
+impl Profiler {
+    /// Registers a system, returning the index
+    /// to wrap it with. Freed slots are reused.
+    pub fn register(&mut self, name: &str) -> usize {
+        let slot = Some((name.to_owned(), 0));
+
+        match self.systems.iter().position(|x| x.is_none()) {
+            Some(index) => {
+                self.systems[index] = slot;
+
+                index
+            }
+            None => {
+                self.systems.push(slot);
+
+                self.systems.len() - 1
+            }
+        }
+    }
+
+    /// Frees the slot of a removed system, discarding
+    /// the samples it recorded so far.
+    pub fn unregister(&mut self, index: usize) {
+        self.systems[index] = None;
+        self.samples
+            .lock()
+            .expect("Mutex poisoned")
+            .retain(|x| x.0 != index);
+    }
+
+    /// Wraps the system registered at `index`.
+    pub fn wrap<T>(&self, index: usize, inner: T) -> Profiled<T> {
+        Profiled {
+            index: index,
+            inner: inner,
+            samples: self.samples.clone(),
+        }
+    }
+
+    /// Sets the stage the system at `index` was assigned to.
+    pub fn set_stage(&mut self, index: usize, stage: usize) {
+        if let Some(ref mut system) = self.systems[index] {
+            system.1 = stage;
+        }
+    }
+
+    /// Discards the samples recorded so far.
+    pub fn clear(&self) {
+        self.samples.lock().expect("Mutex poisoned").clear();
+    }
+
+    /// Pushes the samples recorded so far as a frame
+    /// to `SystemStats`, if that resource exists.
+    pub fn end_frame(&self, res: &Resources) {
+        let samples = take(&mut *self.samples.lock().expect("Mutex poisoned"));
+
+        if let Some(mut stats) = res.try_fetch_mut::<SystemStats>(0) {
+            let frame = samples
+                .into_iter()
+                .filter_map(|(index, thread, duration)| {
+                    self.systems[index]
+                        .as_ref()
+                        .map(|&(ref name, stage)| {
+                                 SystemSample {
+                                     name: name.clone(),
+                                     stage: stage,
+                                     thread: thread,
+                                     duration: duration,
+                                 }
+                             })
+                })
+                .collect();
+
+            stats.push(frame);
+        }
+    }
+}
+
+/// Wraps a system, recording how long
+/// it took and on which thread it ran.
+pub struct Profiled<T> {
+    index: usize,
+    inner: T,
+    samples: Samples,
+}
+
+impl<'a, T> RunNow<'a> for Profiled<T>
+    where T: RunNow<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        let start = Instant::now();
+
+        self.inner.run_now(res);
+
+        let sample = (self.index, thread::current().id(), start.elapsed());
+        self.samples.lock().expect("Mutex poisoned").push(sample);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unregister() {
+        let mut profiler = Profiler::default();
+        assert_eq!(profiler.register("a"), 0);
+        assert_eq!(profiler.register("b"), 1);
+
+        profiler.samples
+            .lock()
+            .unwrap()
+            .push((0, thread::current().id(), Duration::new(0, 0)));
+        profiler.unregister(0);
+        assert!(profiler.samples.lock().unwrap().is_empty());
+
+        assert_eq!(profiler.register("c"), 0);
+        assert_eq!(profiler.register("d"), 2);
+    }
+}
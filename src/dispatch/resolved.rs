@@ -0,0 +1,70 @@
+//! Caching of the indices of the resources a system
+//! fetches, so dispatching doesn't have to hash their ids
+//! again until the resources change structurally.
+
+use res::{Resolution, Resources};
+use system::RunNow;
+
+/// Wraps a system, so it runs with its own resolution
+/// (see `Resources::run_resolved`).
+pub struct Resolved<T> {
+    inner: T,
+    resolution: Resolution,
+}
+
+impl<T> Resolved<T> {
+    pub fn new(inner: T) -> Self {
+        Resolved {
+            inner: inner,
+            resolution: Resolution::default(),
+        }
+    }
+}
+
+impl<'a, T> RunNow<'a> for Resolved<T>
+    where T: RunNow<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        let inner = &mut self.inner;
+
+        res.run_resolved(&mut self.resolution, || inner.run_now(res));
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use res::Fetch;
+    use system::System;
+
+    struct Store(u32);
+
+    impl<'a> System<'a> for Store {
+        type SystemData = Fetch<'a, u32>;
+
+        fn run(&mut self, value: Self::SystemData) {
+            self.0 = *value;
+        }
+    }
+
+    #[test]
+    fn fetches_after_structural_change() {
+        let mut res = Resources::new();
+        res.add(5u32);
+
+        let mut system = Resolved::new(Store(0));
+        system.run_now(&res);
+        assert_eq!(system.inner.0, 5);
+
+        res.remove::<u32>(0);
+        res.add(2u8);
+        res.add(7u32);
+
+        system.run_now(&res);
+        assert_eq!(system.inner.0, 7);
+    }
+}
@@ -0,0 +1,277 @@
+//! The systems of a dispatcher, together with the
+//! metadata needed to reschedule them.
+
+use std::mem::take;
+
+use dispatch::{BuildError, RunCondition, SystemExecSend, SystemId, SystemInfo};
+#[cfg(feature = "diagnostics")]
+use dispatch::diagnosed::Diagnosed;
+#[cfg(feature = "profiling")]
+use dispatch::profiled::Profiler;
+use dispatch::scheduler::Scheduler;
+#[cfg(feature = "std")]
+use dispatch::resolved::Resolved;
+use dispatch::stage::StagesBuilder;
+#[cfg(feature = "tracing")]
+use dispatch::traced::Traced;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::ResourceId;
+use system::{RunNow, RunningTime};
+use Map;
+
+/// The scheduled systems, shared by the builder and the
+/// dispatcher, so systems can still be added and
+/// removed after the dispatcher was built.
+#[derive(Default)]
+pub struct Schedule<'a> {
+    barriers: usize,
+    pub conditions: Vec<RunCondition>,
+    flush_barriers: Vec<usize>,
+    pub flush_points: Vec<usize>,
+    /// The group systems are added to, see
+    /// `DispatcherBuilder::with_group`.
+    pub group: Option<Group>,
+    map: Map<String, SystemId>,
+    #[cfg(feature = "profiling")]
+    pub profiler: Profiler,
+    pub stages: StagesBuilder<'a>,
+    pub systems: Vec<SystemInfo>,
+}
+
+/// A group of systems which can be controlled together.
+pub struct Group {
+    pub name: String,
+    /// The (expanded) dependencies of all systems of the group.
+    pub dependencies: Vec<String>,
+}
+
+impl<'a> Schedule<'a> {
+    pub fn add_barrier(&mut self) {
+        self.barriers += 1;
+        self.stages.add_barrier();
+    }
+
+    pub fn add_flush_point(&mut self) {
+        self.add_barrier();
+        self.flush_barriers.push(self.barriers);
+
+        let point = self.stages.num_stages();
+        if !self.flush_points.contains(&point) {
+            self.flush_points.push(point);
+        }
+    }
+
+    /// Returns the problems inserting a system
+    /// with the given name and dependencies would cause.
+    pub fn check(&self, name: &str, dep: &[&str]) -> Vec<BuildError> {
+        let mut errors: Vec<_> = dep.iter()
+            .filter(|x| !self.map.contains_key(**x) && !self.is_group(x))
+            .map(|x| {
+                     BuildError::MissingDependency {
+                         system: name.to_owned(),
+                         dependency: x.to_string(),
+                     }
+                 })
+            .collect();
+
+        if !name.is_empty() && self.map.contains_key(name) {
+            errors.push(BuildError::DuplicateName(name.to_owned()));
+        }
+
+        errors
+    }
+
+    pub fn id(&self, name: &str) -> Option<SystemId> {
+        self.map.get(name).cloned()
+    }
+
+    /// Returns true if there is a system in the given group.
+    pub fn is_group(&self, group: &str) -> bool {
+        self.systems
+            .iter()
+            .any(|info| info.group.as_ref().is_some_and(|x| x == group))
+    }
+
+    /// Replaces the group names in `dep` with the names of the
+    /// systems in the groups and adds the dependencies of the
+    /// current group. Systems take precedence over groups
+    /// with the same name.
+    pub fn expand(&self, dep: &[&str]) -> Vec<String> {
+        let mut expanded: Vec<String> = Vec::new();
+        let mut push = |name: &str| if !expanded.iter().any(|x| x == name) {
+            expanded.push(name.to_owned());
+        };
+
+        for &x in dep {
+            if self.map.contains_key(x) {
+                push(x);
+                continue;
+            }
+
+            for info in &self.systems {
+                if !info.name.is_empty() && info.group.as_ref().is_some_and(|g| g == x) {
+                    push(&info.name);
+                }
+            }
+        }
+
+        if let Some(ref group) = self.group {
+            for x in &group.dependencies {
+                push(x);
+            }
+        }
+
+        expanded
+    }
+
+    /// Inserts a system, ignoring dependencies which don't exist
+    /// (see `check`). If the name is taken already, the
+    /// existing system keeps it.
+    pub fn insert<T>(&mut self,
+                     system: T,
+                     name: &str,
+                     dep: &[&str],
+                     mut reads: Vec<ResourceId>,
+                     writes: Vec<ResourceId>,
+                     running_time: RunningTime)
+        where T: for<'c> RunNow<'c> + Send + 'a
+    {
+        reads.sort();
+        reads.dedup();
+
+        // Resolutions are cached in a thread local
+        #[cfg(feature = "std")]
+        let system = Resolved::new(system);
+
+        #[cfg(feature = "diagnostics")]
+        let system = Diagnosed::new(name, system);
+
+        #[cfg(feature = "tracing")]
+        let system = Traced::new(name, system);
+
+        #[cfg(feature = "profiling")]
+        let profiler_index = self.profiler.register(name);
+
+        #[cfg(feature = "profiling")]
+        let system = self.profiler.wrap(profiler_index, system);
+
+        let info = SystemInfo {
+            name: name.to_owned(),
+            dependencies: self.expand(dep),
+            group: self.group.as_ref().map(|x| x.name.clone()),
+            reads: reads,
+            writes: writes,
+            stage: 0,
+            barriers: self.barriers,
+            running_time: running_time,
+            #[cfg(feature = "profiling")]
+            profiler_index: profiler_index,
+        };
+
+        self.schedule(info, RunCondition::default(), Box::new(system));
+    }
+
+    /// Removes the system with the given name,
+    /// rescheduling the remaining systems.
+    ///
+    /// The systems keep their state and run conditions,
+    /// and are inserted again in the order they were
+    /// added, respecting barriers and flush points.
+    pub fn remove(&mut self, name: &str) -> Result<(), BuildError> {
+        let removed = match self.id(name) {
+            Some(id) => id.0,
+            None => return Err(BuildError::UnknownSystem(name.to_owned())),
+        };
+
+        if let Some(info) = self.systems
+               .iter()
+               .find(|info| info.dependencies.iter().any(|x| x == name)) {
+            return Err(BuildError::HasDependent {
+                           system: name.to_owned(),
+                           dependent: info.name.clone(),
+                       });
+        }
+
+        self.reschedule(Some(removed));
+
+        Ok(())
+    }
+
+    /// Replaces the scheduler, rescheduling all systems with it.
+    pub fn set_scheduler(&mut self, scheduler: Box<Scheduler>) {
+        self.stages.set_scheduler(scheduler);
+        self.reschedule(None);
+    }
+
+    /// Inserts all systems (except `removed`) again in the order
+    /// they were added, respecting barriers and flush points.
+    fn reschedule(&mut self, removed: Option<usize>) {
+        let mut boxed = self.stages.take_systems();
+        let conditions = take(&mut self.conditions);
+        let flush_barriers = take(&mut self.flush_barriers);
+        let infos = take(&mut self.systems);
+        let barriers = self.barriers;
+
+        self.barriers = 0;
+        self.flush_points.clear();
+        self.map.clear();
+
+        for (index, (info, condition)) in infos.into_iter().zip(conditions).enumerate() {
+            let system = boxed[index].take().expect("System missing from stages");
+
+            if Some(index) == removed {
+                #[cfg(feature = "profiling")]
+                self.profiler.unregister(info.profiler_index);
+
+                continue;
+            }
+
+            self.replay_barriers(info.barriers, &flush_barriers);
+            self.schedule(info, condition, system);
+        }
+
+        self.replay_barriers(barriers, &flush_barriers);
+    }
+
+    /// Adds barriers (and flush points) until there are
+    /// as many as there were when a system was added.
+    fn replay_barriers(&mut self, barriers: usize, flush_barriers: &[usize]) {
+        while self.barriers < barriers {
+            if flush_barriers.contains(&(self.barriers + 1)) {
+                self.add_flush_point();
+            } else {
+                self.add_barrier();
+            }
+        }
+    }
+
+    fn schedule(&mut self,
+                mut info: SystemInfo,
+                condition: RunCondition,
+                system: SystemExecSend<'a>) {
+        let id = SystemId(self.systems.len());
+        let dependencies = info.dependencies
+            .iter()
+            .filter_map(|x| self.map.get(x).cloned())
+            .collect();
+
+        if !info.name.is_empty() {
+            self.map.entry(info.name.clone()).or_insert(id);
+        }
+
+        info.stage = self.stages
+            .insert(dependencies,
+                    id,
+                    &info.reads,
+                    &info.writes,
+                    info.running_time,
+                    system);
+
+        #[cfg(feature = "profiling")]
+        self.profiler.set_stage(info.profiler_index, info.stage);
+
+        self.systems.push(info);
+        self.conditions.push(condition);
+    }
+}
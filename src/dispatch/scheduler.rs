@@ -0,0 +1,126 @@
+//! The extension point deciding how systems
+//! are packed into stages and groups.
+
+use dispatch::SystemId;
+use dispatch::stage::Layout;
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::ResourceId;
+use system::RunningTime;
+
+/// Decides which stage and group a system is added to
+/// (see the stages module for how they are executed).
+///
+/// Stages are executed one after another, the groups of
+/// a stage in parallel and the systems of a group in order.
+/// A scheduler is asked for a `Placement` every time a system
+/// is added and has to respect that constraint; placing a
+/// system next to a group it conflicts with, or before one of
+/// its dependencies, panics.
+///
+/// Use `DispatcherBuilder::with_scheduler` to replace the
+/// `DefaultScheduler`, for example with the `DeterministicScheduler`.
+///
+/// # Examples
+///
+/// A scheduler running every system in its own stage:
+///
+/// ```rust
+/// # use shred::{DispatcherBuilder, Layout, NewSystem, Placement, Scheduler};
+/// struct Sequential;
+///
+/// impl Scheduler for Sequential {
+///     fn place(&mut self, _: &Layout, _: &NewSystem) -> Placement {
+///         Placement::NewStage
+///     }
+/// }
+///
+/// let builder = DispatcherBuilder::new().with_scheduler(Sequential);
+/// ```
+pub trait Scheduler: Send {
+    /// Returns where `system` should be added to `layout`.
+    fn place(&mut self, layout: &Layout, system: &NewSystem) -> Placement;
+}
+
+/// The system a `Scheduler` has to place.
+#[derive(Debug)]
+pub struct NewSystem<'s> {
+    /// The id of the system, which is its index
+    /// in the order systems were added.
+    pub id: SystemId,
+    /// The systems this system has to run after.
+    pub dependencies: &'s [SystemId],
+    /// The (deduplicated) resources read by the system.
+    pub reads: &'s [ResourceId],
+    /// The resources written by the system.
+    pub writes: &'s [ResourceId],
+    /// The estimated running time of the system.
+    pub running_time: RunningTime,
+}
+
+/// Where a system is added, returned by `Scheduler::place`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Placement {
+    /// Appends the system to the group with the given index in
+    /// the given stage, so it runs after the systems of the group.
+    Group(usize, usize),
+    /// Adds a group containing only the system to the given stage.
+    NewGroup(usize),
+    /// Adds a new stage after all the other stages.
+    NewStage,
+}
+
+/// The scheduler used by default.
+///
+/// It adds a system to the first stage after the last barrier
+/// it doesn't conflict with. A system conflicting with exactly
+/// one group of a stage is appended to that group instead, as
+/// long as that balances the running times of the groups.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultScheduler;
+
+/// A scheduler running conflicting systems in the order
+/// they were added, independent of thread timing.
+///
+/// The `DefaultScheduler` may add a system to a stage before
+/// the stage of a conflicting system added earlier, so a system
+/// writing a resource can run before another one added before it.
+/// This scheduler only adds systems after the last stage they
+/// conflict with (or to the end of the single group they
+/// conflict with in that stage), which is useful if the
+/// simulation has to be reproducible, e.g. for lockstep
+/// networking.
+///
+/// Side effects through resources which are mutated from shared
+/// borrows, like `LazyUpdate`, still happen in any order.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeterministicScheduler;
+
+impl Scheduler for DeterministicScheduler {
+    fn place(&mut self, layout: &Layout, system: &NewSystem) -> Placement {
+        let conflicting = |stage| {
+            (0..layout.num_groups(stage))
+                .filter(|&group| layout.conflicts(stage, group, system))
+                .collect::<Vec<_>>()
+        };
+
+        let last = (layout.first_stage()..layout.num_stages())
+            .rev()
+            .map(|stage| (stage, conflicting(stage)))
+            .find(|(_, groups)| !groups.is_empty());
+
+        let next = match last {
+            Some((stage, ref groups)) if groups.len() == 1 && layout.has_room(stage, groups[0]) => {
+                return Placement::Group(stage, groups[0]);
+            }
+            Some((stage, _)) => stage + 1,
+            None => layout.first_stage(),
+        };
+
+        if next < layout.num_stages() {
+            Placement::NewGroup(next)
+        } else {
+            Placement::NewStage
+        }
+    }
+}
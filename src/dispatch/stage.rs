@@ -30,12 +30,24 @@
 //!   in code).
 //!
 
+#[cfg(feature = "std")]
+use std::panic::{AssertUnwindSafe, catch_unwind};
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::time::Instant;
+
 use arrayvec::ArrayVec;
 use smallvec::SmallVec;
 
-use dispatch::{SystemExecSend, SystemId};
+use dispatch::{RunCondition, SystemExecSend, SystemId};
+#[cfg(feature = "std")]
+use dispatch::panic_message;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use dispatch::affinity::Affinity;
+use dispatch::scheduler::{DefaultScheduler, NewSystem, Placement, Scheduler};
+#[cfg(not(feature = "std"))]
+use prelude::*;
 use res::{Resources, ResourceId};
-use system::{RunningTime, System};
+use system::RunningTime;
 
 const MAX_SYSTEMS_PER_GROUP: usize = 5;
 
@@ -58,16 +70,16 @@ impl Conflict {
 
 type GroupVec<T> = SmallVec<[T; 6]>;
 
-#[derive(Debug)]
-enum InsertionTarget {
-    Stage(usize),
-    Group(usize, usize),
-    NewStage,
-}
+type Group<'a> = ArrayVec<[(SystemId, SystemExecSend<'a>); MAX_SYSTEMS_PER_GROUP]>;
+
+/// The panics caught while executing a stage,
+/// with the id of the panicking system and
+/// the panic message.
+pub type Panics = Vec<(SystemId, String)>;
 
 #[derive(Default)]
 pub struct Stage<'a> {
-    groups: GroupVec<ArrayVec<[SystemExecSend<'a>; MAX_SYSTEMS_PER_GROUP]>>,
+    groups: GroupVec<Group<'a>>,
 }
 
 impl<'a> Stage<'a> {
@@ -75,65 +87,239 @@ impl<'a> Stage<'a> {
         Default::default()
     }
 
-    #[cfg(not(target_os = "emscripten"))]
-    pub fn execute(&mut self, res: &Resources) {
+    /// Executes the groups of this stage in parallel, starting
+    /// the ones which took longest in previous dispatches first.
+    ///
+    /// Panicking systems don't stop the other systems
+    /// of the stage; their panics are returned instead.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn execute(&mut self, res: &Resources, conditions: &[RunCondition]) -> Panics {
+        use std::cmp::Reverse;
+        use std::sync::Mutex;
+
         use rayon::prelude::*;
+        use rayon::scope;
+
+        use dispatch::affinity::spawn_pinned;
+
+        // Rayon's worker threads don't know about the current span,
+        // so it has to be passed explicitly.
+        #[cfg(feature = "tracing")]
+        let parent = ::tracing::Span::current();
+
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        let run = |index: usize, group: &mut Group| {
+            #[cfg(feature = "tracing")]
+            let _span = ::tracing::trace_span!(parent: &parent, "group", index = index).entered();
+
+            execute_group(group, res, conditions)
+        };
+
+        let mut groups: GroupVec<_> = self.groups.iter_mut().enumerate().collect();
+        groups.sort_by_key(|(_, group)| Reverse(expected_time(group, conditions)));
+
+        let (pinned, mut free): (GroupVec<_>, GroupVec<_>) = groups
+            .into_iter()
+            .partition(|(_, group)| pinned_condition(group, conditions).is_some());
+
+        let mut execute_free = || {
+            free.par_iter_mut()
+                .map(|&mut (index, ref mut group)| run(index, group))
+                .reduce(Vec::new, |mut a, b| {
+                    a.extend(b);
+                    a
+                })
+        };
+
+        if pinned.is_empty() {
+            return execute_free();
+        }
+
+        let pinned_panics = Mutex::new(Vec::new());
+        let mut panics = Vec::new();
+
+        scope(|s| {
+            for (index, group) in pinned {
+                let condition = pinned_condition(group, conditions).expect("Group isn't pinned");
+                let run = &run;
+                let pinned_panics = &pinned_panics;
+
+                spawn_pinned(s, condition.affinity, &condition.affinity_violations, move || {
+                    let panics = run(index, group);
+                    pinned_panics
+                        .lock()
+                        .expect("Mutex poisoned")
+                        .extend(panics);
+                });
+            }
+
+            panics = execute_free();
+        });
 
-        self.groups
-            .par_iter_mut()
-            .for_each(|group| for system in group {
-                          system.run_now(res);
-                      });
+        panics.extend(pinned_panics.into_inner().expect("Mutex poisoned"));
+
+        panics
     }
 
-    pub fn execute_seq(&mut self, res: &Resources) {
+    pub fn setup(&mut self, res: &mut Resources) {
         for group in &mut self.groups {
-            for system in group {
-                system.run_now(res);
+            for &mut (_, ref mut system) in group {
+                system.setup(res);
+            }
+        }
+    }
+
+    /// Like `execute`, but runs the groups sequentially.
+    pub fn execute_seq(&mut self, res: &Resources, conditions: &[RunCondition]) -> Panics {
+        let mut panics = Vec::new();
+
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        for (index, group) in self.groups.iter_mut().enumerate() {
+            #[cfg(feature = "tracing")]
+            let _span = ::tracing::trace_span!("group", index = index).entered();
+
+            panics.extend(execute_group(group, res, conditions));
+        }
+
+        panics
+    }
+}
+
+/// Returns the condition of the first system in a group
+/// with an affinity other than `Any`, which is the affinity
+/// of the whole group.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+fn pinned_condition<'c>(group: &Group, conditions: &'c [RunCondition]) -> Option<&'c RunCondition> {
+    group
+        .iter()
+        .map(|&(id, _)| &conditions[id.0])
+        .find(|condition| condition.affinity != Affinity::Any)
+}
+
+/// Returns the sum of the average running times of a group.
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+fn expected_time(group: &Group, conditions: &[RunCondition]) -> usize {
+    group
+        .iter()
+        .map(|&(id, _)| conditions[id.0].average())
+        .sum()
+}
+
+fn execute_group(group: &mut Group, res: &Resources, conditions: &[RunCondition]) -> Panics {
+    #[cfg_attr(not(feature = "std"), allow(unused_mut))]
+    let mut panics = Vec::new();
+
+    for &mut (id, ref mut system) in group {
+        if conditions[id.0].should_run(res) {
+            // The times are only used to order the groups of parallel
+            // stages, and `Instant` panics on `wasm32-unknown-unknown`.
+            #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+            let start = Instant::now();
+
+            // Without std, panics can't be caught
+            #[cfg(feature = "std")]
+            {
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(|| system.run_now(res))) {
+                    panics.push((id, panic_message(payload)));
+                }
             }
+
+            #[cfg(not(feature = "std"))]
+            system.run_now(res);
+
+            #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+            conditions[id.0].record(start.elapsed());
         }
     }
+
+    panics
 }
 
-#[derive(Default)]
 pub struct StagesBuilder<'a> {
     barrier: usize,
     ids: Vec<GroupVec<ArrayVec<[SystemId; MAX_SYSTEMS_PER_GROUP]>>>,
     reads: Vec<GroupVec<SmallVec<[ResourceId; 12]>>>,
     running_time: Vec<GroupVec<u8>>,
+    scheduler: Box<Scheduler>,
     stages: Vec<Stage<'a>>,
     writes: Vec<GroupVec<SmallVec<[ResourceId; 10]>>>,
 }
 
+impl<'a> Default for StagesBuilder<'a> {
+    fn default() -> Self {
+        StagesBuilder::new(Box::new(DefaultScheduler))
+    }
+}
+
 impl<'a> StagesBuilder<'a> {
+    pub fn new(scheduler: Box<Scheduler>) -> Self {
+        StagesBuilder {
+            barrier: 0,
+            ids: Vec::new(),
+            reads: Vec::new(),
+            running_time: Vec::new(),
+            scheduler: scheduler,
+            stages: Vec::new(),
+            writes: Vec::new(),
+        }
+    }
+
     pub fn add_barrier(&mut self) {
         self.barrier = self.stages.len();
     }
 
-    pub fn insert<T>(&mut self, mut dep: SmallVec<[SystemId; 4]>, id: SystemId, system: T)
-        where T: for<'b> System<'b> + Send + 'a
-    {
-        use system::SystemData;
-
-        let mut reads = T::SystemData::reads(0);
-        let writes = T::SystemData::writes(0);
-
-        reads.sort();
-        reads.dedup();
-
-        let new_time = system.running_time();
+    pub fn num_stages(&self) -> usize {
+        self.stages.len()
+    }
 
-        let target = self.insertion_target(&reads, &writes, &mut dep, new_time);
+    /// Inserts a system, given its (deduplicated) reads and writes,
+    /// and returns the index of the stage it was added to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scheduler returns an invalid placement.
+    pub fn insert(&mut self,
+                  dep: SmallVec<[SystemId; 4]>,
+                  id: SystemId,
+                  reads: &[ResourceId],
+                  writes: &[ResourceId],
+                  new_time: RunningTime,
+                  system: SystemExecSend<'a>)
+                  -> usize {
+        let placement = {
+            let layout = Layout {
+                barrier: self.barrier,
+                ids: &self.ids,
+                reads: &self.reads,
+                running_time: &self.running_time,
+                writes: &self.writes,
+            };
+            let new = NewSystem {
+                id: id,
+                dependencies: &dep,
+                reads: reads,
+                writes: writes,
+                running_time: new_time,
+            };
+
+            let placement = self.scheduler.place(&layout, &new);
+            assert!(layout.is_valid(placement, &new),
+                    "Scheduler returned invalid placement {:?} for {:?}",
+                    placement,
+                    id);
+
+            placement
+        };
 
-        let (stage, group) = match target {
-            InsertionTarget::Stage(stage) => {
+        let (stage, group) = match placement {
+            Placement::NewGroup(stage) => {
                 let group = self.ids[stage].len();
                 self.add_group(stage);
 
                 (stage, group)
             }
-            InsertionTarget::Group(stage, group) => (stage, group),
-            InsertionTarget::NewStage => {
+            Placement::Group(stage, group) => (stage, group),
+            Placement::NewStage => {
                 let stage = self.stages.len();
 
                 self.add_stage();
@@ -144,16 +330,53 @@ impl<'a> StagesBuilder<'a> {
         };
 
         self.ids[stage][group].push(id);
-        self.reads[stage][group].extend(reads);
+        self.reads[stage][group].extend(reads.iter().cloned());
         self.running_time[stage][group] += new_time as u8;
-        self.stages[stage].groups[group].push(Box::new(system));
-        self.writes[stage][group].extend(writes);
+        self.stages[stage].groups[group].push((id, system));
+        self.writes[stage][group].extend(writes.iter().cloned());
+
+        stage
     }
 
     pub fn build(self) -> Vec<Stage<'a>> {
         self.stages
     }
 
+    pub fn stages_mut(&mut self) -> &mut [Stage<'a>] {
+        &mut self.stages
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Box<Scheduler>) {
+        self.scheduler = scheduler;
+    }
+
+    /// Takes the systems out of the stages, indexed by their
+    /// ids, leaving no stages but keeping the scheduler.
+    pub fn take_systems(&mut self) -> Vec<Option<SystemExecSend<'a>>> {
+        let num_systems = self.ids
+            .iter()
+            .flat_map(|groups| groups.iter())
+            .map(|group| group.len())
+            .sum();
+        let mut systems: Vec<_> = (0..num_systems).map(|_| None).collect();
+
+        self.barrier = 0;
+        self.ids.clear();
+        self.reads.clear();
+        self.running_time.clear();
+        self.writes.clear();
+
+        for stage in self.stages.drain(..) {
+            for group in stage.groups {
+                for (id, system) in group {
+                    systems[id.0] = Some(system);
+                }
+            }
+        }
+
+        systems
+    }
+
     fn add_stage(&mut self) {
         self.ids.push(GroupVec::new());
         self.reads.push(GroupVec::new());
@@ -164,63 +387,10 @@ impl<'a> StagesBuilder<'a> {
 
     fn add_group(&mut self, stage: usize) {
         self.ids[stage].push(ArrayVec::new());
-        self.reads[stage].push(SmallVec::new());
+        self.reads[stage].push(Default::default());
         self.running_time[stage].push(0);
         self.stages[stage].groups.push(ArrayVec::new());
-        self.writes[stage].push(SmallVec::new());
-    }
-
-    fn insertion_target<'rw, R, W>(&self,
-                                   new_reads: R,
-                                   new_writes: W,
-                                   new_dep: &mut SmallVec<[SystemId; 4]>,
-                                   new_time: RunningTime)
-                                   -> InsertionTarget
-        where R: IntoIterator<Item = &'rw ResourceId>,
-              R::IntoIter: Clone,
-              W: IntoIterator<Item = &'rw ResourceId>,
-              W::IntoIter: Clone
-    {
-        let new_reads = new_reads.into_iter();
-        let new_writes = new_writes.into_iter();
-
-        (self.barrier..self.stages.len())
-            .map(|stage| {
-                let conflict = Self::find_conflict(&*self.ids,
-                                                   &*self.reads,
-                                                   &*self.writes,
-                                                   stage,
-                                                   new_reads.clone(),
-                                                   new_writes.clone(),
-                                                   new_dep);
-                self.remove_ids(stage, new_dep);
-                (stage, conflict)
-            })
-            .find(|&(stage, conflict)| match conflict {
-                      Conflict::None => true,
-                      Conflict::Single(group) => {
-                          self.stages[stage].groups[group].len() < MAX_SYSTEMS_PER_GROUP - 1 &&
-                          self.improves_balance(stage, group, new_time as u8)
-                      }
-                      Conflict::Multiple => false,
-                  })
-            .map(|(stage, conflict)| match conflict {
-                     Conflict::None => InsertionTarget::Stage(stage),
-                     Conflict::Single(group) => InsertionTarget::Group(stage, group),
-                     Conflict::Multiple => unreachable!(),
-                 })
-            .unwrap_or(InsertionTarget::NewStage)
-    }
-
-    fn improves_balance(&self, stage: usize, group: usize, new_time: u8) -> bool {
-        let max = *self.running_time[stage].iter().max().unwrap() as i8;
-        let old_time = self.running_time[stage][group];
-        let new_time = (old_time + new_time) as i8;
-
-        // Check if adding the system to the group would
-        // balance the stage better.
-
-        (max - new_time).abs() < (max - old_time as i8).abs()
+        self.writes[stage].push(Default::default());
     }
 
     /// Returns an enum indicating which kind of conflict a system has
@@ -243,19 +413,117 @@ impl<'a> StagesBuilder<'a> {
 
         let num_groups = ids[stage].len();
 
-        let conflict = (0..num_groups)
+        (0..num_groups)
             .filter(|&group| {
-                let read_and_write = writes[stage][group]
-                    .iter()
-                    .chain(reads[stage][group].iter());
-
-                check_intersection(new_writes.clone(), read_and_write) ||
-                check_intersection(new_reads.clone(), writes[stage][group].iter()) ||
-                check_intersection(new_dep.iter(), ids[stage][group].iter())
+                conflicts_with_group(&ids[stage][group],
+                                     &reads[stage][group],
+                                     &writes[stage][group],
+                                     new_reads.clone(),
+                                     new_writes.clone(),
+                                     new_dep)
             })
-            .fold(Conflict::None, Conflict::add);
+            .fold(Conflict::None, Conflict::add)
+    }
+}
+
+/// A view of the stages built so far, passed to
+/// a [`Scheduler`] deciding where to add a system.
+///
+/// Stages and groups are referred to by their index.
+///
+/// [`Scheduler`]: trait.Scheduler.html
+pub struct Layout<'l> {
+    barrier: usize,
+    ids: &'l [GroupVec<ArrayVec<[SystemId; MAX_SYSTEMS_PER_GROUP]>>],
+    reads: &'l [GroupVec<SmallVec<[ResourceId; 12]>>],
+    running_time: &'l [GroupVec<u8>],
+    writes: &'l [GroupVec<SmallVec<[ResourceId; 10]>>],
+}
+
+impl<'l> Layout<'l> {
+    /// Returns the first stage systems can be added to,
+    /// which is the first stage after the last barrier.
+    pub fn first_stage(&self) -> usize {
+        self.barrier
+    }
+
+    /// Returns the number of stages.
+    pub fn num_stages(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns the number of groups in a stage.
+    pub fn num_groups(&self, stage: usize) -> usize {
+        self.ids[stage].len()
+    }
+
+    /// Returns the systems of a group, in the order they run.
+    pub fn systems(&self, stage: usize, group: usize) -> &[SystemId] {
+        &self.ids[stage][group]
+    }
+
+    /// Returns the sum of the running times of the systems
+    /// in a group, counting `RunningTime::VeryShort` as 1.
+    pub fn running_time(&self, stage: usize, group: usize) -> u8 {
+        self.running_time[stage][group]
+    }
+
+    /// Returns true if another system can be added to a group.
+    pub fn has_room(&self, stage: usize, group: usize) -> bool {
+        self.ids[stage][group].len() < MAX_SYSTEMS_PER_GROUP
+    }
+
+    /// Returns true if `system` can't run in parallel with a
+    /// group, because it accesses a resource the group writes,
+    /// writes one the group reads or depends on a system of it.
+    pub fn conflicts(&self, stage: usize, group: usize, system: &NewSystem) -> bool {
+        conflicts_with_group(&self.ids[stage][group],
+                             &self.reads[stage][group],
+                             &self.writes[stage][group],
+                             system.reads.iter(),
+                             system.writes.iter(),
+                             system.dependencies)
+    }
+
+    /// Returns true if `system` can be added as the scheduler asked.
+    fn is_valid(&self, placement: Placement, system: &NewSystem) -> bool {
+        let (stage, group) = match placement {
+            Placement::Group(stage, group) => (stage, Some(group)),
+            Placement::NewGroup(stage) => (stage, None),
+            Placement::NewStage => return true,
+        };
+
+        if stage < self.barrier || stage >= self.num_stages() {
+            return false;
+        }
 
-        conflict
+        if let Some(group) = group {
+            if group >= self.num_groups(stage) || !self.has_room(stage, group) {
+                return false;
+            }
+        }
+
+        // Dependencies have to be in an earlier stage or the same group
+        if (stage + 1..self.num_stages())
+               .flat_map(|later| self.ids[later].iter().flat_map(|ids| ids.iter()))
+               .any(|id| system.dependencies.contains(id)) {
+            return false;
+        }
+
+        (0..self.num_groups(stage))
+            .filter(|&other| Some(other) != group)
+            .all(|other| !self.conflicts(stage, other, system))
+    }
+
+    fn improves_balance(&self, stage: usize, group: usize, new_time: u8) -> bool {
+        let max = *self.running_time[stage].iter().max().unwrap() as i8;
+        let old_time = self.running_time[stage][group];
+        let new_time = (old_time + new_time) as i8;
+
+        // Check if adding the system to the group would
+        // balance the stage better.
+
+        (max - new_time).abs() < (max - old_time as i8).abs()
     }
 
     /// Removes the ids of a given stage from the passed dependency list.
@@ -270,6 +538,62 @@ impl<'a> StagesBuilder<'a> {
     }
 }
 
+impl Scheduler for DefaultScheduler {
+    // `SmallVec` is a `Vec` without std
+    #[cfg_attr(not(feature = "std"), allow(clippy::iter_cloned_collect))]
+    fn place(&mut self, layout: &Layout, system: &NewSystem) -> Placement {
+        let mut new_dep: SmallVec<[SystemId; 4]> = system.dependencies.iter().cloned().collect();
+        for stage in 0..layout.barrier {
+            layout.remove_ids(stage, &mut new_dep);
+        }
+
+        (layout.barrier..layout.num_stages())
+            .map(|stage| {
+                let conflict = StagesBuilder::find_conflict(layout.ids,
+                                                            layout.reads,
+                                                            layout.writes,
+                                                            stage,
+                                                            system.reads,
+                                                            system.writes,
+                                                            &new_dep);
+                layout.remove_ids(stage, &mut new_dep);
+                // Dependencies in later stages rule out this stage
+                (stage, conflict, new_dep.is_empty())
+            })
+            .filter(|&(_, _, after_dep)| after_dep)
+            .map(|(stage, conflict, _)| (stage, conflict))
+            .find(|&(stage, conflict)| match conflict {
+                      Conflict::None => true,
+                      Conflict::Single(group) => {
+                          layout.ids[stage][group].len() < MAX_SYSTEMS_PER_GROUP - 1 &&
+                          layout.improves_balance(stage, group, system.running_time as u8)
+                      }
+                      Conflict::Multiple => false,
+                  })
+            .map(|(stage, conflict)| match conflict {
+                     Conflict::None => Placement::NewGroup(stage),
+                     Conflict::Single(group) => Placement::Group(stage, group),
+                     Conflict::Multiple => unreachable!(),
+                 })
+            .unwrap_or(Placement::NewStage)
+    }
+}
+
+fn conflicts_with_group<'rw, R, W>(ids: &[SystemId],
+                                  reads: &[ResourceId],
+                                  writes: &[ResourceId],
+                                  new_reads: R,
+                                  new_writes: W,
+                                  new_dep: &[SystemId])
+                                  -> bool
+    where R: Iterator<Item = &'rw ResourceId>,
+          W: Iterator<Item = &'rw ResourceId>
+{
+    check_intersection(new_writes, writes.iter().chain(reads.iter())) ||
+    check_intersection(new_reads, writes.iter()) ||
+    check_intersection(new_dep.iter(), ids.iter())
+}
+
 fn check_intersection<'i, 'j, T, I, J>(mut i: I, j: J) -> bool
     where I: Iterator<Item = &'i T>,
           J: Iterator<Item = &'j T> + Clone,
@@ -281,6 +605,7 @@ fn check_intersection<'i, 'j, T, I, J>(mut i: I, j: J) -> bool
 #[cfg(test)]
 mod tests {
     use super::*;
+    use system::System;
 
     fn create_ids(ids: &[&[&[usize]]])
                   -> Vec<GroupVec<ArrayVec<[SystemId; MAX_SYSTEMS_PER_GROUP]>>> {
@@ -322,6 +647,22 @@ mod tests {
     struct ResB;
     struct ResC;
 
+    fn insert<T>(builder: &mut StagesBuilder, id: usize, system: T) -> usize
+        where T: for<'b> System<'b> + Send + 'static
+    {
+        use system::SystemData;
+
+        let reads = T::SystemData::reads(0);
+        let writes = T::SystemData::writes(0);
+
+        builder.insert(Default::default(),
+                       SystemId(id),
+                       &reads,
+                       &writes,
+                       system.running_time(),
+                       Box::new(system))
+    }
+
     #[test]
     fn check_intersection_basic() {
         assert!(check_intersection((&[1, 5]).iter(), (&[2, 5]).iter()));
@@ -345,7 +686,7 @@ mod tests {
                                                     0,
                                                     &[],
                                                     &[ResourceId::new::<ResB>()],
-                                                    &SmallVec::new());
+                                                    &Default::default());
         assert_eq!(conflict, Conflict::Single(1));
     }
 
@@ -361,7 +702,7 @@ mod tests {
                                                     0,
                                                     &[],
                                                     &[ResourceId::new::<ResB>()],
-                                                    &SmallVec::new());
+                                                    &Default::default());
         assert_eq!(conflict, Conflict::Single(0));
     }
 
@@ -379,7 +720,7 @@ mod tests {
                                                     &[],
                                                     &[ResourceId::new::<ResB>(),
                                                       ResourceId::new::<ResC>()],
-                                                    &SmallVec::new());
+                                                    &Default::default());
         assert_eq!(conflict, Conflict::Multiple);
     }
 
@@ -425,9 +766,9 @@ mod tests {
 
         let mut builder: StagesBuilder = Default::default();
 
-        builder.insert(SmallVec::new(), SystemId(0), SysA);
-        builder.insert(SmallVec::new(), SystemId(1), SysB);
-        builder.insert(SmallVec::new(), SystemId(2), SysC);
+        assert_eq!(insert(&mut builder, 0, SysA), 0);
+        assert_eq!(insert(&mut builder, 1, SysB), 0);
+        assert_eq!(insert(&mut builder, 2, SysC), 0);
 
         let ref ids = builder.ids[0];
 
@@ -435,4 +776,59 @@ mod tests {
         assert_eq!(ids[1][0], SystemId(1));
         assert_eq!(ids[1][1], SystemId(2));
     }
+
+    #[test]
+    fn barrier() {
+        use res::Fetch;
+
+        struct SysA;
+
+        impl<'a> System<'a> for SysA {
+            type SystemData = Fetch<'a, ResA>;
+
+            fn run(&mut self, _: Self::SystemData) {}
+        }
+
+        let mut builder: StagesBuilder = Default::default();
+
+        assert_eq!(insert(&mut builder, 0, SysA), 0);
+        builder.add_barrier();
+        assert_eq!(insert(&mut builder, 1, SysA), 1);
+        assert_eq!(insert(&mut builder, 2, SysA), 1);
+        builder.add_barrier();
+        builder.add_barrier();
+        assert_eq!(insert(&mut builder, 3, SysA), 2);
+    }
+
+    #[test]
+    fn dependency_in_later_stage() {
+        use res::FetchMut;
+
+        struct SysA;
+
+        impl<'a> System<'a> for SysA {
+            type SystemData = FetchMut<'a, ResA>;
+
+            fn run(&mut self, _: Self::SystemData) {}
+        }
+
+        // SysC shares no resources with the others, but depends
+        // on the second SysA, which is in the second stage.
+
+        let mut builder: StagesBuilder = Default::default();
+
+        assert_eq!(insert(&mut builder, 0, SysA), 0);
+        assert_eq!(insert(&mut builder, 1, SysA), 1);
+
+        let mut dep: SmallVec<[SystemId; 4]> = Default::default();
+        dep.push(SystemId(1));
+        let stage = builder.insert(dep,
+                                   SystemId(2),
+                                   &[ResourceId::new::<ResC>()],
+                                   &[],
+                                   RunningTime::Average,
+                                   Box::new(SysA));
+
+        assert_eq!(stage, 2);
+    }
 }
@@ -0,0 +1,37 @@
+//! Instrumentation of systems with `tracing`,
+//! only compiled with the `tracing` feature.
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::Resources;
+use system::RunNow;
+
+/// Wraps a system, running it inside a span
+/// named after the system.
+pub struct Traced<T> {
+    inner: T,
+    name: String,
+}
+
+impl<T> Traced<T> {
+    pub fn new(name: &str, inner: T) -> Self {
+        Traced {
+            inner: inner,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl<'a, T> RunNow<'a> for Traced<T>
+    where T: RunNow<'a>
+{
+    fn run_now(&mut self, res: &'a Resources) {
+        let _span = ::tracing::trace_span!("system", name = %self.name).entered();
+
+        self.inner.run_now(res);
+    }
+
+    fn setup(&mut self, res: &mut Resources) {
+        self.inner.setup(res);
+    }
+}
@@ -0,0 +1,222 @@
+//! A channel for sending events
+//! from one system to many others.
+
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::iter::Skip;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+
+/// A queue of events with any number of readers,
+/// meant to be stored as a resource.
+///
+/// Writing requires mutable access, but reading only
+/// needs a `Fetch`, so readers of a channel can run in
+/// parallel. Every reader has a [`ReaderId`] keeping track
+/// of the events it has read already.
+///
+/// Events are dropped once all registered readers have
+/// read them, which is checked when writing new events.
+/// Dropping a `ReaderId` unregisters the reader, so it
+/// doesn't hold back events anymore.
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{DispatcherBuilder, EventChannel, Fetch, FetchMut, ReaderId, Resources, System};
+/// struct Collision(u32);
+///
+/// struct Physics;
+///
+/// impl<'a> System<'a> for Physics {
+///     type SystemData = FetchMut<'a, EventChannel<Collision>>;
+///
+///     fn run(&mut self, mut collisions: Self::SystemData) {
+///         collisions.single_write(Collision(7));
+///     }
+/// }
+///
+/// struct Sound(ReaderId);
+///
+/// impl<'a> System<'a> for Sound {
+///     type SystemData = Fetch<'a, EventChannel<Collision>>;
+///
+///     fn run(&mut self, collisions: Self::SystemData) {
+///         for collision in collisions.read(&mut self.0) {
+///             assert_eq!(collision.0, 7);
+///         }
+///     }
+/// }
+///
+/// let mut res = Resources::new();
+/// let mut channel = EventChannel::<Collision>::new();
+/// let reader = channel.register_reader();
+/// res.add(channel);
+///
+/// let mut dispatcher = DispatcherBuilder::new()
+///     .add(Physics, "physics", &[])
+///     .add(Sound(reader), "sound", &["physics"])
+///     .build();
+///
+/// dispatcher.dispatch(&mut res);
+/// ```
+///
+/// [`ReaderId`]: struct.ReaderId.html
+pub struct EventChannel<T> {
+    events: VecDeque<T>,
+    offset: usize,
+    readers: Vec<Arc<AtomicUsize>>,
+}
+
+impl<T> EventChannel<T> {
+    /// Creates an empty channel without readers.
+    pub fn new() -> Self {
+        EventChannel {
+            events: VecDeque::new(),
+            offset: 0,
+            readers: Vec::new(),
+        }
+    }
+
+    /// Registers a new reader, which only reads the
+    /// events written after it was registered.
+    pub fn register_reader(&mut self) -> ReaderId {
+        let position = Arc::new(AtomicUsize::new(self.offset + self.events.len()));
+        self.readers.push(position.clone());
+
+        ReaderId { position: position }
+    }
+
+    /// Writes a single event.
+    pub fn single_write(&mut self, event: T) {
+        self.collect_garbage();
+        self.events.push_back(event);
+    }
+
+    /// Writes all events of `events`, in order.
+    pub fn iter_write<I>(&mut self, events: I)
+        where I: IntoIterator<Item = T>
+    {
+        self.collect_garbage();
+        self.events.extend(events);
+    }
+
+    /// Returns an iterator over the events `reader`
+    /// hasn't read yet, and marks them as read.
+    ///
+    /// `reader` has to be registered with this channel.
+    pub fn read(&self, reader: &mut ReaderId) -> EventIter<T> {
+        let position = reader.position.load(Ordering::Acquire);
+        let skip = position.max(self.offset) - self.offset;
+
+        reader
+            .position
+            .store(self.offset + self.events.len(), Ordering::Release);
+
+        EventIter { inner: self.events.iter().skip(skip) }
+    }
+
+    /// Returns the number of events which haven't
+    /// been read by all readers yet.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns true if there are no unread events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Unregisters dropped readers and drops
+    /// the events all other readers have read.
+    fn collect_garbage(&mut self) {
+        // The channel holds the only other reference
+        // to the position of a registered reader.
+        self.readers.retain(|x| Arc::strong_count(x) > 1);
+
+        let end = self.offset + self.events.len();
+        let read = self.readers
+            .iter()
+            .map(|x| x.load(Ordering::Acquire))
+            .min()
+            .unwrap_or(end);
+
+        for _ in self.offset..read {
+            self.events.pop_front();
+        }
+
+        self.offset = read.max(self.offset);
+    }
+}
+
+impl<T> Default for EventChannel<T> {
+    fn default() -> Self {
+        EventChannel::new()
+    }
+}
+
+/// A handle to a reader of an [`EventChannel`],
+/// created with `EventChannel::register_reader`.
+///
+/// [`EventChannel`]: struct.EventChannel.html
+#[derive(Debug)]
+pub struct ReaderId {
+    position: Arc<AtomicUsize>,
+}
+
+/// Iterator returned by [`EventChannel::read`].
+///
+/// [`EventChannel::read`]: struct.EventChannel.html#method.read
+pub struct EventIter<'a, T: 'a> {
+    inner: Skip<Iter<'a, T>>,
+}
+
+impl<'a, T> Iterator for EventIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_collect() {
+        let mut channel = EventChannel::new();
+        channel.single_write(0);
+
+        let mut a = channel.register_reader();
+        let mut b = channel.register_reader();
+        channel.iter_write(vec![1, 2]);
+
+        assert_eq!(channel.read(&mut a).cloned().collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(channel.read(&mut a).count(), 0);
+
+        // `b` hasn't read anything yet
+        channel.single_write(3);
+        assert_eq!(channel.len(), 3);
+        assert_eq!(channel.read(&mut b).cloned().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        channel.single_write(4);
+        assert_eq!(channel.len(), 2);
+
+        drop(a);
+        channel.single_write(5);
+        assert_eq!(channel.read(&mut b).cloned().collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(channel.len(), 2);
+
+        drop(b);
+        channel.single_write(6);
+        assert_eq!(channel.len(), 1);
+    }
+}
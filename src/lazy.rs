@@ -0,0 +1,164 @@
+//! Changes to the resources which are deferred
+//! until `Resources::maintain` is called.
+
+use std::mem::take;
+use std::sync::Mutex;
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::{Resource, Resources};
+
+/// A queued change, which is only `FnOnce`
+/// because it can be called from a box.
+trait Update: Send {
+    fn update(self: Box<Self>, res: &mut Resources);
+}
+
+impl<F> Update for F
+    where F: FnOnce(&mut Resources) + Send
+{
+    fn update(self: Box<Self>, res: &mut Resources) {
+        (*self)(res)
+    }
+}
+
+/// A queue of changes to the resources, which can be
+/// pushed with a shared borrow of this resource and are
+/// applied by [`Resources::maintain`].
+///
+/// This allows systems to add and remove resources
+/// without conflicting with other systems while
+/// the dispatcher is running.
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{DispatcherBuilder, LazyUpdate, ReadDefault, Resources, System};
+/// struct Spawner;
+///
+/// impl<'a> System<'a> for Spawner {
+///     type SystemData = ReadDefault<'a, LazyUpdate>;
+///
+///     fn run(&mut self, lazy: Self::SystemData) {
+///         lazy.add(5u32);
+///     }
+/// }
+///
+/// let mut res = Resources::new();
+/// let mut dispatcher = DispatcherBuilder::new()
+///     .add(Spawner, "spawner", &[])
+///     .build();
+///
+/// dispatcher.setup(&mut res);
+/// dispatcher.dispatch(&mut res);
+/// res.maintain();
+///
+/// assert_eq!(*res.fetch::<u32>(0), 5);
+/// ```
+///
+/// [`Resources::maintain`]: struct.Resources.html#method.maintain
+#[derive(Default)]
+pub struct LazyUpdate {
+    queue: Mutex<Vec<Box<Update>>>,
+}
+
+impl LazyUpdate {
+    /// Creates an empty queue.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Queues `f`, which is called with mutable
+    /// access to all resources.
+    pub fn exec<F>(&self, f: F)
+        where F: FnOnce(&mut Resources) + Send + 'static
+    {
+        self.queue
+            .lock()
+            .expect("Mutex poisoned")
+            .push(Box::new(f));
+    }
+
+    /// Queues adding the resource `r` with the id `0`
+    /// (see `Resources::add`).
+    ///
+    /// Applying this panics if the resource
+    /// is already registered.
+    pub fn add<R>(&self, r: R)
+        where R: Resource
+    {
+        self.exec(move |res| res.add(r));
+    }
+
+    /// Queues removing the resource of type `R`
+    /// with the given id (see `Resources::remove`).
+    pub fn remove<R>(&self, id: usize)
+        where R: Resource
+    {
+        self.exec(move |res| { res.remove::<R>(id); });
+    }
+
+    /// Returns the number of queued changes.
+    pub fn len(&self) -> usize {
+        self.queue.lock().expect("Mutex poisoned").len()
+    }
+
+    /// Returns true if there are no queued changes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Applies the changes queued in the `LazyUpdate`
+/// resource, including the ones queued while applying.
+pub fn maintain(res: &mut Resources) {
+    loop {
+        let updates = match res.try_fetch::<LazyUpdate>(0) {
+            Some(lazy) => take(&mut *lazy.queue.lock().expect("Mutex poisoned")),
+            None => return,
+        };
+
+        if updates.is_empty() {
+            return;
+        }
+
+        for update in updates {
+            update.update(res);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use res::ResourceId;
+
+    #[test]
+    fn maintain_in_order() {
+        let mut res = Resources::new();
+        res.add(LazyUpdate::new());
+        res.add(1u8);
+
+        {
+            let lazy = res.fetch::<LazyUpdate>(0);
+            lazy.add(5u32);
+            lazy.remove::<u8>(0);
+            lazy.exec(|res| {
+                          *res.fetch_mut::<u32>(0) += 1;
+                          res.fetch::<LazyUpdate>(0).add(2u64);
+                      });
+            assert_eq!(lazy.len(), 3);
+        }
+
+        res.maintain();
+
+        assert_eq!(*res.fetch::<u32>(0), 6);
+        assert_eq!(*res.fetch::<u64>(0), 2);
+        assert!(!res.has_value(ResourceId::new::<u8>()));
+        assert!(res.fetch::<LazyUpdate>(0).is_empty());
+
+        // Doesn't require the queue
+        res.remove::<LazyUpdate>(0);
+        res.maintain();
+    }
+}
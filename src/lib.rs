@@ -49,27 +49,184 @@
 //! }
 //! ```
 //!
+//! # Single-threaded targets
+//!
+//! Systems are executed on a `rayon` thread pool, which is
+//! enabled by the default `parallel` feature. Without it, no
+//! threads are spawned and `Dispatcher::dispatch` runs the
+//! systems one after another in the order of their stages and
+//! groups, i.e. the same order for every dispatch. This makes it
+//! possible to build for `wasm32-unknown-unknown`:
+//!
+//! ```toml
+//! [dependencies]
+//! shred = { version = "0.4", default-features = false, features = ["std"] }
+//! ```
+//!
+//! The `parking` and `profiling` features measure time or
+//! sleep, which isn't supported on that target.
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature, the crate only depends on
+//! `core` and `alloc`. `Resources`, `SystemData` and the
+//! sequential `Dispatcher` are still available, but
+//!
+//! * resources are looked up in a `BTreeMap` instead of a hash map
+//! * `SmallVec`s are replaced by `Vec`s, so the dependencies and
+//!   accesses of systems are always allocated on the heap
+//! * errors of `FallibleSystem`s implement `shred::Error`
+//!   instead of `std::error::Error`
+//! * there are no thread-local resources, `FetchLocal` and `FetchLocalMut`
+//! * panics of systems aren't caught (they unwind through `try_dispatch`,
+//!   if the target unwinds at all), and `CancellationToken`s have no deadlines
+//! * there's no `Dispatcher::write_dot`, which needs `std::io`
+//! * the `parallel` feature (the thread pool and the parallel dispatcher)
+//!   and all other features except `tracing` enable `std`
+//!
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![deny(unused_must_use)]
 #![warn(missing_docs)]
 
+#[cfg(not(any(feature = "std", test)))]
+#[macro_use]
+extern crate alloc;
 extern crate arrayvec;
+#[cfg(feature = "serialize")]
+extern crate erased_serde;
+#[cfg(feature = "std")]
 extern crate fnv;
 #[macro_use]
 extern crate mopa;
-#[cfg(not(target_os = "emscripten"))]
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 extern crate pulse;
-#[cfg(not(target_os = "emscripten"))]
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 extern crate rayon;
+#[cfg(any(feature = "config", feature = "serialize"))]
+extern crate serde;
+#[cfg(feature = "config")]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(all(test, any(feature = "config", feature = "serialize")))]
+extern crate serde_json;
+#[cfg(feature = "std")]
 extern crate smallvec;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+
+/// Without std, the parts of it used
+/// by this crate come from `core` and `alloc`.
+#[cfg(not(any(feature = "std", test)))]
+mod std {
+    pub use alloc::{borrow, boxed, collections, fmt, string, vec};
+    pub use core::{any, cell, cmp, convert, hint, iter, marker, mem, ops, option, result, slice,
+                   time};
+
+    pub mod error {
+        use core::fmt::{Debug, Display};
+
+        /// Stands in for `std::error::Error` without std,
+        /// e.g. for the errors of a `FallibleSystem`.
+        pub trait Error: Debug + Display {
+            /// A short description of the error.
+            fn description(&self) -> &str {
+                "description() is deprecated; use Display"
+            }
+        }
+    }
+
+    pub mod sync {
+        pub use alloc::sync::Arc;
+        pub use core::sync::atomic;
+        pub use spin::Mutex;
+    }
+}
+
+/// The items of the std prelude which are
+/// only available with std or from `alloc`.
+#[cfg(not(feature = "std"))]
+mod prelude {
+    pub use std::borrow::ToOwned;
+    pub use std::boxed::Box;
+    pub use std::string::{String, ToString};
+    pub use std::vec::Vec;
+}
+
+/// `smallvec` needs nightly without std,
+/// so plain vectors are used instead.
+#[cfg(not(feature = "std"))]
+mod smallvec {
+    use prelude::*;
+
+    pub trait Array {
+        type Item;
+    }
+
+    impl<T, const N: usize> Array for [T; N] {
+        type Item = T;
+    }
+
+    pub type SmallVec<A> = Vec<<A as Array>::Item>;
+}
+
+/// The map used for lookups; there are
+/// no hash maps without std.
+#[cfg(feature = "std")]
+type Map<K, V> = fnv::FnvHashMap<K, V>;
+#[cfg(not(feature = "std"))]
+type Map<K, V> = std::collections::BTreeMap<K, V>;
 
 mod cell;
+
+mod cancel;
 mod dispatch;
+mod event;
+mod lazy;
+mod meta;
+mod par;
+#[cfg(feature = "profiling")]
+mod profiling;
 mod res;
+#[cfg(feature = "serialize")]
+mod serialize;
+#[cfg(any(not(feature = "std"), test))]
+mod spin;
 mod system;
 
-#[cfg(not(target_os = "emscripten"))]
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
 pub use dispatch::AsyncDispatcher;
-pub use dispatch::{Dispatcher, DispatcherBuilder};
-pub use res::{Fetch, FetchId, FetchIdMut, FetchMut, Resource, ResourceId, Resources};
-pub use system::{RunNow, RunningTime, System, SystemData};
+#[cfg(all(feature = "future", feature = "parallel", not(target_os = "emscripten")))]
+pub use dispatch::Finished;
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+pub use dispatch::register_worker;
+#[cfg(feature = "config")]
+pub use dispatch::{ConfigEntry, DispatcherConfig, SystemConfig, SystemRegistry};
+pub use dispatch::{Affinity, BatchExecutor, BuildError, DefaultScheduler, DeterministicScheduler,
+                   DispatchError, Dispatcher, DispatcherBuilder, Layout, NewSystem, Nil, Par,
+                   ParSeq, Placement, RunWithPool, ScheduleDiff, Scheduler, Seq, SystemId,
+                   Systems};
+pub use cancel::CancellationToken;
+#[cfg(feature = "parking")]
+pub use cell::BlockingError;
+#[cfg(not(any(feature = "std", test)))]
+pub use std::error::Error;
+pub use event::{EventChannel, EventIter, ReaderId};
+pub use lazy::LazyUpdate;
+pub use meta::{CastFrom, MetaFetch, MetaFetchMut, MetaIter, MetaIterMut, MetaTable};
+pub use par::ParallelContext;
+#[cfg(feature = "profiling")]
+pub use profiling::{SystemSample, SystemStats, hold_threshold, set_hold_handler,
+                    set_hold_threshold};
+#[cfg(feature = "parking")]
+pub use res::BackoffPolicy;
+#[cfg(feature = "std")]
+pub use res::{FetchLocal, FetchLocalMut, NonSendFetch, NonSendFetchMut, Resolution};
+pub use res::{Changed, ConflictPolicy, DenseStorage, DynamicId, Entry, Fetch, FetchId,
+              FetchIdMut, FetchManyError, FetchMut, FetchProblem, FlushableResource, MappedFetch,
+              MappedFetchMut, NamedId, OwnedFetch, OwnedFetchMut, Read, ReadDefault, RenameError,
+              Resource, ResourceCell, ResourceId, ResourceIndex, ResourceObserver,
+              ResourceStorage, Resources, ResourcesView, Snapshot, TryFetch, Version,
+              WriteDefault};
+pub use system::{Accessor, DynamicAccessor, DynamicData, DynamicSystem, DynamicSystemData,
+                 FallibleSystem, RunNow, RunningTime, System, SystemData};
@@ -0,0 +1,417 @@
+//! Trait object access to resources
+//! of different types.
+
+use std::ops::{Deref, DerefMut};
+use std::slice::Iter;
+
+#[cfg(not(feature = "std"))]
+use prelude::*;
+use res::{FetchId, FetchIdMut, Resource, ResourceId, ResourceStorage, Resources};
+
+/// Casts a resource of type `T` to the trait object
+/// implementing this trait, e.g. `Inspect` for the trait
+/// `Inspect`. This is required for registering
+/// resources in a [`MetaTable`].
+///
+/// The implementation is usually trivial:
+///
+/// ```
+/// # use shred::CastFrom;
+/// trait Inspect {
+///     fn inspect(&self) -> String;
+/// }
+///
+/// impl<T> CastFrom<T> for Inspect
+///     where T: Inspect + 'static
+/// {
+///     fn cast(t: &T) -> &Self {
+///         t
+///     }
+///
+///     fn cast_mut(t: &mut T) -> &mut Self {
+///         t
+///     }
+/// }
+/// ```
+///
+/// [`MetaTable`]: struct.MetaTable.html
+pub trait CastFrom<T> {
+    /// Casts an immutable reference.
+    fn cast(t: &T) -> &Self;
+
+    /// Casts a mutable reference.
+    fn cast_mut(t: &mut T) -> &mut Self;
+}
+
+struct Casts<T: ?Sized> {
+    cast: fn(&Resource) -> &T,
+    cast_mut: fn(&mut Resource) -> &mut T,
+}
+
+fn cast<R, T>(res: &Resource) -> &T
+    where R: Resource,
+          T: CastFrom<R> + ?Sized
+{
+    T::cast(res.downcast_ref::<R>().expect("Resource registered with wrong type"))
+}
+
+fn cast_mut<R, T>(res: &mut Resource) -> &mut T
+    where R: Resource,
+          T: CastFrom<R> + ?Sized
+{
+    T::cast_mut(res.downcast_mut::<R>().expect("Resource registered with wrong type"))
+}
+
+/// A table of resources implementing the trait `T`,
+/// allowing to access them as trait objects without
+/// knowing their concrete types (e.g. for a generic
+/// save or inspection system).
+///
+/// Resources have to be registered with their type,
+/// which requires `T: CastFrom<R>`.
+///
+/// ## Examples
+///
+/// ```
+/// # use shred::{CastFrom, MetaTable, Resources};
+/// trait Inspect {
+///     fn inspect(&self) -> String;
+/// }
+///
+/// impl<T> CastFrom<T> for Inspect
+///     where T: Inspect + 'static
+/// {
+///     fn cast(t: &T) -> &Self {
+///         t
+///     }
+///
+///     fn cast_mut(t: &mut T) -> &mut Self {
+///         t
+///     }
+/// }
+///
+/// struct Time(f32);
+///
+/// impl Inspect for Time {
+///     fn inspect(&self) -> String {
+///         format!("Time: {}", self.0)
+///     }
+/// }
+///
+/// let mut table = MetaTable::<Inspect>::new();
+/// table.register::<Time>(0);
+///
+/// let mut res = Resources::new();
+/// res.add(Time(0.5));
+///
+/// for (_, inspect) in table.iter(&res) {
+///     assert_eq!(inspect.inspect(), "Time: 0.5");
+/// }
+/// ```
+pub struct MetaTable<T: ?Sized> {
+    casts: Vec<Casts<T>>,
+    ids: Vec<ResourceId>,
+}
+
+impl<T: ?Sized> MetaTable<T> {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        MetaTable {
+            casts: Vec::new(),
+            ids: Vec::new(),
+        }
+    }
+
+    /// Registers the resource of type `R` with the given id.
+    ///
+    /// Registering the same resource again does nothing.
+    pub fn register<R>(&mut self, id: usize)
+        where R: Resource,
+              T: CastFrom<R>
+    {
+        let res_id = ResourceId::new_with_id::<R>(id);
+
+        if self.ids.contains(&res_id) {
+            return;
+        }
+
+        self.ids.push(res_id);
+        self.casts
+            .push(Casts {
+                      cast: cast::<R, T>,
+                      cast_mut: cast_mut::<R, T>,
+                  });
+    }
+
+    /// Returns the ids of all registered resources,
+    /// in the order they were registered.
+    pub fn ids(&self) -> &[ResourceId] {
+        &self.ids
+    }
+
+    /// Fetches the registered resource `id` as trait object,
+    /// returning `None` if it isn't registered or
+    /// doesn't exist in `res`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is being accessed mutably.
+    pub fn get<'a, S>(&'a self, res: &'a Resources<S>, id: ResourceId) -> Option<MetaFetch<'a, T>>
+        where S: ResourceStorage
+    {
+        self.ids
+            .iter()
+            .position(|x| *x == id)
+            .and_then(|index| fetch(res, id, &self.casts[index]))
+    }
+
+    /// Like `get`, but fetches the resource mutably.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is being accessed already.
+    pub fn get_mut<'a, S>(&'a self,
+                          res: &'a Resources<S>,
+                          id: ResourceId)
+                          -> Option<MetaFetchMut<'a, T>>
+        where S: ResourceStorage
+    {
+        self.ids
+            .iter()
+            .position(|x| *x == id)
+            .and_then(|index| fetch_mut(res, id, &self.casts[index]))
+    }
+
+    /// Iterates over all registered resources existing in `res`,
+    /// yielding their ids and the trait objects.
+    ///
+    /// Each resource is fetched once the iterator reaches it,
+    /// so this panics if one of them is being accessed mutably.
+    pub fn iter<'a, S>(&'a self, res: &'a Resources<S>) -> MetaIter<'a, T, S>
+        where S: ResourceStorage
+    {
+        MetaIter {
+            casts: self.casts.iter(),
+            ids: self.ids.iter(),
+            res: res,
+        }
+    }
+
+    /// Like `iter`, but fetches the resources mutably.
+    pub fn iter_mut<'a, S>(&'a self, res: &'a Resources<S>) -> MetaIterMut<'a, T, S>
+        where S: ResourceStorage
+    {
+        MetaIterMut {
+            casts: self.casts.iter(),
+            ids: self.ids.iter(),
+            res: res,
+        }
+    }
+}
+
+impl<T: ?Sized> Default for MetaTable<T> {
+    fn default() -> Self {
+        MetaTable::new()
+    }
+}
+
+fn fetch<'a, T: ?Sized, S>(res: &'a Resources<S>,
+                           id: ResourceId,
+                           casts: &'a Casts<T>)
+                           -> Option<MetaFetch<'a, T>>
+    where S: ResourceStorage
+{
+    if res.has_value(id) {
+        Some(MetaFetch {
+                 cast: casts.cast,
+                 inner: res.fetch_id(id.0, id.1),
+             })
+    } else {
+        None
+    }
+}
+
+fn fetch_mut<'a, T: ?Sized, S>(res: &'a Resources<S>,
+                               id: ResourceId,
+                               casts: &'a Casts<T>)
+                               -> Option<MetaFetchMut<'a, T>>
+    where S: ResourceStorage
+{
+    if res.has_value(id) {
+        Some(MetaFetchMut {
+                 cast: casts.cast,
+                 cast_mut: casts.cast_mut,
+                 inner: res.fetch_id_mut(id.0, id.1),
+             })
+    } else {
+        None
+    }
+}
+
+/// A resource fetched as trait object `T`
+/// from a [`MetaTable`].
+///
+/// [`MetaTable`]: struct.MetaTable.html
+pub struct MetaFetch<'a, T: ?Sized + 'a> {
+    cast: fn(&Resource) -> &T,
+    inner: FetchId<'a>,
+}
+
+impl<'a, T: ?Sized> Deref for MetaFetch<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.cast)(&*self.inner)
+    }
+}
+
+/// A resource fetched mutably as trait
+/// object `T` from a [`MetaTable`].
+///
+/// [`MetaTable`]: struct.MetaTable.html
+pub struct MetaFetchMut<'a, T: ?Sized + 'a> {
+    cast: fn(&Resource) -> &T,
+    cast_mut: fn(&mut Resource) -> &mut T,
+    inner: FetchIdMut<'a>,
+}
+
+impl<'a, T: ?Sized> Deref for MetaFetchMut<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        (self.cast)(&*self.inner)
+    }
+}
+
+impl<'a, T: ?Sized> DerefMut for MetaFetchMut<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        (self.cast_mut)(&mut *self.inner)
+    }
+}
+
+/// Iterator returned by [`MetaTable::iter`].
+///
+/// [`MetaTable::iter`]: struct.MetaTable.html#method.iter
+pub struct MetaIter<'a, T: ?Sized + 'a, S>
+    where S: ResourceStorage + 'a
+{
+    casts: Iter<'a, Casts<T>>,
+    ids: Iter<'a, ResourceId>,
+    res: &'a Resources<S>,
+}
+
+impl<'a, T: ?Sized, S> Iterator for MetaIter<'a, T, S>
+    where S: ResourceStorage
+{
+    type Item = (ResourceId, MetaFetch<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let (Some(&id), Some(casts)) = (self.ids.next(), self.casts.next()) {
+            if let Some(fetched) = fetch(self.res, id, casts) {
+                return Some((id, fetched));
+            }
+        }
+
+        None
+    }
+}
+
+/// Iterator returned by [`MetaTable::iter_mut`].
+///
+/// [`MetaTable::iter_mut`]: struct.MetaTable.html#method.iter_mut
+pub struct MetaIterMut<'a, T: ?Sized + 'a, S>
+    where S: ResourceStorage + 'a
+{
+    casts: Iter<'a, Casts<T>>,
+    ids: Iter<'a, ResourceId>,
+    res: &'a Resources<S>,
+}
+
+impl<'a, T: ?Sized, S> Iterator for MetaIterMut<'a, T, S>
+    where S: ResourceStorage
+{
+    type Item = (ResourceId, MetaFetchMut<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let (Some(&id), Some(casts)) = (self.ids.next(), self.casts.next()) {
+            if let Some(fetched) = fetch_mut(self.res, id, casts) {
+                return Some((id, fetched));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    trait Counter {
+        fn count(&self) -> u32;
+
+        fn inc(&mut self);
+    }
+
+    impl<T> CastFrom<T> for Counter
+        where T: Counter + 'static
+    {
+        fn cast(t: &T) -> &Self {
+            t
+        }
+
+        fn cast_mut(t: &mut T) -> &mut Self {
+            t
+        }
+    }
+
+    struct ByOne(u32);
+
+    impl Counter for ByOne {
+        fn count(&self) -> u32 {
+            self.0
+        }
+
+        fn inc(&mut self) {
+            self.0 += 1;
+        }
+    }
+
+    struct ByTwo(u32);
+
+    impl Counter for ByTwo {
+        fn count(&self) -> u32 {
+            self.0
+        }
+
+        fn inc(&mut self) {
+            self.0 += 2;
+        }
+    }
+
+    #[test]
+    fn iter() {
+        let mut table = MetaTable::<Counter>::new();
+        table.register::<ByOne>(0);
+        table.register::<ByTwo>(0);
+        table.register::<ByTwo>(1);
+        table.register::<ByTwo>(0);
+
+        let mut res = Resources::new();
+        res.add(ByOne(0));
+        res.add(ByTwo(0));
+
+        for (_, mut counter) in table.iter_mut(&res) {
+            counter.inc();
+        }
+
+        let counts: Vec<_> = table.iter(&res).map(|(_, x)| x.count()).collect();
+        assert_eq!(counts, vec![1, 2]);
+        assert_eq!(table.ids().len(), 3);
+
+        let id = ResourceId::new::<ByTwo>();
+        assert_eq!(table.get(&res, id).unwrap().count(), 2);
+        assert!(table.get(&res, ResourceId::new_with_id::<ByTwo>(1)).is_none());
+        assert!(table.get_mut(&res, ResourceId::new::<u32>()).is_none());
+    }
+}
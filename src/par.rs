@@ -0,0 +1,205 @@
+//! Helpers for data parallelism inside
+//! systems, using the dispatcher's workers.
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use std::sync::Arc;
+
+#[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+use rayon::{Scope, ThreadPool};
+
+/// A handle to the thread pool of the dispatcher,
+/// added as a resource by `Dispatcher::setup`
+/// if there isn't one yet.
+///
+/// Systems which split their work (e.g. over chunks of a large
+/// `Vec`) should use this instead of creating a rayon pool of their
+/// own, which would spawn more threads than there are cores. The
+/// work is executed by the same workers as the systems, so a system
+/// waiting for its jobs helps executing them.
+///
+/// Without the `parallel` feature, everything runs
+/// on the current thread.
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{DispatcherBuilder, ParallelContext, ReadDefault, Resources, System, WriteDefault};
+/// #[derive(Default)]
+/// struct Positions(Vec<f32>);
+///
+/// struct Integrate;
+///
+/// impl<'a> System<'a> for Integrate {
+///     type SystemData = (ReadDefault<'a, ParallelContext>, WriteDefault<'a, Positions>);
+///
+///     fn run(&mut self, (par, mut positions): Self::SystemData) {
+///         let mid = positions.0.len() / 2;
+///         let (a, b) = positions.0.split_at_mut(mid);
+///
+///         par.join(|| for x in a { *x += 1.0 }, || for x in b { *x += 1.0 });
+///     }
+/// }
+///
+/// let mut res = Resources::new();
+/// res.add(Positions(vec![0.0; 100]));
+///
+/// let mut dispatcher = DispatcherBuilder::new()
+///     .add(Integrate, "integrate", &[])
+///     .build();
+/// dispatcher.setup(&mut res);
+/// dispatcher.dispatch(&mut res);
+///
+/// assert!(res.fetch::<Positions>(0).0.iter().all(|&x| x == 1.0));
+/// ```
+#[derive(Clone, Default)]
+pub struct ParallelContext {
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pool: Option<Arc<ThreadPool>>,
+}
+
+impl ParallelContext {
+    /// Creates a context executing jobs on `pool`.
+    ///
+    /// Only available with the `parallel` feature.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn new(pool: Arc<ThreadPool>) -> Self {
+        ParallelContext { pool: Some(pool) }
+    }
+
+    /// Creates a context running everything on the current thread.
+    pub fn sequential() -> Self {
+        Default::default()
+    }
+
+    /// Returns the number of threads jobs are distributed over,
+    /// which is a good hint for how many chunks to split work into.
+    pub fn num_threads(&self) -> usize {
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        {
+            if let Some(ref pool) = self.pool {
+                return pool.current_num_threads();
+            }
+        }
+
+        1
+    }
+
+    /// Executes `a` and `b`, potentially in parallel,
+    /// and returns both results.
+    ///
+    /// Panics of `a` or `b` are propagated.
+    pub fn join<A, B, RA, RB>(&self, a: A, b: B) -> (RA, RB)
+        where A: FnOnce() -> RA + Send,
+              B: FnOnce() -> RB + Send,
+              RA: Send,
+              RB: Send
+    {
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        {
+            if self.pool.is_some() {
+                return self.install(move || ::rayon::join(a, b));
+            }
+        }
+
+        (a(), b())
+    }
+
+    /// Creates a rayon scope on the pool, which jobs borrowing the
+    /// environment can be spawned on. Returns once all of them
+    /// have finished.
+    ///
+    /// Only available with the `parallel` feature.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    pub fn scope<'s, F, R>(&self, f: F) -> R
+        where F: for<'a> FnOnce(&'a Scope<'s>) -> R + Send + 's,
+              R: Send
+    {
+        self.install(move || ::rayon::scope(f))
+    }
+
+    /// Runs `f` on the pool, or directly if the current thread
+    /// is one of its workers (e.g. while dispatching), because
+    /// `ThreadPool::install` would block the worker.
+    #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+    fn install<F, R>(&self, f: F) -> R
+        where F: FnOnce() -> R + Send,
+              R: Send
+    {
+        match self.pool {
+            Some(ref pool) if pool.current_thread_index().is_none() => pool.install(f),
+            _ => f(),
+        }
+    }
+
+    /// Calls `f` for every chunk of `items` with at most `chunk_size`
+    /// elements, distributing the chunks over the threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is zero.
+    pub fn for_each_chunk<T, F>(&self, items: &mut [T], chunk_size: usize, f: F)
+        where T: Send,
+              F: Fn(&mut [T]) + Sync
+    {
+        assert!(chunk_size > 0, "Chunk size must not be zero");
+
+        self.chunks(items, chunk_size, &f);
+    }
+
+    fn chunks<T, F>(&self, items: &mut [T], chunk_size: usize, f: &F)
+        where T: Send,
+              F: Fn(&mut [T]) + Sync
+    {
+        if items.len() <= chunk_size || self.num_threads() < 2 {
+            for chunk in items.chunks_mut(chunk_size) {
+                f(chunk);
+            }
+
+            return;
+        }
+
+        // Split at a multiple of the chunk size,
+        // so the chunks are the same as sequentially.
+        let mid = (items.len() / chunk_size + 1) / 2 * chunk_size;
+        let (a, b) = items.split_at_mut(mid);
+
+        self.join(|| self.chunks(a, chunk_size, f),
+                  || self.chunks(b, chunk_size, f));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn for_each_chunk() {
+        let check = |par: ParallelContext| {
+            let mut items: Vec<usize> = (0..103).collect();
+            par.for_each_chunk(&mut items, 10, |chunk| {
+                assert!(chunk.len() == 10 || chunk.len() == 3);
+                assert_eq!(chunk[0] % 10, 0);
+
+                for x in chunk {
+                    *x *= 2;
+                }
+            });
+
+            assert!(items.iter().enumerate().all(|(i, &x)| x == i * 2));
+        };
+
+        check(ParallelContext::sequential());
+
+        #[cfg(all(feature = "parallel", not(target_os = "emscripten")))]
+        {
+            use rayon::Configuration;
+
+            let pool = ThreadPool::new(Configuration::new().num_threads(3)).unwrap();
+            let par = ParallelContext::new(Arc::new(pool));
+            assert_eq!(par.num_threads(), 3);
+            assert_eq!(par.join(|| 1, || 2), (1, 2));
+
+            check(par);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Profiling helpers, only available
+//! with the `profiling` feature.
+
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+use res::ResourceId;
+
+/// The hold threshold in microseconds.
+static HOLD_THRESHOLD: AtomicUsize = AtomicUsize::new(1_000);
+
+/// A function called for borrows held too long.
+type HoldHandler = fn(ResourceId, Duration);
+
+/// The function set with `set_hold_handler`.
+static HOLD_HANDLER: Mutex<Option<HoldHandler>> = Mutex::new(None);
+
+/// Sets the duration after which holding a borrow
+/// of a resource is considered too long.
+///
+/// Once a `Fetch`, `FetchMut`, `FetchId` or `FetchIdMut` which has
+/// been held for longer than this is dropped, the handler set
+/// with `set_hold_handler` is called with the id of the resource
+/// and the duration. With the `tracing` feature, a warning event
+/// is emitted as well. Long-held borrows (e.g. held across an
+/// expensive computation) serialize the schedule, so this helps
+/// finding them.
+///
+/// The threshold is global and defaults to one millisecond.
+/// Thresholds which don't fit into a `usize` of microseconds
+/// (about 71 minutes on 32 bit targets) are saturated.
+pub fn set_hold_threshold(threshold: Duration) {
+    let micros = threshold
+        .as_secs()
+        .saturating_mul(1_000_000)
+        .saturating_add(threshold.subsec_nanos() as u64 / 1_000);
+
+    HOLD_THRESHOLD.store(micros.min(usize::MAX as u64) as usize, Ordering::Relaxed);
+}
+
+/// Sets the function called for borrows held longer than
+/// the threshold (see `set_hold_threshold`), or removes it.
+///
+/// The handler is global; there is none by default.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use shred::{ResourceId, set_hold_handler};
+///
+/// fn report(id: ResourceId, held: Duration) {
+///     println!("{:?} was borrowed for {:?}", id, held);
+/// }
+///
+/// set_hold_handler(Some(report));
+/// ```
+pub fn set_hold_handler(handler: Option<fn(ResourceId, Duration)>) {
+    *HOLD_HANDLER.lock().expect("Mutex poisoned") = handler;
+}
+
+/// Returns the threshold set with `set_hold_threshold`.
+pub fn hold_threshold() -> Duration {
+    let micros = HOLD_THRESHOLD.load(Ordering::Relaxed);
+
+    Duration::new((micros / 1_000_000) as u64,
+                  (micros % 1_000_000) as u32 * 1_000)
+}
+
+/// Records when a resource has been borrowed
+/// and checks the hold duration once dropped.
+pub struct HoldTimer {
+    id: ResourceId,
+    start: Instant,
+}
+
+impl HoldTimer {
+    pub fn new(id: ResourceId) -> Self {
+        HoldTimer {
+            id: id,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for HoldTimer {
+    fn drop(&mut self) {
+        let held = self.start.elapsed();
+        let threshold = hold_threshold();
+
+        if held > threshold {
+            report_hold(self.id, held, threshold);
+        }
+    }
+}
+
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn report_hold(id: ResourceId, held: Duration, threshold: Duration) {
+    #[cfg(feature = "tracing")]
+    ::tracing::warn!(resource = ?id, ?held, ?threshold, "resource borrowed too long");
+
+    let handler = *HOLD_HANDLER.lock().expect("Mutex poisoned");
+
+    if let Some(handler) = handler {
+        handler(id, held);
+    }
+}
+
+/// A single execution of a system during a dispatch.
+#[derive(Clone, Debug)]
+pub struct SystemSample {
+    /// The name the system was added with.
+    pub name: String,
+    /// The index of the stage the system is part of.
+    pub stage: usize,
+    /// The worker thread which executed the system.
+    pub thread: ThreadId,
+    /// The wall-clock time `run_now` took.
+    pub duration: Duration,
+}
+
+/// A resource collecting a `SystemSample` for every system
+/// executed by a dispatcher, keeping the last few dispatches
+/// (frames).
+///
+/// Profiling is opt-in: add this resource and the dispatcher
+/// (`dispatch`, `dispatch_par`, `dispatch_seq` and the
+/// `AsyncDispatcher`) will push a frame after each dispatch.
+/// Thread local systems are not recorded, and systems of a
+/// batch are only recorded as the batch as a whole.
+///
+/// ## Examples
+///
+/// ```
+/// # extern crate shred;
+/// # use shred::{DispatcherBuilder, Fetch, Resources, System, SystemStats};
+/// # struct Sys;
+/// # impl<'a> System<'a> for Sys {
+/// #     type SystemData = ();
+/// #     fn run(&mut self, _: ()) {}
+/// # }
+/// # fn main() {
+/// let mut res = Resources::new();
+/// res.add(SystemStats::new(60));
+///
+/// let mut dispatcher = DispatcherBuilder::new().add(Sys, "sys", &[]).build();
+/// dispatcher.dispatch(&mut res);
+///
+/// let stats = res.fetch::<SystemStats>(0);
+/// let frame = stats.last_frame().unwrap();
+/// assert_eq!(frame[0].name, "sys");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SystemStats {
+    capacity: usize,
+    frames: VecDeque<Vec<SystemSample>>,
+}
+
+impl SystemStats {
+    /// Creates a new `SystemStats`, keeping
+    /// the last `frames` frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `frames` is zero.
+    pub fn new(frames: usize) -> Self {
+        assert!(frames > 0, "SystemStats needs to keep at least one frame");
+
+        SystemStats {
+            capacity: frames,
+            frames: VecDeque::with_capacity(frames),
+        }
+    }
+
+    /// Returns the maximum number of frames kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Iterates over the kept frames, from the oldest to the newest.
+    ///
+    /// The samples of a frame are ordered by the time
+    /// the systems finished.
+    pub fn frames(&self) -> Iter<Vec<SystemSample>> {
+        self.frames.iter()
+    }
+
+    /// Returns the samples of the latest dispatch.
+    pub fn last_frame(&self) -> Option<&[SystemSample]> {
+        self.frames.back().map(|x| x.as_slice())
+    }
+
+    /// Pushes a frame, dropping the oldest one
+    /// if `capacity` frames are kept already.
+    pub fn push(&mut self, frame: Vec<SystemSample>) {
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The threshold and the handler are global, so they're tested together
+    #[test]
+    fn threshold() {
+        use std::{u64, usize};
+
+        struct Held;
+
+        static HELD: AtomicUsize = AtomicUsize::new(0);
+
+        fn count(id: ResourceId, held: Duration) {
+            if id == ResourceId::new::<Held>() {
+                assert!(held >= Duration::new(10, 0));
+                HELD.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        set_hold_threshold(Duration::new(u64::MAX, 0));
+        assert_eq!(hold_threshold().as_secs(), usize::MAX as u64 / 1_000_000);
+
+        set_hold_threshold(Duration::new(2, 500_000_000));
+        assert_eq!(hold_threshold(), Duration::new(2, 500_000_000));
+
+        set_hold_threshold(Duration::new(0, 1_000_000));
+        assert_eq!(hold_threshold(), Duration::new(0, 1_000_000));
+
+        set_hold_handler(Some(count));
+        drop(HoldTimer::new(ResourceId::new::<Held>()));
+        if let Some(start) = Instant::now().checked_sub(Duration::new(10, 0)) {
+            drop(HoldTimer {
+                     id: ResourceId::new::<Held>(),
+                     start: start,
+                 });
+            assert_eq!(HELD.load(Ordering::Relaxed), 1);
+        }
+        set_hold_handler(None);
+    }
+
+    #[test]
+    fn stats_ring_buffer() {
+        use std::thread;
+
+        let sample = |stage| {
+            SystemSample {
+                name: "sys".to_owned(),
+                stage: stage,
+                thread: thread::current().id(),
+                duration: Duration::new(0, 0),
+            }
+        };
+
+        let mut stats = SystemStats::new(2);
+        assert!(stats.last_frame().is_none());
+
+        stats.push(vec![sample(0)]);
+        stats.push(vec![sample(1)]);
+        stats.push(vec![sample(2)]);
+
+        let stages: Vec<_> = stats.frames().map(|frame| frame[0].stage).collect();
+        assert_eq!(stages, vec![1, 2]);
+        assert_eq!(stats.last_frame().unwrap()[0].stage, 2);
+    }
+}
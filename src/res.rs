@@ -1,14 +1,38 @@
 //! Module for resource related types
 
-use std::any::TypeId;
+#[cfg(feature = "std")]
+use std::any::Any as StdAny;
+use std::any::{TypeId, type_name};
+#[cfg(feature = "std")]
+use std::cell::{Cell, RefCell};
+use std::cmp::max;
+use std::error::Error;
+use std::fmt::{Display, Error as FormatError, Formatter};
 use std::marker::PhantomData;
+use std::mem::take;
 use std::ops::{Deref, DerefMut};
+#[cfg(feature = "std")]
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "std")]
+use std::thread::{self, ThreadId};
+#[cfg(feature = "parking")]
+use std::time::Duration;
 
-use fnv::FnvHashMap;
 use mopa::Any;
 
+#[cfg(feature = "parking")]
+use cell::BlockingError;
 use cell::{Ref, RefMut, TrustCell};
+#[cfg(not(feature = "std"))]
+use prelude::*;
+#[cfg(feature = "profiling")]
+use profiling::HoldTimer;
+#[cfg(feature = "serialize")]
+use serialize::{ResourcesVisitor, Serializable};
 use system::SystemData;
+use Map;
 
 /// Return value of [`Resources::fetch`].
 ///
@@ -16,6 +40,8 @@ use system::SystemData;
 pub struct Fetch<'a, T: 'a> {
     inner: Ref<'a, Box<Resource>>,
     phantom: PhantomData<&'a T>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
 }
 
 impl<'a, T> Deref for Fetch<'a, T>
@@ -28,6 +54,88 @@ impl<'a, T> Deref for Fetch<'a, T>
     }
 }
 
+impl<'a, T> Fetch<'a, T>
+    where T: Resource
+{
+    #[allow(unused_variables)]
+    fn new(inner: Ref<'a, Box<Resource>>, id: ResourceId) -> Self {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(resource = ?id, "borrow");
+
+        Fetch {
+            inner: inner,
+            phantom: PhantomData,
+            #[cfg(feature = "profiling")]
+            _timer: HoldTimer::new(id),
+        }
+    }
+
+    /// Creates a cheap, copyable read-only reference
+    /// to the resource, which can be passed by value into
+    /// helper functions without moving the guard.
+    ///
+    /// The returned `Read` can not outlive this guard:
+    ///
+    /// ```rust,compile_fail
+    /// # use shred::Resources;
+    /// let mut res = Resources::new();
+    /// res.add(5i32);
+    ///
+    /// let read = {
+    ///     let fetched = res.fetch::<i32>(0);
+    ///     fetched.reborrow()
+    /// };
+    /// ```
+    pub fn reborrow(&self) -> Read<T> {
+        Read { value: &**self }
+    }
+
+    /// Narrows the fetched resource to a part of it,
+    /// e.g. a field or a sub-slice, keeping it borrowed.
+    ///
+    /// This is an associated function, because
+    /// `T` could have a method with the same name.
+    ///
+    /// ```rust
+    /// # use shred::{Fetch, Resources};
+    /// struct Level {
+    ///     name: String,
+    /// }
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(Level { name: "intro".to_owned() });
+    ///
+    /// let name = Fetch::map(res.fetch::<Level>(0), |level| level.name.as_str());
+    /// assert_eq!(&*name, "intro");
+    /// ```
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedFetch<'a, U>
+        where F: FnOnce(&T) -> &U
+    {
+        MappedFetch {
+            inner: Ref::map(this.inner, |r| f(unsafe { r.downcast_ref_unchecked() })),
+            #[cfg(feature = "profiling")]
+            _timer: this._timer,
+        }
+    }
+}
+
+/// A part of a resource, returned by [`Fetch::map`].
+///
+/// [`Fetch::map`]: struct.Fetch.html#method.map
+pub struct MappedFetch<'a, U: ?Sized + 'a> {
+    inner: Ref<'a, U>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
+}
+
+impl<'a, U: ?Sized> Deref for MappedFetch<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        &self.inner
+    }
+}
+
 impl<'a, T> SystemData<'a> for Fetch<'a, T>
     where T: Resource
 {
@@ -44,11 +152,242 @@ impl<'a, T> SystemData<'a> for Fetch<'a, T>
     }
 }
 
+impl<'a, T> SystemData<'a> for Option<Fetch<'a, T>>
+    where T: Resource
+{
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        if res.has_value(ResourceId::new_with_id::<T>(id)) {
+            Some(res.fetch(id))
+        } else {
+            None
+        }
+    }
+
+    fn reads(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+
+    fn writes(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+}
+
+/// A copyable read-only reference to a resource,
+/// created with [`Fetch::reborrow`].
+///
+/// This only borrows from a guard; [`ReadDefault`] is the system
+/// data which fetches (and sets up) the resource itself.
+///
+/// [`Fetch::reborrow`]: struct.Fetch.html#method.reborrow
+/// [`ReadDefault`]: struct.ReadDefault.html
+pub struct Read<'a, T: 'a> {
+    value: &'a T,
+}
+
+impl<'a, T> Clone for Read<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for Read<'a, T> {}
+
+impl<'a, T> Deref for Read<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+/// Read access to a resource, which is added with its
+/// `Default` value during setup if it is missing.
+///
+/// Apart from that, this behaves exactly like `Fetch`.
+pub struct ReadDefault<'a, T: 'a> {
+    inner: Fetch<'a, T>,
+}
+
+impl<'a, T> Deref for ReadDefault<'a, T>
+    where T: Resource
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T> SystemData<'a> for ReadDefault<'a, T>
+    where T: Default + Resource
+{
+    fn setup(res: &mut Resources, id: usize) {
+        res.entry::<T>(id).or_insert_with(T::default);
+    }
+
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        ReadDefault { inner: res.fetch(id) }
+    }
+
+    fn reads(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+
+    fn writes(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+}
+
+/// Write access to a resource, which is added with its
+/// `Default` value during setup if it is missing.
+///
+/// Apart from that, this behaves exactly like `FetchMut`.
+pub struct WriteDefault<'a, T: 'a> {
+    inner: FetchMut<'a, T>,
+}
+
+impl<'a, T> Deref for WriteDefault<'a, T>
+    where T: Resource
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T> DerefMut for WriteDefault<'a, T>
+    where T: Resource
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<'a, T> SystemData<'a> for WriteDefault<'a, T>
+    where T: Default + Resource
+{
+    fn setup(res: &mut Resources, id: usize) {
+        res.entry::<T>(id).or_insert_with(T::default);
+    }
+
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        WriteDefault { inner: res.fetch_mut(id) }
+    }
+
+    fn reads(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+
+    fn writes(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+}
+
+/// Read access to a resource, which additionally tells
+/// whether the resource was modified since a system
+/// last checked it (see `Resources::version` for what
+/// counts as modification).
+///
+/// The system keeps the version it saw last, which
+/// is `None` before its first run:
+///
+/// ```rust
+/// # use shred::{Changed, System, Version};
+/// struct Sum(Vec<u32>);
+///
+/// struct Recompute {
+///     last: Option<Version>,
+///     total: u32,
+/// }
+///
+/// impl<'a> System<'a> for Recompute {
+///     type SystemData = Changed<'a, Sum>;
+///
+///     fn run(&mut self, sum: Self::SystemData) {
+///         if sum.changed(&mut self.last) {
+///             self.total = sum.0.iter().sum();
+///         }
+///     }
+/// }
+/// ```
+pub struct Changed<'a, T: 'a> {
+    inner: Fetch<'a, T>,
+    version: Version,
+}
+
+impl<'a, T> Changed<'a, T>
+    where T: Resource
+{
+    /// Returns true if the resource was modified since
+    /// `last`, or `last` is `None`, and sets `last` to
+    /// the current version of the resource.
+    pub fn changed(&self, last: &mut Option<Version>) -> bool {
+        let changed = last.map_or(true, |last| self.version > last);
+        *last = Some(self.version);
+
+        changed
+    }
+
+    /// Returns the current version of the resource.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+}
+
+impl<'a, T> Deref for Changed<'a, T>
+    where T: Resource
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T> SystemData<'a> for Changed<'a, T>
+    where T: Resource
+{
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        let version = res.version(ResourceId::new_with_id::<T>(id))
+            .expect("No resource with the given id");
+
+        Changed {
+            inner: res.fetch(id),
+            version: version,
+        }
+    }
+
+    fn reads(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+
+    fn writes(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+}
+
 /// Return value of [`Resources::fetch_id`].
 ///
 /// [`Resources::fetch_id`]: struct.Resources.html#method.fetch_id
 pub struct FetchId<'a> {
     inner: Ref<'a, Box<Resource>>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
+}
+
+impl<'a> FetchId<'a> {
+    #[allow(unused_variables)]
+    fn new(inner: Ref<'a, Box<Resource>>, id: ResourceId) -> Self {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(resource = ?id, "borrow");
+
+        FetchId {
+            inner: inner,
+            #[cfg(feature = "profiling")]
+            _timer: HoldTimer::new(id),
+        }
+    }
 }
 
 impl<'a> Deref for FetchId<'a> {
@@ -64,6 +403,22 @@ impl<'a> Deref for FetchId<'a> {
 /// [`Resources::fetch_id_mut`]: struct.Resources.html#method.fetch_id_mut
 pub struct FetchIdMut<'a> {
     inner: RefMut<'a, Box<Resource>>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
+}
+
+impl<'a> FetchIdMut<'a> {
+    #[allow(unused_variables)]
+    fn new(inner: RefMut<'a, Box<Resource>>, id: ResourceId) -> Self {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(resource = ?id, "borrow_mut");
+
+        FetchIdMut {
+            inner: inner,
+            #[cfg(feature = "profiling")]
+            _timer: HoldTimer::new(id),
+        }
+    }
 }
 
 impl<'a> Deref for FetchIdMut<'a> {
@@ -80,37 +435,104 @@ impl<'a> DerefMut for FetchIdMut<'a> {
     }
 }
 
-/// Return value of [`Resources::fetch_mut`].
+/// Return value of [`Resources::fetch_thread_local`].
 ///
-/// [`Resources::fetch_mut`]: struct.Resources.html#method.fetch_mut
-pub struct FetchMut<'a, T: 'a> {
-    inner: RefMut<'a, Box<Resource>>,
+/// Because thread-local resources may only be accessed
+/// from the thread they were added on, this can only be
+/// used as system data of thread-local systems
+/// (see [`DispatcherBuilder::add_thread_local`]).
+/// Adding such a system with `add` fails to compile:
+///
+/// ```rust,compile_fail
+/// # use std::rc::Rc;
+/// # use shred::{DispatcherBuilder, FetchLocal, System};
+/// struct Handle(Rc<u32>);
+///
+/// struct UseHandle;
+///
+/// impl<'a> System<'a> for UseHandle {
+///     type SystemData = FetchLocal<'a, Handle>;
+///
+///     fn run(&mut self, _: Self::SystemData) {}
+/// }
+///
+/// DispatcherBuilder::new().add(UseHandle, "use_handle", &[]);
+/// ```
+///
+/// [`Resources::fetch_thread_local`]: struct.Resources.html#method.fetch_thread_local
+/// [`DispatcherBuilder::add_thread_local`]: struct.DispatcherBuilder.html#method.add_thread_local
+#[cfg(feature = "std")]
+pub struct FetchLocal<'a, T: 'a> {
+    inner: Ref<'a, Box<StdAny>>,
+    phantom: PhantomData<&'a T>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> Deref for FetchLocal<'a, T>
+    where T: StdAny
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.inner.downcast_ref().expect(ERR_LOCAL_TYPE)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, T> SystemData<'a> for FetchLocal<'a, T>
+    where T: StdAny
+{
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        res.fetch_thread_local(id)
+    }
+
+    fn reads(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId(TypeId::of::<T>(), id)]
+    }
+
+    fn writes(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+}
+
+/// Return value of [`Resources::fetch_thread_local_mut`].
+///
+/// Please see [`FetchLocal`] for restrictions.
+///
+/// [`Resources::fetch_thread_local_mut`]: struct.Resources.html#method.fetch_thread_local_mut
+/// [`FetchLocal`]: struct.FetchLocal.html
+#[cfg(feature = "std")]
+pub struct FetchLocalMut<'a, T: 'a> {
+    inner: RefMut<'a, Box<StdAny>>,
     phantom: PhantomData<&'a mut T>,
 }
 
-impl<'a, T> Deref for FetchMut<'a, T>
-    where T: Resource
+#[cfg(feature = "std")]
+impl<'a, T> Deref for FetchLocalMut<'a, T>
+    where T: StdAny
 {
     type Target = T;
 
     fn deref(&self) -> &T {
-        unsafe { self.inner.downcast_ref_unchecked() }
+        self.inner.downcast_ref().expect(ERR_LOCAL_TYPE)
     }
 }
 
-impl<'a, T> DerefMut for FetchMut<'a, T>
-    where T: Resource
+#[cfg(feature = "std")]
+impl<'a, T> DerefMut for FetchLocalMut<'a, T>
+    where T: StdAny
 {
     fn deref_mut(&mut self) -> &mut T {
-        unsafe { self.inner.downcast_mut_unchecked() }
+        self.inner.downcast_mut().expect(ERR_LOCAL_TYPE)
     }
 }
 
-impl<'a, T> SystemData<'a> for FetchMut<'a, T>
-    where T: Resource
+#[cfg(feature = "std")]
+impl<'a, T> SystemData<'a> for FetchLocalMut<'a, T>
+    where T: StdAny
 {
     fn fetch(res: &'a Resources, id: usize) -> Self {
-        res.fetch_mut(id)
+        res.fetch_thread_local_mut(id)
     }
 
     fn reads(_: usize) -> Vec<ResourceId> {
@@ -118,251 +540,4202 @@ impl<'a, T> SystemData<'a> for FetchMut<'a, T>
     }
 
     fn writes(id: usize) -> Vec<ResourceId> {
-        vec![ResourceId::new_with_id::<T>(id)]
+        vec![ResourceId(TypeId::of::<T>(), id)]
     }
 }
 
-/// A resource defines a set of data
-/// which can only be accessed according
-/// to Rust's typical borrowing model (one writer xor multiple readers).
-pub trait Resource: Any + Send + Sync {}
+/// System data for a `!Send` resource added
+/// with [`Resources::add_non_send`].
+///
+/// This is the same as [`FetchLocal`].
+///
+/// [`Resources::add_non_send`]: struct.Resources.html#method.add_non_send
+/// [`FetchLocal`]: struct.FetchLocal.html
+#[cfg(feature = "std")]
+pub type NonSendFetch<'a, T> = FetchLocal<'a, T>;
 
-mopafy!(Resource);
+/// Mutable system data for a `!Send` resource added
+/// with [`Resources::add_non_send`].
+///
+/// This is the same as [`FetchLocalMut`].
+///
+/// [`Resources::add_non_send`]: struct.Resources.html#method.add_non_send
+/// [`FetchLocalMut`]: struct.FetchLocalMut.html
+#[cfg(feature = "std")]
+pub type NonSendFetchMut<'a, T> = FetchLocalMut<'a, T>;
 
-impl<T> Resource for T where T: Any + Send + Sync {}
+#[cfg(feature = "std")]
+const ERR_LOCAL_TYPE: &str = "Thread-local resource stored with the wrong type id";
 
-/// The id of a [`Resource`],
-/// which is a tuple struct with a type
-/// id and an additional resource id (represented with a `usize`).
-///
-/// The default resource id is `0`.
+/// Return value of [`Resources::fetch_mut`].
 ///
-/// [`Resource`]: trait.Resource.html
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
-pub struct ResourceId(pub TypeId, pub usize);
+/// [`Resources::fetch_mut`]: struct.Resources.html#method.fetch_mut
+pub struct FetchMut<'a, T: 'a> {
+    inner: RefMut<'a, Box<Resource>>,
+    phantom: PhantomData<&'a mut T>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
+}
 
-impl ResourceId {
-    /// Creates a new resource id from
-    /// a given type with the default
-    /// extra id.
-    pub fn new<T: Resource>() -> Self {
-        Self::new_with_id::<T>(0)
-    }
+impl<'a, T> FetchMut<'a, T>
+    where T: Resource
+{
+    #[allow(unused_variables)]
+    fn new(inner: RefMut<'a, Box<Resource>>, id: ResourceId) -> Self {
+        #[cfg(feature = "tracing")]
+        ::tracing::trace!(resource = ?id, "borrow_mut");
 
-    /// Creates a new resource id from
-    /// a given type and an additional id.
-    pub fn new_with_id<T: Resource>(id: usize) -> Self {
-        ResourceId(TypeId::of::<T>(), id)
+        FetchMut {
+            inner: inner,
+            phantom: PhantomData,
+            #[cfg(feature = "profiling")]
+            _timer: HoldTimer::new(id),
+        }
     }
 }
 
-/// A resource container, which
-/// provides methods to access to
-/// the contained resources.
+impl<'a, T> FetchMut<'a, T>
+    where T: Resource
+{
+    /// Turns this into a shared fetch of the resource,
+    /// e.g. after modifying it, so it can be fetched
+    /// immutably again while this is still held.
+    ///
+    /// The resource isn't released in between.
+    pub fn downgrade(self) -> Fetch<'a, T> {
+        Fetch {
+            inner: self.inner.downgrade(),
+            phantom: PhantomData,
+            #[cfg(feature = "profiling")]
+            _timer: self._timer,
+        }
+    }
+
+    /// Narrows the fetched resource to a part of it,
+    /// keeping it borrowed mutably (see `Fetch::map`).
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedFetchMut<'a, U>
+        where F: FnOnce(&mut T) -> &mut U
+    {
+        MappedFetchMut {
+            inner: RefMut::map(this.inner, |r| f(unsafe { r.downcast_mut_unchecked() })),
+            #[cfg(feature = "profiling")]
+            _timer: this._timer,
+        }
+    }
+}
+
+/// A part of a resource, returned by [`FetchMut::map`].
 ///
-/// # Resource Ids
+/// [`FetchMut::map`]: struct.FetchMut.html#method.map
+pub struct MappedFetchMut<'a, U: ?Sized + 'a> {
+    inner: RefMut<'a, U>,
+    #[cfg(feature = "profiling")]
+    _timer: HoldTimer,
+}
+
+impl<'a, U: ?Sized> Deref for MappedFetchMut<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        &self.inner
+    }
+}
+
+impl<'a, U: ?Sized> DerefMut for MappedFetchMut<'a, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        &mut self.inner
+    }
+}
+
+impl<'a, T> Deref for FetchMut<'a, T>
+    where T: Resource
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.inner.downcast_ref_unchecked() }
+    }
+}
+
+impl<'a, T> DerefMut for FetchMut<'a, T>
+    where T: Resource
+{
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.inner.downcast_mut_unchecked() }
+    }
+}
+
+impl<'a, T> SystemData<'a> for FetchMut<'a, T>
+    where T: Resource
+{
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        res.fetch_mut(id)
+    }
+
+    fn reads(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+
+    fn writes(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+}
+
+impl<'a, T> SystemData<'a> for Option<FetchMut<'a, T>>
+    where T: Resource
+{
+    fn fetch(res: &'a Resources, id: usize) -> Self {
+        if res.has_value(ResourceId::new_with_id::<T>(id)) {
+            Some(res.fetch_mut(id))
+        } else {
+            None
+        }
+    }
+
+    fn reads(_: usize) -> Vec<ResourceId> {
+        vec![]
+    }
+
+    fn writes(id: usize) -> Vec<ResourceId> {
+        vec![ResourceId::new_with_id::<T>(id)]
+    }
+}
+
+/// A view into a single resource slot of a `Resources`
+/// container, which may be vacant or occupied.
 ///
-/// Resources are in general identified
-/// by `ResourceId`, which consists of a `TypeId`
-/// and a `usize`. The `usize` may be used as
-/// an additional identifier. In many cases, there
-/// are convenience methods which assume this id is `0`.
-#[derive(Default)]
-pub struct Resources {
-    resources: FnvHashMap<ResourceId, TrustCell<Box<Resource>>>,
+/// Returned by [`Resources::entry`].
+///
+/// [`Resources::entry`]: struct.Resources.html#method.entry
+pub struct Entry<'a, T: 'a, S: 'a = DefaultStorage>
+    where S: ResourceStorage
+{
+    id: usize,
+    res: &'a mut Resources<S>,
+    phantom: PhantomData<T>,
 }
 
-impl Resources {
-    /// Creates a new, empty resource container.
-    pub fn new() -> Self {
-        Default::default()
+impl<'a, T, S> Entry<'a, T, S>
+    where T: Resource,
+          S: ResourceStorage
+{
+    /// Inserts `v` if the resource is missing
+    /// and fetches it mutably.
+    pub fn or_insert(self, v: T) -> FetchMut<'a, T> {
+        self.or_insert_with(move || v)
     }
 
-    /// Adds a new resource to this container.
-    ///
-    /// This method calls `add_with_id` with
-    /// 0 for the id.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the resource is already registered.
-    ///
-    /// # Examples
+    /// Inserts the return value of `f` if the
+    /// resource is missing and fetches it mutably.
     ///
-    /// Every type satisfying `Any + Debug + Send + Sync`
-    /// automatically implements `Resource`:
-    ///
-    /// ```rust
-    /// # #![allow(dead_code)]
-    /// #[derive(Debug)]
-    /// struct MyRes(i32);
-    /// ```
+    /// `f` is not called if the resource already exists.
+    pub fn or_insert_with<F>(self, f: F) -> FetchMut<'a, T>
+        where F: FnOnce() -> T
+    {
+        let res_id = ResourceId::new_with_id::<T>(self.id);
+
+        if !self.res.has_value(res_id) {
+            self.res.register_type_name::<T>();
+            self.res.insert_cell(res_id, TrustCell::new(Box::new(f())));
+        }
+
+        // Now that the resource exists, the exclusive borrow
+        // is only needed for the lifetime of the returned `FetchMut`.
+        let res: &'a Resources<S> = self.res;
+
+        res.fetch_mut(self.id)
+    }
+}
+
+/// Configures how [`Resources::fetch_mut_retry`] waits
+/// for a contended resource.
+///
+/// After every failed attempt, the calling thread sleeps
+/// for the current delay, which starts at `base_delay` and
+/// doubles with every retry.
+///
+/// Only available with the `parking` feature.
+///
+/// [`Resources::fetch_mut_retry`]: struct.Resources.html#method.fetch_mut_retry
+#[cfg(feature = "parking")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BackoffPolicy {
+    /// The delay before the first retry.
+    pub base_delay: Duration,
+    /// The maximum number of retries after the first
+    /// attempt failed. `0` means the borrow is only
+    /// tried once.
+    pub max_retries: u32,
+}
+
+#[cfg(feature = "parking")]
+impl BackoffPolicy {
+    /// Creates a new backoff policy from a base delay
+    /// and the maximum number of retries.
+    pub fn new(base_delay: Duration, max_retries: u32) -> Self {
+        BackoffPolicy {
+            base_delay: base_delay,
+            max_retries: max_retries,
+        }
+    }
+}
+
+#[cfg(feature = "parking")]
+impl Default for BackoffPolicy {
+    /// Starts with a delay of 10 microseconds and
+    /// retries up to 8 times (waiting roughly 2.5 milliseconds
+    /// in total).
+    fn default() -> Self {
+        BackoffPolicy::new(Duration::new(0, 10_000), 8)
+    }
+}
+
+/// A resource defines a set of data
+/// which can only be accessed according
+/// to Rust's typical borrowing model (one writer xor multiple readers).
+pub trait Resource: Any + Send + Sync {}
+
+// The downcasts generated by `mopafy` transmute raw pointers
+#[allow(clippy::transmute_ptr_to_ref)]
+mod downcast {
+    use super::Resource;
+
+    mopafy!(Resource);
+}
+
+impl<T> Resource for T where T: Any + Send + Sync {}
+
+/// Gets notified when resources are added to or removed
+/// from a `Resources` container, registered with
+/// [`Resources::add_observer`].
+///
+/// Replacing a resource counts as removing the old and adding
+/// the new one. Thread-local resources are not observed.
+///
+/// [`Resources::add_observer`]: struct.Resources.html#method.add_observer
+pub trait ResourceObserver: Send {
+    /// Called after the resource with the
+    /// id `id` has been added.
+    fn inserted(&mut self, id: ResourceId, resource: &Resource) {
+        let _ = (id, resource);
+    }
+
+    /// Called after the resource with the id `id` has
+    /// been removed, before it's dropped.
+    fn removed(&mut self, id: ResourceId, resource: &Resource) {
+        let _ = (id, resource);
+    }
+}
+
+/// The observer registered by `Resources::on_insert`
+/// and `Resources::on_remove`.
+struct TypedObserver<F> {
+    on_insert: bool,
+    type_id: TypeId,
+    f: F,
+}
+
+impl<F> ResourceObserver for TypedObserver<F>
+    where F: FnMut(ResourceId, &Resource) + Send
+{
+    fn inserted(&mut self, id: ResourceId, resource: &Resource) {
+        if self.on_insert && id.0 == self.type_id {
+            (self.f)(id, resource);
+        }
+    }
+
+    fn removed(&mut self, id: ResourceId, resource: &Resource) {
+        if !self.on_insert && id.0 == self.type_id {
+            (self.f)(id, resource);
+        }
+    }
+}
+
+/// A resource which accumulates changes (e.g. commands
+/// pushed by systems) that have to be applied at specific points.
+///
+/// After registering it with [`Resources::register_flushable`],
+/// it is flushed by [`Resources::flush`], which is also
+/// called at every flush point of a dispatcher (see
+/// [`DispatcherBuilder::with_flush_point`]).
+///
+/// [`Resources::register_flushable`]: struct.Resources.html#method.register_flushable
+/// [`Resources::flush`]: struct.Resources.html#method.flush
+/// [`DispatcherBuilder::with_flush_point`]: struct.DispatcherBuilder.html#method.with_flush_point
+pub trait FlushableResource: Resource {
+    /// Applies the accumulated changes.
     ///
-    /// When you have a resource, simply
-    /// register it like this:
+    /// The resource itself is borrowed mutably while
+    /// this is called, so it must not be fetched from `res`.
+    fn flush(&mut self, res: &Resources);
+}
+
+/// The id of a [`Resource`],
+/// which is a tuple struct with a type
+/// id and an additional resource id (represented with a `usize`).
+///
+/// The default resource id is `0`. Use [`DynamicId`]
+/// for ids which are names instead of integers.
+///
+/// [`DynamicId`]: enum.DynamicId.html
+///
+/// [`Resource`]: trait.Resource.html
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ResourceId(pub TypeId, pub usize);
+
+impl ResourceId {
+    /// Creates a new resource id from
+    /// a given type with the default
+    /// extra id.
+    pub fn new<T: Resource>() -> Self {
+        Self::new_with_id::<T>(0)
+    }
+
+    /// Creates a new resource id from
+    /// a given type and an additional id.
+    pub fn new_with_id<T: Resource>(id: usize) -> Self {
+        ResourceId(TypeId::of::<T>(), id)
+    }
+}
+
+/// The names interned by `DynamicId`, the name
+/// at index `i` has the id `DynamicId::FIRST_NAMED + i`.
+static NAMES: Mutex<Option<Interner>> = Mutex::new(None);
+
+#[derive(Default)]
+struct Interner {
+    ids: Map<String, usize>,
+    names: Vec<String>,
+}
+
+/// Returns the id of `name`, interning it if it's a new one.
+fn intern(name: &str) -> usize {
+    let mut interner = NAMES.lock().expect("Mutex poisoned");
+    let interner = interner.get_or_insert_with(Interner::default);
+
+    if let Some(&id) = interner.ids.get(name) {
+        return id;
+    }
+
+    let id = DynamicId::FIRST_NAMED + interner.names.len();
+    interner.ids.insert(name.to_owned(), id);
+    interner.names.push(name.to_owned());
+
+    id
+}
+
+/// An additional resource id, which is either
+/// an integer or a name.
+///
+/// Resources are stored with `usize` ids, so names are interned
+/// by `to_usize`. Every name gets its own id, counting up from
+/// `DynamicId::FIRST_NAMED` (the upper half of the `usize` range)
+/// and stable until the process exits, so named ids don't collide
+/// with integer ids like the ones of players or worlds. This saves
+/// assigning ids to such resources by hand.
+///
+/// Integer ids must be below `FIRST_NAMED`; `to_usize` panics
+/// for larger ones, and `usize` ids from that range passed to
+/// `fetch` directly belong to names.
+///
+/// Interning takes a global lock, so ids which are needed often
+/// (e.g. in `SystemData::fetch`) should be resolved once, or
+/// be stored in a [`NamedId`], which resolves the id on first
+/// use and caches it.
+///
+/// Derived `SystemData` can fetch a field with a fixed id
+/// by annotating it with `#[shred(id = 1)]` or
+/// `#[shred(id = "minimap")]`, which uses a `NamedId`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{DynamicId, Resources};
+/// # struct Camera;
+/// let minimap = DynamicId::from("minimap").to_usize();
+///
+/// let mut res = Resources::new();
+/// res.add_with_id(Camera, minimap);
+///
+/// assert!(res.try_fetch::<Camera>(DynamicId::from("minimap").to_usize()).is_some());
+/// assert_eq!(DynamicId::from_usize(minimap), DynamicId::from("minimap"));
+/// assert_eq!(DynamicId::from_usize(2), DynamicId::Index(2));
+/// ```
+///
+/// [`NamedId`]: struct.NamedId.html
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DynamicId {
+    /// An integer id, used as is.
+    Index(usize),
+    /// A name, which is interned.
+    Name(String),
+}
+
+impl DynamicId {
+    /// The id of the first interned name. Integer
+    /// ids must be below, named ids are above.
+    pub const FIRST_NAMED: usize = !(!0 >> 1);
+
+    /// Returns the `usize` id resources are stored with,
+    /// interning the name if it's a new one.
     ///
-    /// ```rust
-    /// # #[derive(Debug)] struct MyRes(i32);
-    /// use shred::Resources;
+    /// # Panics
     ///
-    /// let mut res = Resources::new();
-    /// res.add(MyRes(5));
-    /// ```
-    pub fn add<R>(&mut self, r: R)
-        where R: Resource
-    {
-        self.add_with_id(r, 0)
+    /// Panics if this is an `Index` not below `FIRST_NAMED`.
+    pub fn to_usize(&self) -> usize {
+        match *self {
+            DynamicId::Index(id) => {
+                assert!(id < Self::FIRST_NAMED,
+                        "Integer ids must be below `DynamicId::FIRST_NAMED`");
+
+                id
+            }
+            DynamicId::Name(ref name) => intern(name),
+        }
+    }
+
+    /// Converts an id returned by `to_usize` back, which is
+    /// a `Name` if the id belongs to an interned name.
+    pub fn from_usize(id: usize) -> Self {
+        if id < Self::FIRST_NAMED {
+            return DynamicId::Index(id);
+        }
+
+        let names = NAMES.lock().expect("Mutex poisoned");
+
+        match names.as_ref().and_then(|x| x.names.get(id - Self::FIRST_NAMED)) {
+            Some(name) => DynamicId::Name(name.clone()),
+            None => DynamicId::Index(id),
+        }
+    }
+}
+
+/// A name which is interned the first time its id is
+/// requested, meant to be stored in a `static`.
+///
+/// Later requests only load the cached id, without
+/// taking the lock of the interner (see `DynamicId`).
+///
+/// # Examples
+///
+/// ```rust
+/// # use shred::{DynamicId, NamedId, Resources};
+/// static MINIMAP: NamedId = NamedId::new("minimap");
+///
+/// let mut res = Resources::new();
+/// res.add_with_id(5u32, MINIMAP.get());
+///
+/// assert_eq!(MINIMAP.get(), DynamicId::from("minimap").to_usize());
+/// assert_eq!(*res.fetch::<u32>(MINIMAP.get()), 5);
+/// ```
+#[derive(Debug)]
+pub struct NamedId {
+    name: &'static str,
+    /// The interned id, or `0` if it wasn't requested yet.
+    id: AtomicUsize,
+}
+
+impl NamedId {
+    /// Creates a `NamedId` for `name`.
+    pub const fn new(name: &'static str) -> Self {
+        NamedId {
+            name: name,
+            id: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the name.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the id of the name, interning it on first use.
+    pub fn get(&self) -> usize {
+        match self.id.load(Ordering::Relaxed) {
+            0 => {
+                let id = intern(self.name);
+                self.id.store(id, Ordering::Relaxed);
+
+                id
+            }
+            id => id,
+        }
+    }
+}
+
+impl From<usize> for DynamicId {
+    fn from(id: usize) -> Self {
+        DynamicId::Index(id)
+    }
+}
+
+impl<'a> From<&'a str> for DynamicId {
+    fn from(name: &'a str) -> Self {
+        DynamicId::Name(name.to_owned())
+    }
+}
+
+impl From<String> for DynamicId {
+    fn from(name: String) -> Self {
+        DynamicId::Name(name)
+    }
+}
+
+impl Display for DynamicId {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match *self {
+            DynamicId::Index(id) => write!(f, "{}", id),
+            DynamicId::Name(ref name) => write!(f, "{:?}", name),
+        }
+    }
+}
+
+/// The dense index of a resource in the storage,
+/// returned by [`Resources::index`].
+///
+/// [`Resources::index`]: struct.Resources.html#method.index
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ResourceIndex {
+    id: ResourceId,
+    index: usize,
+}
+
+impl ResourceIndex {
+    /// Returns the id of the indexed resource.
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+}
+
+/// The resources a system fetched during its last run,
+/// so [`Resources::run_resolved`] can fetch them again
+/// without hashing their ids.
+///
+/// A resolution belongs to a single system; the dispatcher
+/// keeps one for each of its systems.
+///
+/// [`Resources::run_resolved`]: struct.Resources.html#method.run_resolved
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct Resolution {
+    /// The container the fetches were recorded for
+    /// (see `Resources::container`), 0 for none.
+    container: usize,
+    generation: usize,
+    /// True once a run has recorded all of its fetches.
+    complete: bool,
+    /// The fetched resources, in the order they were fetched.
+    indices: RefCell<Vec<ResourceIndex>>,
+    /// The position of the next fetch in `indices`.
+    next: Cell<usize>,
+    recording: Cell<bool>,
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    /// The resolution of the system running on this thread.
+    static RESOLUTION: Cell<*const Resolution> = const { Cell::new(ptr::null()) };
+}
+
+/// Hands out the ids identifying containers in resolutions,
+/// which unlike addresses are never reused.
+#[cfg(feature = "std")]
+static NEXT_CONTAINER: AtomicUsize = AtomicUsize::new(1);
+
+/// The version of a resource, which is bumped
+/// every time the resource is modified through
+/// a mutable fetch (e.g. `FetchMut`), `get_mut`
+/// or replaced.
+///
+/// The versions of a resource never decrease, but
+/// versions of different resources are unrelated.
+///
+/// See [`Resources::version`].
+///
+/// [`Resources::version`]: struct.Resources.html#method.version
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Version(usize);
+
+/// Error returned by [`Resources::rename`].
+///
+/// [`Resources::rename`]: struct.Resources.html#method.rename
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RenameError {
+    /// There is no resource with the source id.
+    Missing,
+    /// There is already a resource with the target id.
+    Occupied,
+    /// The type ids of source and target differ.
+    TypeMismatch,
+}
+
+impl RenameError {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            RenameError::Missing => "No resource with the given id",
+            RenameError::Occupied => "The target id is already occupied",
+            RenameError::TypeMismatch => "Only the additional id of a resource can be changed",
+        }
+    }
+}
+
+impl Display for RenameError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Error for RenameError {
+    fn description(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// Decides what [`Resources::extend`] does with resources (and
+/// names) which exist in both containers.
+///
+/// [`Resources::extend`]: struct.Resources.html#method.extend
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Panic before anything is moved.
+    Panic,
+    /// Keep the existing resource and drop the new one.
+    Skip,
+    /// Replace the existing resource by the new one.
+    Replace,
+}
+
+/// A resource which couldn't be fetched by
+/// [`Resources::fetch_many`], with the name of its type.
+///
+/// [`Resources::fetch_many`]: struct.Resources.html#method.fetch_many
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FetchProblem {
+    /// The resource was requested mutably more
+    /// than once, or both mutably and immutably.
+    Aliased(&'static str, ResourceId),
+    /// There is no such resource.
+    Missing(&'static str, ResourceId),
+    /// The resource is borrowed in a conflicting way already.
+    Borrowed(&'static str, ResourceId),
+}
+
+impl Display for FetchProblem {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        let (problem, name, id) = match *self {
+            FetchProblem::Aliased(name, id) => ("Requested conflicting borrows of", name, id),
+            FetchProblem::Missing(name, id) => ("No resource", name, id),
+            FetchProblem::Borrowed(name, id) => ("Already borrowed:", name, id),
+        };
+
+        write!(f, "{} `{}` ({})", problem, name, DynamicId::from_usize(id.1))
+    }
+}
+
+/// The error returned by [`Resources::fetch_many`],
+/// listing all resources which couldn't be fetched.
+///
+/// [`Resources::fetch_many`]: struct.Resources.html#method.fetch_many
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FetchManyError {
+    problems: Vec<FetchProblem>,
+}
+
+impl FetchManyError {
+    /// Returns the problems, in the order of the
+    /// resources in the fetched data.
+    pub fn problems(&self) -> &[FetchProblem] {
+        &self.problems
+    }
+}
+
+impl Display for FetchManyError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        write!(f, "Failed to fetch resources")?;
+
+        for problem in &self.problems {
+            write!(f, "\n{}", problem)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for FetchManyError {
+    fn description(&self) -> &str {
+        "Failed to fetch resources"
+    }
+}
+
+/// `SystemData` which can be fetched without panicking,
+/// used by [`Resources::fetch_many`].
+///
+/// Implemented for `Fetch`, `FetchMut`, `ReadDefault`, `WriteDefault`
+/// and tuples of them.
+///
+/// [`Resources::fetch_many`]: struct.Resources.html#method.fetch_many
+pub trait TryFetch<'a>: SystemData<'a> + Sized {
+    /// Fetches the data, or appends the reasons it
+    /// can't be fetched to `problems` and returns `None`.
+    ///
+    /// All resources are tried, so every problem is reported.
+    fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>) -> Option<Self>;
+}
+
+impl<'a, T> TryFetch<'a> for Fetch<'a, T>
+    where T: Resource
+{
+    fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>) -> Option<Self> {
+        let res_id = ResourceId::new_with_id::<T>(id);
+        let fetched = res.try_fetch(id);
+
+        if fetched.is_none() {
+            problems.push(if res.has_value(res_id) {
+                              FetchProblem::Borrowed(type_name::<T>(), res_id)
+                          } else {
+                              FetchProblem::Missing(type_name::<T>(), res_id)
+                          });
+        }
+
+        fetched
+    }
+}
+
+impl<'a, T> TryFetch<'a> for FetchMut<'a, T>
+    where T: Resource
+{
+    fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>) -> Option<Self> {
+        let res_id = ResourceId::new_with_id::<T>(id);
+        let fetched = res.try_fetch_mut(id);
+
+        if fetched.is_none() {
+            problems.push(if res.has_value(res_id) {
+                              FetchProblem::Borrowed(type_name::<T>(), res_id)
+                          } else {
+                              FetchProblem::Missing(type_name::<T>(), res_id)
+                          });
+        }
+
+        fetched
+    }
+}
+
+impl<'a, T> TryFetch<'a> for ReadDefault<'a, T>
+    where T: Default + Resource
+{
+    fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>) -> Option<Self> {
+        Fetch::try_fetch(res, id, problems).map(|inner| ReadDefault { inner: inner })
+    }
+}
+
+impl<'a, T> TryFetch<'a> for WriteDefault<'a, T>
+    where T: Default + Resource
+{
+    fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>) -> Option<Self> {
+        FetchMut::try_fetch(res, id, problems).map(|inner| WriteDefault { inner: inner })
+    }
+}
+
+macro_rules! impl_try_fetch {
+    ( $($ty:ident),* ) => {
+        impl<'a, $($ty),*> TryFetch<'a> for ( $( $ty , )* )
+            where $( $ty : TryFetch<'a> ),*
+        {
+            #[allow(non_snake_case)]
+            fn try_fetch(res: &'a Resources, id: usize, problems: &mut Vec<FetchProblem>)
+                -> Option<Self>
+            {
+                // Fetch everything first, so all problems are reported
+                let ( $( $ty , )* ) = ( $( $ty::try_fetch(res, id, problems) , )* );
+
+                match ( $( $ty , )* ) {
+                    ( $( Some($ty) , )* ) => Some(( $( $ty , )* )),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+mod impl_try_fetch {
+    #![cfg_attr(rustfmt, rustfmt_skip)]
+
+    use super::*;
+
+    impl_try_fetch!(A);
+    impl_try_fetch!(A, B);
+    impl_try_fetch!(A, B, C);
+    impl_try_fetch!(A, B, C, D);
+    impl_try_fetch!(A, B, C, D, E);
+    impl_try_fetch!(A, B, C, D, E, F);
+    impl_try_fetch!(A, B, C, D, E, F, G);
+    impl_try_fetch!(A, B, C, D, E, F, G, H);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y);
+    impl_try_fetch!(A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z);
+}
+
+/// A resource together with its borrow state and version,
+/// as kept by a [`ResourceStorage`].
+///
+/// The cell is opaque: storages only move it around,
+/// while `Resources` creates it and accesses the resource.
+///
+/// [`ResourceStorage`]: trait.ResourceStorage.html
+pub struct ResourceCell(TrustCell<Box<Resource>>);
+
+/// The storage backend of [`Resources`].
+///
+/// By default, resources are stored in a [`DenseStorage`],
+/// but this trait allows using a different data structure,
+/// e.g. a `FnvHashMap` (a `BTreeMap` without the `std` feature)
+/// or a flat `Vec` for a few hot resources.
+///
+/// Storages only hold opaque [`ResourceCell`]s, so they
+/// can't access or modify the resources themselves.
+///
+/// Note that systems and dispatchers always fetch from
+/// `Resources` with the default storage; a container with
+/// a custom storage is accessed with its own methods
+/// (e.g. `fetch` and `fetch_mut`).
+///
+/// # Safety
+///
+/// `Resources` downcasts the returned cells without checking
+/// their types, so implementations must make sure that `get`
+/// and `get_mut` only ever return the cell inserted under exactly
+/// `id`, and that `get_index` returns the id the cell was
+/// inserted under. A storage mixing up ids causes undefined
+/// behavior in safe code.
+///
+/// [`DenseStorage`]: struct.DenseStorage.html
+/// [`ResourceCell`]: struct.ResourceCell.html
+/// [`Resources`]: struct.Resources.html
+pub unsafe trait ResourceStorage {
+    /// Returns the cell stored for `id`, if any.
+    fn get(&self, id: ResourceId) -> Option<&ResourceCell>;
+
+    /// Like `get`, but returns the cell mutably.
+    fn get_mut(&mut self, id: ResourceId) -> Option<&mut ResourceCell>;
+
+    /// Stores `cell` for `id`, returning the cell
+    /// which was stored before (if any).
+    fn insert(&mut self,
+              id: ResourceId,
+              cell: ResourceCell)
+              -> Option<ResourceCell>;
+
+    /// Removes the cell stored for `id` and returns it.
+    fn remove(&mut self, id: ResourceId) -> Option<ResourceCell>;
+
+    /// Returns the ids of all stored cells.
+    fn ids(&self) -> Vec<ResourceId>;
+
+    /// Returns the dense index assigned to `id`, which
+    /// can be passed to `get_index` instead of the id.
+    ///
+    /// Storages without dense indices return
+    /// `None`, which is the default.
+    fn index(&self, _id: ResourceId) -> Option<usize> {
+        None
+    }
+
+    /// Returns the id and the cell stored at `index`, if any.
+    fn get_index(&self, _index: usize) -> Option<(ResourceId, &ResourceCell)> {
+        None
+    }
+}
+
+type DefaultStorage = DenseStorage;
+
+/// The default storage of [`Resources`], which assigns every
+/// resource id a dense index the first time it is inserted.
+///
+/// Indices aren't reused for other ids, so they can be
+/// resolved once (see `Resources::index`) and then be used
+/// to fetch without hashing the id.
+///
+/// [`Resources`]: struct.Resources.html
+#[derive(Default)]
+pub struct DenseStorage {
+    cells: Vec<Option<ResourceCell>>,
+    ids: Vec<ResourceId>,
+    indices: Map<ResourceId, usize>,
+}
+
+unsafe impl ResourceStorage for DenseStorage {
+    fn get(&self, id: ResourceId) -> Option<&ResourceCell> {
+        self.index(id).and_then(|index| self.cells[index].as_ref())
+    }
+
+    fn get_mut(&mut self, id: ResourceId) -> Option<&mut ResourceCell> {
+        match self.index(id) {
+            Some(index) => self.cells[index].as_mut(),
+            None => None,
+        }
+    }
+
+    fn insert(&mut self,
+              id: ResourceId,
+              cell: ResourceCell)
+              -> Option<ResourceCell> {
+        let index = match self.index(id) {
+            Some(index) => index,
+            None => {
+                let index = self.cells.len();
+                self.cells.push(None);
+                self.ids.push(id);
+                self.indices.insert(id, index);
+
+                index
+            }
+        };
+
+        self.cells[index].replace(cell)
+    }
+
+    fn remove(&mut self, id: ResourceId) -> Option<ResourceCell> {
+        match self.index(id) {
+            Some(index) => self.cells[index].take(),
+            None => None,
+        }
+    }
+
+    fn ids(&self) -> Vec<ResourceId> {
+        self.ids
+            .iter()
+            .zip(&self.cells)
+            .filter(|&(_, cell)| cell.is_some())
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    fn index(&self, id: ResourceId) -> Option<usize> {
+        self.indices.get(&id).cloned()
+    }
+
+    fn get_index(&self, index: usize) -> Option<(ResourceId, &ResourceCell)> {
+        self.cells
+            .get(index)
+            .and_then(|cell| cell.as_ref())
+            .map(|cell| (self.ids[index], cell))
+    }
+}
+
+unsafe impl ResourceStorage for Map<ResourceId, ResourceCell> {
+    fn get(&self, id: ResourceId) -> Option<&ResourceCell> {
+        Map::get(self, &id)
+    }
+
+    fn get_mut(&mut self, id: ResourceId) -> Option<&mut ResourceCell> {
+        Map::get_mut(self, &id)
+    }
+
+    fn insert(&mut self,
+              id: ResourceId,
+              cell: ResourceCell)
+              -> Option<ResourceCell> {
+        Map::insert(self, id, cell)
+    }
+
+    fn remove(&mut self, id: ResourceId) -> Option<ResourceCell> {
+        Map::remove(self, &id)
+    }
+
+    fn ids(&self) -> Vec<ResourceId> {
+        self.keys().cloned().collect()
+    }
+}
+
+/// A resource container, which
+/// provides methods to access to
+/// the contained resources.
+///
+/// # Resource Ids
+///
+/// Resources are in general identified
+/// by `ResourceId`, which consists of a `TypeId`
+/// and a `usize`. The `usize` may be used as
+/// an additional identifier. In many cases, there
+/// are convenience methods which assume this id is `0`.
+///
+/// # Named resources
+///
+/// Resources can additionally be registered under a string
+/// name with `add_named` or `register_name`, and fetched
+/// with `fetch_by_name` without knowing their type. This allows
+/// scripts to define their own resources, e.g. by storing
+/// their data as `Vec<u8>` or `Box<Any + Send + Sync>`.
+///
+/// # Thread-local resources
+///
+/// Resources which are not `Send` or `Sync` can be stored
+/// separately with `add_thread_local`. They can only be fetched
+/// from the thread which added them and are never handed to
+/// the worker threads of a dispatcher.
+///
+/// # Safety
+///
+/// `Resources` is `Send` and `Sync` even if it contains thread-local
+/// resources, which is sound because they're only handed out on
+/// their owning thread. For the same reason, they can't be dropped
+/// on another thread: clearing or dropping the container there
+/// leaks them and then panics (unless the thread is already
+/// panicking). Call `clear` on the owning thread before moving
+/// the container away for good.
+///
+/// # Storage
+///
+/// The resources are stored in a [`ResourceStorage`],
+/// which defaults to a [`DenseStorage`]. Use `with_storage` to
+/// create a container with a different backend. Only containers
+/// with the default storage can be passed to systems.
+///
+/// [`DenseStorage`]: struct.DenseStorage.html
+/// [`ResourceStorage`]: trait.ResourceStorage.html
+///
+/// # Teardown order
+///
+/// By default, resources are dropped in an unspecified order.
+/// If some resources have to be torn down before others,
+/// register hooks with `on_drop`.
+///
+/// # Scopes
+///
+/// `push_scope` starts a scope in which resources can be
+/// shadowed with temporary values using `shadow`. Fetching
+/// returns the temporary values until `pop_scope` restores
+/// the originals, so a dispatcher can run against variations
+/// of a few resources.
+///
+/// # Snapshots
+///
+/// Resources implementing `Clone` can be registered with
+/// `register_cloneable`, so they're copied by `snapshot`
+/// and reset to the copied state by `restore` (e.g. for
+/// rollback or undo).
+///
+/// # Merging
+///
+/// `extend` moves all resources of another container into this
+/// one (e.g. bundles built by plugins), and `split_off` moves a
+/// subset of the resources into a new container.
+///
+/// # Serialization
+///
+/// With the `serialize` feature, resources implementing
+/// `Serialize` and `Deserialize` can be registered under
+/// a key with `register_serializable`, so they're written
+/// by `serialize` and read back by `deserialize`
+/// (e.g. for save games).
+pub struct Resources<S = DefaultStorage>
+    where S: ResourceStorage
+{
+    cloneable: Vec<(ResourceId, CloneFn)>,
+    /// Identifies this container in resolutions.
+    #[cfg(feature = "std")]
+    container: usize,
+    drop_hooks: Vec<DropHook>,
+    flushers: Vec<(usize, FlushFn)>,
+    generation: usize,
+    /// The highest version of all cells removed from the storage,
+    /// so replacing a resource never decreases its version.
+    last_version: usize,
+    names: Map<String, ResourceId>,
+    observers: Vec<Box<ResourceObserver>>,
+    resources: S,
+    scopes: Vec<Scope>,
+    #[cfg(feature = "serialize")]
+    serializable: Vec<Serializable>,
+    #[cfg(feature = "std")]
+    thread_local: Map<ResourceId, LocalCell>,
+    /// The names of the types of all resources added
+    /// so far, used for panic messages.
+    type_names: Map<TypeId, &'static str>,
+}
+
+/// Flushes the resource of a fixed type with
+/// the given id (see `register_flushable`).
+type FlushFn = fn(&Resources, usize);
+
+/// The resources shadowed by a scope, and `None`
+/// for the ones which didn't exist before.
+type Scope = Vec<(ResourceId, Option<TrustCell<Box<Resource>>>)>;
+
+/// A hook registered with `Resources::on_drop`.
+struct DropHook {
+    priority: i32,
+    type_id: TypeId,
+    hook: DropFn,
+}
+
+/// The function of a `DropHook`.
+type DropFn = Box<FnMut(&mut Resource) + Send>;
+
+/// A thread-local resource together with
+/// the thread it belongs to.
+#[cfg(feature = "std")]
+struct LocalCell {
+    owner: ThreadId,
+    cell: TrustCell<Box<StdAny>>,
+}
+
+/// Drops thread-local resources owned by the current thread.
+///
+/// Resources owned by another thread can't be dropped here, as their
+/// destructor could race with the owning thread, so they're leaked.
+///
+/// # Panics
+///
+/// Panics if a resource had to be leaked, unless the current thread
+/// is already panicking.
+#[cfg(feature = "std")]
+fn drop_thread_local<I>(locals: I)
+    where I: IntoIterator<Item = LocalCell>
+{
+    use std::mem::forget;
+
+    let current = thread::current().id();
+    let mut leaked = 0;
+
+    for local in locals {
+        if local.owner != current {
+            forget(local);
+            leaked += 1;
+        }
+    }
+
+    if leaked > 0 && !thread::panicking() {
+        panic!("Leaked {} thread-local resource(s) because they were dropped on another \
+                thread; clear the resources on the thread which added them",
+               leaked);
+    }
+}
+
+// Thread-local resources are only ever accessed from their owning thread
+// (checked in `fetch_local_internal`) and never dropped on another thread
+// (see `drop_thread_local`), so sharing the container itself is fine.
+unsafe impl<S> Send for Resources<S> where S: ResourceStorage + Send {}
+unsafe impl<S> Sync for Resources<S> where S: ResourceStorage + Sync {}
+
+impl<S> Default for Resources<S>
+    where S: Default + ResourceStorage
+{
+    fn default() -> Self {
+        Resources::with_storage(Default::default())
+    }
+}
+
+impl Resources {
+    /// Creates a new, empty resource container.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Registers the resource of type `T` with the given id
+    /// to be flushed by `flush`.
+    ///
+    /// Resources are flushed in registration order.
+    /// Registering the same resource twice has no effect.
+    pub fn register_flushable<T>(&mut self, id: usize)
+        where T: FlushableResource
+    {
+        fn flush<T: FlushableResource>(res: &Resources, id: usize) {
+            res.fetch_mut::<T>(id).flush(res);
+        }
+
+        let flusher = (id, flush::<T> as FlushFn);
+
+        if !self.flushers
+                .iter()
+                .any(|x| x.0 == flusher.0 && x.1 as usize == flusher.1 as usize) {
+            self.flushers.push(flusher);
+        }
+    }
+
+    /// Applies all changes queued in the [`LazyUpdate`]
+    /// resource, in the order they were queued.
+    ///
+    /// This is meant to be called after dispatching.
+    /// Does nothing if there is no `LazyUpdate` resource.
+    ///
+    /// [`LazyUpdate`]: struct.LazyUpdate.html
+    pub fn maintain(&mut self) {
+        ::lazy::maintain(self);
+    }
+
+    /// Fetches several resources at once, e.g. a tuple of
+    /// `Fetch`es and `FetchMut`s, without panicking.
+    ///
+    /// Either all resources are fetched, or none and the error lists
+    /// every resource which couldn't be fetched. This avoids holding
+    /// some of the resources when fetching another one panics, which
+    /// happens when they are fetched one after another. Fetching a
+    /// resource mutably twice, or both mutably and immutably, is
+    /// reported as `FetchProblem::Aliased` before anything is borrowed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::{Fetch, FetchMut, FetchProblem, ResourceId, Resources};
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(1u32);
+    /// res.add(2u64);
+    ///
+    /// {
+    ///     let (a, mut b) = res.fetch_many::<(Fetch<u32>, FetchMut<u64>)>(0).unwrap();
+    ///     *b += *a as u64;
+    /// }
+    ///
+    /// let _guard = res.fetch::<u64>(0);
+    /// let error = res.fetch_many::<(FetchMut<u32>, FetchMut<u64>)>(0).err().unwrap();
+    /// assert_eq!(error.problems(),
+    ///            &[FetchProblem::Borrowed("u64", ResourceId::new::<u64>())]);
+    ///
+    /// // Not borrowed, because fetching the `u64` failed
+    /// assert!(res.try_fetch_mut::<u32>(0).is_some());
+    /// ```
+    pub fn fetch_many<'a, T>(&'a self, id: usize) -> Result<T, FetchManyError>
+        where T: TryFetch<'a>
+    {
+        let reads = T::reads(id);
+        let writes = T::writes(id);
+
+        let aliased: Vec<_> = writes
+            .iter()
+            .enumerate()
+            .filter(|&(i, x)| reads.contains(x) || writes[..i].contains(x))
+            .map(|(_, &x)| FetchProblem::Aliased(self.type_name(x.0), x))
+            .collect();
+
+        if !aliased.is_empty() {
+            return Err(FetchManyError { problems: aliased });
+        }
+
+        let mut problems = Vec::new();
+
+        match T::try_fetch(self, id, &mut problems) {
+            Some(data) => Ok(data),
+            None => Err(FetchManyError { problems: problems }),
+        }
+    }
+
+    /// Flushes all resources registered with `register_flushable`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the registered resources is missing
+    /// or borrowed.
+    pub fn flush(&self) {
+        for &(id, flush) in &self.flushers {
+            flush(self, id);
+        }
+    }
+}
+
+impl<S> Resources<S>
+    where S: ResourceStorage
+{
+    /// Creates a new, empty resource container
+    /// using `storage` as backend.
+    pub fn with_storage(storage: S) -> Self {
+        Resources {
+            cloneable: Vec::new(),
+            #[cfg(feature = "std")]
+            container: NEXT_CONTAINER.fetch_add(1, Ordering::Relaxed),
+            drop_hooks: Vec::new(),
+            flushers: Vec::new(),
+            generation: 0,
+            last_version: 0,
+            names: Default::default(),
+            observers: Vec::new(),
+            resources: storage,
+            scopes: Vec::new(),
+            #[cfg(feature = "serialize")]
+            serializable: Vec::new(),
+            #[cfg(feature = "std")]
+            thread_local: Default::default(),
+            type_names: Default::default(),
+        }
+    }
+
+    /// Adds a new resource to this container.
+    ///
+    /// This method calls `add_with_id` with
+    /// 0 for the id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is already registered.
+    ///
+    /// # Examples
+    ///
+    /// Every type satisfying `Any + Debug + Send + Sync`
+    /// automatically implements `Resource`:
+    ///
+    /// ```rust
+    /// # #![allow(dead_code)]
+    /// #[derive(Debug)]
+    /// struct MyRes(i32);
+    /// ```
+    ///
+    /// When you have a resource, simply
+    /// register it like this:
+    ///
+    /// ```rust
+    /// # #[derive(Debug)] struct MyRes(i32);
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(MyRes(5));
+    /// ```
+    pub fn add<R>(&mut self, r: R)
+        where R: Resource
+    {
+        self.add_with_id(r, 0)
+    }
+
+    /// Like `add()`, but allows specifying
+    /// and id while `add()` assumes `0`.
+    pub fn add_with_id<R>(&mut self, r: R, id: usize)
+        where R: Resource
+    {
+        let res_id = ResourceId::new_with_id::<R>(id);
+
+        if self.resources.get(res_id).is_some() {
+            panic!("Tried to add a resource though it is already registered");
+        }
+
+        self.register_type_name::<R>();
+        self.insert_cell(res_id, TrustCell::new(Box::new(r)));
+    }
+
+    /// Adds a new resource which can be fetched by `name`,
+    /// using the lowest id which isn't used by another
+    /// resource of type `R`.
+    ///
+    /// Returns the id the resource was added with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already registered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    ///
+    /// // Script resources without a Rust type of their own
+    /// let id = res.add_named("health", vec![100u8]);
+    /// res.add_named("mana", vec![50u8]);
+    ///
+    /// assert_eq!(res.resource_id("health"), Some(id));
+    ///
+    /// let mana = res.fetch_by_name("mana");
+    /// assert_eq!(mana.downcast_ref::<Vec<u8>>(), Some(&vec![50]));
+    /// ```
+    pub fn add_named<R>(&mut self, name: &str, r: R) -> ResourceId
+        where R: Resource
+    {
+        assert!(!self.names.contains_key(name),
+                "Tried to add a resource with a name which is already registered");
+
+        let res_id = (0..)
+            .map(ResourceId::new_with_id::<R>)
+            .find(|&id| !self.has_value(id))
+            .expect("No free resource id");
+
+        self.register_type_name::<R>();
+        self.insert_cell(res_id, TrustCell::new(Box::new(r)));
+        self.names.insert(name.to_owned(), res_id);
+
+        res_id
+    }
+
+    /// Registers `name` for the existing resource `res_id`,
+    /// so it can be fetched with `fetch_by_name`.
+    ///
+    /// A resource may have multiple names.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is already registered
+    /// or there is no such resource.
+    pub fn register_name(&mut self, name: &str, res_id: ResourceId) {
+        assert!(self.has_value(res_id), "No resource with the given id");
+        assert!(!self.names.contains_key(name),
+                "Tried to register a name which is already registered");
+
+        self.names.insert(name.to_owned(), res_id);
+    }
+
+    /// Returns the id of the resource registered as `name`.
+    ///
+    /// The id can be used to declare the resources
+    /// accessed by a system.
+    pub fn resource_id(&self, name: &str) -> Option<ResourceId> {
+        self.names.get(name).cloned()
+    }
+
+    /// Returns true if the specified type / id combination
+    /// is registered.
+    pub fn has_value(&self, res_id: ResourceId) -> bool {
+        self.resources.get(res_id).is_some()
+    }
+
+    /// Returns true if a system panicked while fetching
+    /// the specified resource mutably, so it may be in an
+    /// inconsistent state (see `Dispatcher::try_dispatch`).
+    ///
+    /// Poisoned resources can still be fetched.
+    pub fn is_poisoned(&self, res_id: ResourceId) -> bool {
+        self.resources
+            .get(res_id)
+            .is_some_and(|cell| cell.0.is_poisoned())
+    }
+
+    /// Returns a counter which is bumped whenever resources are
+    /// added, removed or replaced (e.g. by `rename` or `restore`),
+    /// but not when they are modified.
+    ///
+    /// `run_resolved` uses this to detect when the
+    /// resources a system fetches have to be looked up
+    /// again, instead of reusing their indices (see `index`).
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Runs `f` (e.g. a system), remembering the indices of
+    /// the resources it fetches in `resolution`.
+    ///
+    /// The first run records the fetches. Later runs expect the
+    /// same fetches in the same order, as a system does for its
+    /// `SystemData`, and skip hashing their ids. Fetches deviating
+    /// from the recording are looked up as usual. The fetches are
+    /// recorded again after a structural change (see `generation`)
+    /// or if `resolution` was used with another container.
+    ///
+    /// The dispatcher runs all its systems like this. Only the typed
+    /// fetches (e.g. `fetch` and `fetch_mut`) use the resolution.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use shred::{Fetch, FetchMut, Resolution, Resources, RunNow, System};
+    /// struct Count;
+    ///
+    /// impl<'a> System<'a> for Count {
+    ///     type SystemData = (Fetch<'a, u32>, FetchMut<'a, u64>);
+    ///
+    ///     fn run(&mut self, (step, mut total): Self::SystemData) {
+    ///         *total += *step as u64;
+    ///     }
+    /// }
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(2u32);
+    /// res.add(0u64);
+    ///
+    /// let mut resolution = Resolution::default();
+    /// for _ in 0..3 {
+    ///     res.run_resolved(&mut resolution, || Count.run_now(&res));
+    /// }
+    ///
+    /// assert_eq!(*res.fetch::<u64>(0), 6);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn run_resolved<F, R>(&self, resolution: &mut Resolution, f: F) -> R
+        where F: FnOnce() -> R
+    {
+        let valid = resolution.complete && resolution.container == self.container &&
+                    resolution.generation == self.generation;
+
+        if !valid {
+            resolution.container = self.container;
+            resolution.generation = self.generation;
+            resolution.complete = false;
+            resolution.indices.get_mut().clear();
+        }
+
+        resolution.recording.set(!valid);
+        resolution.next.set(0);
+
+        let result = {
+            // Restore the resolution of the outer system
+            // (e.g. of a batch) even if this one panics
+            struct Restore(*const Resolution);
+
+            impl Drop for Restore {
+                fn drop(&mut self) {
+                    let outer = self.0;
+                    RESOLUTION.with(|current| current.set(outer));
+                }
+            }
+
+            let current = &*resolution as *const Resolution;
+            let _restore = Restore(RESOLUTION.with(|x| x.replace(current)));
+
+            f()
+        };
+
+        resolution.complete = true;
+
+        result
+    }
+
+    /// Returns the current version of the specified resource,
+    /// or `None` if it doesn't exist.
+    ///
+    /// The version is bumped whenever the resource is
+    /// dereferenced mutably or borrowed with `get_mut`
+    /// (even if it isn't written to); fetching it mutably
+    /// without dereferencing it mutably doesn't count. Replacing a resource (e.g. with `restore` or
+    /// by removing and adding it) counts as modification,
+    /// so versions never decrease.
+    pub fn version(&self, res_id: ResourceId) -> Option<Version> {
+        self.resources
+            .get(res_id)
+            .map(|cell| Version(cell.0.version()))
+    }
+
+    /// Returns true if the specified resource was
+    /// modified since it had the version `since`.
+    ///
+    /// Returns `false` if the resource doesn't exist.
+    pub fn modified_since(&self, res_id: ResourceId, since: Version) -> bool {
+        self.version(res_id).is_some_and(|version| version > since)
+    }
+
+    /// Clears the poison flag of the specified resource,
+    /// e.g. once it has been repaired or replaced.
+    ///
+    /// Does nothing if the resource doesn't exist.
+    pub fn clear_poison(&self, res_id: ResourceId) {
+        if let Some(cell) = self.resources.get(res_id) {
+            cell.0.clear_poison();
+        }
+    }
+
+    /// Removes the resource of type `R` with the given id
+    /// from this container and returns it.
+    ///
+    /// Returns `None` if there is no such resource. Drop hooks
+    /// registered with `on_drop` are not run for removed resources.
+    pub fn remove<R>(&mut self, id: usize) -> Option<R>
+        where R: Resource
+    {
+        let res_id = ResourceId::new_with_id::<R>(id);
+        self.names.retain(|_, x| *x != res_id);
+
+        self.remove_cell(res_id)
+            .map(|cell| match cell.into_inner().downcast() {
+                     Ok(r) => *r,
+                     Err(_) => unreachable!("Resource stored with a wrong type id"),
+                 })
+    }
+
+    /// Returns the entry for the resource of type `T`
+    /// with the given id, which allows lazily inserting it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    ///
+    /// *res.entry::<u32>(0).or_insert(0) += 1;
+    /// *res.entry::<u32>(0).or_insert_with(|| unreachable!()) += 1;
+    ///
+    /// assert_eq!(*res.fetch::<u32>(0), 2);
+    /// ```
+    pub fn entry<T>(&mut self, id: usize) -> Entry<T, S>
+        where T: Resource
+    {
+        Entry {
+            id: id,
+            res: self,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Fetches the resource of type `T` mutably, inserting the
+    /// return value of `f` first if it is missing.
+    ///
+    /// This is a shorthand for `entry(id).or_insert_with(f)`.
+    pub fn fetch_or_insert_with<T, F>(&mut self, id: usize, f: F) -> FetchMut<T>
+        where T: Resource,
+              F: FnOnce() -> T
+    {
+        self.entry(id).or_insert_with(f)
+    }
+
+    /// Moves the resource registered as `from` to `to`,
+    /// keeping its concrete type.
+    ///
+    /// Only the additional id can be changed, so both
+    /// ids need to have the same type id.
+    ///
+    /// This is useful when reindexing worlds
+    /// or compacting id spaces.
+    pub fn rename(&mut self, from: ResourceId, to: ResourceId) -> Result<(), RenameError> {
+        if from.0 != to.0 {
+            return Err(RenameError::TypeMismatch);
+        }
+
+        if self.resources.get(to).is_some() {
+            return Err(RenameError::Occupied);
+        }
+
+        let cell = self.remove_cell(from).ok_or(RenameError::Missing)?;
+        self.insert_cell(to, cell);
+
+        for id in self.names.values_mut().filter(|x| **x == from) {
+            *id = to;
+        }
+
+        Ok(())
+    }
+
+    /// Moves all resources of `other` into this container,
+    /// together with their names and their registrations for
+    /// `snapshot` and `serialize`. `policy` decides what happens
+    /// to resources and names which exist in both containers.
+    ///
+    /// This allows building worlds from independent bundles of
+    /// resources. Hooks and observers of `other` are not moved;
+    /// skipped resources are dropped with `other`.
+    ///
+    /// # Panics
+    ///
+    /// Panics with `ConflictPolicy::Panic` if a resource, a name
+    /// or a `register_serializable` key exists in both containers.
+    /// With `ConflictPolicy::Skip`, panics if a skipped thread-local
+    /// resource was added on another thread (see `clear`).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::{ConflictPolicy, Resources};
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(1u32);
+    ///
+    /// let mut bundle = Resources::new();
+    /// bundle.add(2u32);
+    /// bundle.add_named("volume", 0.5f32);
+    ///
+    /// res.extend(bundle, ConflictPolicy::Skip);
+    ///
+    /// assert_eq!(*res.fetch::<u32>(0), 1);
+    /// assert!(res.resource_id("volume").is_some());
+    /// ```
+    pub fn extend<T>(&mut self, mut other: Resources<T>, policy: ConflictPolicy)
+        where T: ResourceStorage
+    {
+        let ids = other.resources.ids();
+
+        if policy == ConflictPolicy::Panic {
+            if let Some(id) = ids.iter().find(|&&id| self.resources.get(id).is_some()) {
+                panic!("Tried to extend with a resource which is already registered: `{}` ({})",
+                       other.type_name(id.0),
+                       DynamicId::from_usize(id.1));
+            }
+
+            #[cfg(feature = "std")]
+            {
+                if let Some(id) = other
+                       .thread_local
+                       .keys()
+                       .find(|&id| self.thread_local.contains_key(id)) {
+                    panic!("Tried to extend with a thread-local resource which is already \
+                            registered: `{}` ({})",
+                           other.type_name(id.0),
+                           DynamicId::from_usize(id.1));
+                }
+            }
+
+            if let Some(name) = other.names.keys().find(|&name| self.names.contains_key(name)) {
+                panic!("Tried to extend with a name which is already registered: `{}`", name);
+            }
+
+            #[cfg(feature = "serialize")]
+            {
+                if let Some(entry) = other
+                       .serializable
+                       .iter()
+                       .find(|entry| self.serializable.iter().any(|x| x.key == entry.key)) {
+                    panic!("Tried to extend with a serializable key which is already \
+                            registered: `{}`",
+                           entry.key);
+                }
+            }
+        }
+
+        let skip = policy == ConflictPolicy::Skip;
+        self.type_names.extend(take(&mut other.type_names));
+
+        for id in ids {
+            if skip && self.resources.get(id).is_some() {
+                continue;
+            }
+
+            let cell = other.remove_cell(id).expect("Storage lost a resource");
+            self.insert_cell(id, cell);
+        }
+
+        #[cfg(feature = "std")]
+        {
+            let mut skipped = Vec::new();
+
+            for (id, local) in take(&mut other.thread_local) {
+                if !skip || !self.thread_local.contains_key(&id) {
+                    self.thread_local.insert(id, local);
+                } else {
+                    skipped.push(local);
+                }
+            }
+
+            drop_thread_local(skipped);
+        }
+
+        for (name, id) in take(&mut other.names) {
+            if !skip || !self.names.contains_key(&name) {
+                self.names.insert(name, id);
+            }
+        }
+
+        for (id, f) in other.cloneable.drain(..) {
+            if !self.cloneable.iter().any(|x| x.0 == id) {
+                self.cloneable.push((id, f));
+            }
+        }
+
+        #[cfg(feature = "serialize")]
+        {
+            for entry in other.serializable.drain(..) {
+                match self.serializable.iter().position(|x| x.key == entry.key) {
+                    Some(i) if !skip => self.serializable[i] = entry,
+                    Some(_) => {}
+                    None => self.serializable.push(entry),
+                }
+            }
+        }
+    }
+
+    /// Moves the resources with the given ids into a new container,
+    /// together with their names and their registrations for
+    /// `snapshot` and `serialize`. Missing ids are ignored.
+    ///
+    /// This allows e.g. handing a few resources to a loading
+    /// thread and merging them back with `extend` afterwards.
+    /// Thread-local resources, hooks and observers are not moved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of the resources is shadowed (or was
+    /// added with `shadow`) in a scope which is still active,
+    /// as the scope couldn't restore it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::{ConflictPolicy, ResourceId, Resources};
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(1u32);
+    /// res.add(2u64);
+    ///
+    /// let mut loading = res.split_off(&[ResourceId::new::<u64>()]);
+    /// assert!(res.try_fetch::<u64>(0).is_none());
+    ///
+    /// *loading.fetch_mut::<u64>(0) += 1;
+    /// res.extend(loading, ConflictPolicy::Panic);
+    ///
+    /// assert_eq!(*res.fetch::<u64>(0), 3);
+    /// ```
+    pub fn split_off(&mut self, ids: &[ResourceId]) -> Resources {
+        if let Some(&(id, _)) = self.scopes
+               .iter()
+               .flat_map(|scope| scope.iter())
+               .find(|x| ids.contains(&x.0)) {
+            panic!("Tried to split off a shadowed resource: `{}` ({})",
+                   self.type_name(id.0),
+                   DynamicId::from_usize(id.1));
+        }
+
+        let mut split = Resources::new();
+
+        for &id in ids {
+            if let Some(cell) = self.remove_cell(id) {
+                if let Some(name) = self.type_names.get(&id.0) {
+                    split.type_names.insert(id.0, name);
+                }
+
+                split.insert_cell(id, cell);
+            }
+        }
+
+        let names = self.names
+            .iter()
+            .filter(|&(_, id)| ids.contains(id))
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        for name in names {
+            let id = self.names.remove(&name).expect("Name vanished");
+            split.names.insert(name, id);
+        }
+
+        let (moved, kept) = self.cloneable.drain(..).partition(|x| ids.contains(&x.0));
+        split.cloneable = moved;
+        self.cloneable = kept;
+
+        #[cfg(feature = "serialize")]
+        {
+            let (moved, kept) = self.serializable
+                .drain(..)
+                .partition(|x| ids.contains(&x.id));
+            split.serializable = moved;
+            self.serializable = kept;
+        }
+
+        split
+    }
+
+    /// Registers a hook which is called with every resource
+    /// of type `T` (regardless of its id) when the container is
+    /// cleared or dropped.
+    ///
+    /// Hooks are run in descending priority order (hooks with
+    /// equal priority in registration order), before any resource
+    /// is deallocated. Afterwards, resources with hooks are dropped
+    /// in the same order, followed by all other resources.
+    ///
+    /// Hooks stay registered after `clear`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # struct Device; impl Device { fn shutdown(&mut self) {} }
+    /// # struct Buffers;
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(Device);
+    /// res.add(Buffers);
+    ///
+    /// // The device has to be shut down before the buffers are freed
+    /// res.on_drop(10, |device: &mut Device| device.shutdown());
+    /// res.on_drop(0, |_: &mut Buffers| {});
+    ///
+    /// res.clear();
+    /// ```
+    pub fn on_drop<T, F>(&mut self, priority: i32, mut f: F)
+        where T: Resource,
+              F: FnMut(&mut T) + Send + 'static
+    {
+        let hook = DropHook {
+            priority: priority,
+            type_id: TypeId::of::<T>(),
+            hook: Box::new(move |r: &mut Resource| unsafe { f(r.downcast_mut_unchecked()) }),
+        };
+
+        let index = self.drop_hooks
+            .iter()
+            .position(|x| x.priority < priority)
+            .unwrap_or(self.drop_hooks.len());
+        self.drop_hooks.insert(index, hook);
+    }
+
+    /// Registers an observer, which is notified whenever
+    /// a resource is added or removed.
+    ///
+    /// This allows e.g. editors to keep a list of the
+    /// resources up to date without polling.
+    pub fn add_observer<O>(&mut self, observer: O)
+        where O: ResourceObserver + 'static
+    {
+        self.observers.push(Box::new(observer));
+    }
+
+    /// Registers `f` to be called whenever a resource
+    /// of type `T` is added (see `add_observer`).
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use std::sync::{Arc, Mutex};
+    /// use shred::{ResourceId, Resources};
+    ///
+    /// let added = Arc::new(Mutex::new(Vec::new()));
+    ///
+    /// let mut res = Resources::new();
+    /// let list = added.clone();
+    /// res.on_insert(move |id: ResourceId, value: &u32| {
+    ///     list.lock().unwrap().push((id.1, *value));
+    /// });
+    ///
+    /// res.add_with_id(5u32, 2);
+    /// res.add(1u64);
+    ///
+    /// assert_eq!(*added.lock().unwrap(), vec![(2, 5)]);
+    /// ```
+    pub fn on_insert<T, F>(&mut self, mut f: F)
+        where T: Resource,
+              F: FnMut(ResourceId, &T) + Send + 'static
+    {
+        self.add_observer(TypedObserver {
+                              on_insert: true,
+                              type_id: TypeId::of::<T>(),
+                              f: move |id, r: &Resource| unsafe {
+                                  f(id, r.downcast_ref_unchecked())
+                              },
+                          });
+    }
+
+    /// Registers `f` to be called whenever a resource
+    /// of type `T` is removed, before it's dropped
+    /// (see `add_observer`).
+    pub fn on_remove<T, F>(&mut self, mut f: F)
+        where T: Resource,
+              F: FnMut(ResourceId, &T) + Send + 'static
+    {
+        self.add_observer(TypedObserver {
+                              on_insert: false,
+                              type_id: TypeId::of::<T>(),
+                              f: move |id, r: &Resource| unsafe {
+                                  f(id, r.downcast_ref_unchecked())
+                              },
+                          });
+    }
+
+    /// Starts a new scope, in which resources
+    /// can be shadowed with `shadow`.
+    ///
+    /// Scopes can be nested.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(1u32);
+    ///
+    /// res.push_scope();
+    /// res.shadow(2u32, 0);
+    /// res.shadow(5u64, 0);
+    /// assert_eq!(*res.fetch::<u32>(0), 2);
+    ///
+    /// res.pop_scope();
+    /// assert_eq!(*res.fetch::<u32>(0), 1);
+    /// assert!(res.try_fetch::<u64>(0).is_none());
+    /// ```
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Replaces the resource of type `R` with the given
+    /// id by `r` until the current scope is popped.
+    ///
+    /// The resource doesn't have to exist; if it doesn't,
+    /// it's removed again when the scope is popped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no scope was pushed.
+    pub fn shadow<R>(&mut self, r: R, id: usize)
+        where R: Resource
+    {
+        let res_id = ResourceId::new_with_id::<R>(id);
+        self.register_type_name::<R>();
+
+        assert!(!self.scopes.is_empty(), "No scope pushed");
+        let original = self.insert_cell(res_id, TrustCell::new(Box::new(r)));
+
+        let scope = self.scopes.last_mut().expect("No scope pushed");
+        if !scope.iter().any(|x| x.0 == res_id) {
+            scope.push((res_id, original));
+        }
+    }
+
+    /// Ends the current scope, dropping the values
+    /// shadowing resources and restoring the originals.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no scope was pushed.
+    pub fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("No scope pushed");
+
+        for (id, original) in scope {
+            match original {
+                Some(cell) => {
+                    self.insert_cell(id, cell);
+                }
+                None => {
+                    self.remove_cell(id);
+                }
+            }
+        }
+    }
+
+    /// Removes all resources (including thread-local ones),
+    /// respecting the order specified with `on_drop`.
+    ///
+    /// All scopes are ended without restoring the shadowed
+    /// resources, which are dropped (and passed to the hooks)
+    /// together with the others.
+    ///
+    /// # Panics
+    ///
+    /// Panics if thread-local resources are cleared on another thread
+    /// than the one which added them. They're leaked in this case.
+    pub fn clear(&mut self) {
+        self.generation += 1;
+
+        let mut shadowed: Vec<_> = self.scopes
+            .drain(..)
+            .flatten()
+            .filter_map(|(id, cell)| cell.map(|cell| (id, cell)))
+            .collect();
+
+        // The hooks have been sorted from the start, so the resources are
+        // collected in the order of the first hook of their type. The flag
+        // tells whether the resource was stored or shadowed.
+        let mut hooked = Vec::new();
+
+        for hook in &self.drop_hooks {
+            for id in self.resources.ids() {
+                if id.0 == hook.type_id {
+                    let cell = self.resources.remove(id).expect("Storage lost a resource").0;
+                    self.last_version = max(self.last_version, cell.version());
+                    hooked.push((id, cell, true));
+                }
+            }
+
+            let (matching, rest): (Vec<_>, Vec<_>) =
+                shadowed.into_iter().partition(|x| (x.0).0 == hook.type_id);
+            hooked.extend(matching.into_iter().map(|(id, cell)| (id, cell, false)));
+            shadowed = rest;
+        }
+
+        for hook in &mut self.drop_hooks {
+            let type_id = hook.type_id;
+
+            for &mut (_, ref mut cell, _) in hooked.iter_mut().filter(|x| (x.0).0 == type_id) {
+                (hook.hook)(&mut **cell.get_mut());
+            }
+        }
+
+        for &mut (id, ref mut cell, stored) in &mut hooked {
+            if stored {
+                for observer in &mut self.observers {
+                    observer.removed(id, &**cell.get_mut());
+                }
+            }
+        }
+
+        drop(hooked);
+        drop(shadowed);
+
+        for id in self.resources.ids() {
+            self.remove_cell(id);
+        }
+
+        self.names.clear();
+
+        #[cfg(feature = "std")]
+        drop_thread_local(take(&mut self.thread_local).into_iter().map(|x| x.1));
+    }
+
+    /// Returns the ids of all registered resources,
+    /// excluding thread-local ones.
+    pub fn ids(&self) -> Vec<ResourceId> {
+        self.resources.ids()
+    }
+
+    /// Registers the resource of type `T` with the given id
+    /// to be copied by `snapshot`.
+    ///
+    /// Registering the same resource twice has no effect.
+    pub fn register_cloneable<T>(&mut self, id: usize)
+        where T: Clone + Resource
+    {
+        fn clone<T: Clone + Resource>(r: &Resource) -> Box<Resource> {
+            Box::new(r.downcast_ref::<T>()
+                         .expect("Resource stored with a wrong type id")
+                         .clone())
+        }
+
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        if !self.cloneable.iter().any(|x| x.0 == res_id) {
+            self.cloneable.push((res_id, clone::<T>));
+        }
+    }
+
+    /// Copies all resources registered with `register_cloneable`
+    /// which currently exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of them is being accessed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(5u32);
+    /// res.register_cloneable::<u32>(0);
+    ///
+    /// let snapshot = res.snapshot();
+    /// *res.fetch_mut::<u32>(0) += 1;
+    ///
+    /// res.restore(&snapshot);
+    /// assert_eq!(*res.fetch::<u32>(0), 5);
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let resources = self.cloneable
+            .iter()
+            .filter_map(|&(id, clone)| {
+                            self.resources
+                                .get(id)
+                                .map(|cell| (id, clone(&**cell.0.borrow()), clone))
+                        })
+            .collect();
+
+        Snapshot { resources: resources }
+    }
+
+    /// Resets all resources registered with `register_cloneable`
+    /// to their state in `snapshot`.
+    ///
+    /// Registered resources which didn't exist when the snapshot
+    /// was taken are removed, removed ones are added again.
+    /// Other resources are not affected. The snapshot can
+    /// be restored multiple times.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let removed: Vec<_> = self.cloneable
+            .iter()
+            .map(|&(id, _)| id)
+            .filter(|&id| !snapshot.resources.iter().any(|x| x.0 == id))
+            .collect();
+
+        for id in removed {
+            self.remove_cell(id);
+        }
+
+        for &(id, ref r, clone) in &snapshot.resources {
+            self.insert_cell(id, TrustCell::new(clone(&**r)));
+        }
+    }
+
+    /// Registers the resource of type `T` with the given id
+    /// to be serialized under `key`.
+    ///
+    /// Only available with the `serialize` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is already registered.
+    #[cfg(feature = "serialize")]
+    pub fn register_serializable<T>(&mut self, key: &str, id: usize)
+        where T: ::serde::de::DeserializeOwned + Resource + ::serde::Serialize
+    {
+        assert!(!self.serializable.iter().any(|x| x.key == key),
+                "Tried to register a key which is already registered");
+
+        let entry = Serializable::new::<T>(key, ResourceId::new_with_id::<T>(id));
+        self.register_type_name::<T>();
+        self.serializable.push(entry);
+    }
+
+    /// Serializes all resources registered with
+    /// `register_serializable` which currently exist,
+    /// as a map from their keys to their values.
+    ///
+    /// Only available with the `serialize` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if one of them is being accessed mutably.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate serde_json;
+    /// # extern crate shred;
+    /// # use shred::Resources;
+    /// # fn main() {
+    /// let mut res = Resources::new();
+    /// res.add(5u32);
+    /// res.register_serializable::<u32>("score", 0);
+    ///
+    /// let mut out = Vec::new();
+    /// res.serialize(&mut serde_json::Serializer::new(&mut out)).unwrap();
+    /// assert_eq!(out, b"{\"score\":5}");
+    ///
+    /// res.deserialize(&mut serde_json::Deserializer::from_slice(b"{\"score\":7}")).unwrap();
+    /// assert_eq!(*res.fetch::<u32>(0), 7);
+    /// # }
+    /// ```
+    #[cfg(feature = "serialize")]
+    pub fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+        where Ser: ::serde::Serializer
+    {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+
+        for entry in &self.serializable {
+            if let Some(cell) = self.resources.get(entry.id) {
+                let r = cell.0.borrow();
+                map.serialize_entry(&entry.key, (entry.serialize)(&**r))?;
+            }
+        }
+
+        map.end()
+    }
+
+    /// Deserializes a map created by `serialize`, adding
+    /// the contained resources or replacing existing ones.
+    ///
+    /// Registered resources missing from the map are not
+    /// affected. If deserializing fails, no resource is changed.
+    ///
+    /// Only available with the `serialize` feature.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the map contains a key which isn't registered,
+    /// in addition to the errors of the deserializer.
+    #[cfg(feature = "serialize")]
+    pub fn deserialize<'de, D>(&mut self, deserializer: D) -> Result<(), D::Error>
+        where D: ::serde::Deserializer<'de>
+    {
+        let resources =
+            deserializer
+                .deserialize_map(ResourcesVisitor { entries: &self.serializable })?;
+
+        for (id, r) in resources {
+            self.insert_cell(id, TrustCell::new(r));
+        }
+
+        Ok(())
+    }
+
+    /// Creates a read-only view of this container.
+    ///
+    /// See [`ResourcesView`] for details.
+    ///
+    /// [`ResourcesView`]: struct.ResourcesView.html
+    pub fn view(&self) -> ResourcesView<S> {
+        ResourcesView { res: self }
+    }
+
+    /// Fetches the resource with the specified type `T`.
+    /// The id is useful if you don't define your resources
+    /// in Rust or you want a more dynamic resource handling.
+    /// By default, the `#[derive(SystemData)]` passes `()`
+    /// as id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is being accessed mutably.
+    /// Also panics if there is no such resource.
+    ///
+    /// The panic message names the type of the resource. With the
+    /// `diagnostics` feature, it also contains the location and the
+    /// system of the last borrow (see `TrustCell::last_borrow`).
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch<T>(&self, id: usize) -> Fetch<T>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+        let c = self.typed_cell::<T>(id);
+
+        match c.try_borrow() {
+            Ok(inner) => Fetch::new(inner, res_id),
+            Err(_) => Self::borrow_conflict(type_name::<T>(), res_id, c, false),
+        }
+    }
+
+    /// Like `fetch`, but returns `None` instead of panicking
+    /// if there is no such resource or it is being accessed mutably.
+    pub fn try_fetch<T>(&self, id: usize) -> Option<Fetch<T>>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        self.resources
+            .get(res_id)
+            .and_then(|c| c.0.try_borrow().ok())
+            .map(|inner| Fetch::new(inner, res_id))
+    }
+
+    /// Fetches the resource with the specified type `T` mutably.
+    ///
+    /// Please see `fetch` for details.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_mut<T>(&self, id: usize) -> FetchMut<T>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+        let c = self.typed_cell::<T>(id);
+
+        match c.try_borrow_mut() {
+            Ok(inner) => FetchMut::new(inner, res_id),
+            Err(_) => Self::borrow_conflict(type_name::<T>(), res_id, c, true),
+        }
+    }
+
+    /// Like `fetch_mut`, but returns `None` instead of panicking
+    /// if there is no such resource or it is already being accessed.
+    pub fn try_fetch_mut<T>(&self, id: usize) -> Option<FetchMut<T>>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        self.resources
+            .get(res_id)
+            .and_then(|c| c.0.try_borrow_mut().ok())
+            .map(|inner| FetchMut::new(inner, res_id))
+    }
+
+    /// Tries to fetch the resource with the specified type `T` mutably,
+    /// retrying with an exponential backoff while it is borrowed.
+    ///
+    /// Returns `None` if the resource is still borrowed after
+    /// `policy.max_retries` retries. This is useful for best-effort
+    /// access under contention without blocking indefinitely.
+    ///
+    /// Only available with the `parking` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such resource.
+    #[cfg(feature = "parking")]
+    pub fn fetch_mut_retry<T>(&self, id: usize, policy: BackoffPolicy) -> Option<FetchMut<T>>
+        where T: Resource
+    {
+        use std::thread::sleep;
+
+        let c = self.typed_cell::<T>(id);
+        let mut delay = policy.base_delay;
+
+        for attempt in 0..=policy.max_retries {
+            if let Ok(inner) = c.try_borrow_mut() {
+                return Some(FetchMut::new(inner, ResourceId::new_with_id::<T>(id)));
+            }
+
+            if attempt < policy.max_retries {
+                sleep(delay);
+                delay = delay.checked_mul(2).unwrap_or(delay);
+            }
+        }
+
+        None
+    }
+
+    /// Fetches the resource with the specified type `T`, waiting
+    /// up to `timeout` while it is being accessed mutably.
+    ///
+    /// Unlike `fetch`, a conflicting borrow of another thread doesn't
+    /// panic, which suits long-running processes accessing resources
+    /// from several threads. See `TrustCell::borrow_blocking` for
+    /// how waiting and deadlock detection work.
+    ///
+    /// Only available with the `parking` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such resource.
+    #[cfg(feature = "parking")]
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_blocking<T>(&self, id: usize, timeout: Duration) -> Result<Fetch<T>, BlockingError>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        self.typed_cell::<T>(id)
+            .borrow_blocking(timeout)
+            .map(|inner| Fetch::new(inner, res_id))
+    }
+
+    /// Fetches the resource with the specified type `T` mutably,
+    /// waiting up to `timeout` while it is being accessed.
+    ///
+    /// Please see `fetch_blocking` for details.
+    ///
+    /// Only available with the `parking` feature.
+    #[cfg(feature = "parking")]
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_mut_blocking<T>(&self,
+                                 id: usize,
+                                 timeout: Duration)
+                                 -> Result<FetchMut<T>, BlockingError>
+        where T: Resource
+    {
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        self.typed_cell::<T>(id)
+            .borrow_mut_blocking(timeout)
+            .map(|inner| FetchMut::new(inner, res_id))
+    }
+
+    /// Returns the dense index of the resource with the given id,
+    /// or `None` if there is no such resource or the storage
+    /// doesn't support indices (see `ResourceStorage::index`).
+    ///
+    /// Fetching with the index using `fetch_indexed` skips
+    /// hashing the id, which is useful if a resource is
+    /// fetched many times. The index stays valid if the
+    /// resource is removed and added again.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use shred::{ResourceId, Resources};
+    /// let mut res = Resources::new();
+    /// res.add(5u32);
+    ///
+    /// let index = res.index(ResourceId::new::<u32>()).unwrap();
+    ///
+    /// for _ in 0..3 {
+    ///     *res.fetch_indexed_mut::<u32>(index) += 1;
+    /// }
+    ///
+    /// assert_eq!(*res.fetch_indexed::<u32>(index), 8);
+    /// ```
+    pub fn index(&self, id: ResourceId) -> Option<ResourceIndex> {
+        if !self.has_value(id) {
+            return None;
+        }
+
+        self.resources
+            .index(id)
+            .map(|index| ResourceIndex { id: id, index: index })
+    }
+
+    /// Fetches the resource with the given index.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index belongs to a resource of a
+    /// different type, or for the reasons listed in `fetch`.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_indexed<T>(&self, index: ResourceIndex) -> Fetch<T>
+        where T: Resource
+    {
+        let c = self.indexed_cell::<T>(index);
+
+        match c.try_borrow() {
+            Ok(inner) => Fetch::new(inner, index.id),
+            Err(_) => Self::borrow_conflict(type_name::<T>(), index.id, c, false),
+        }
+    }
+
+    /// Fetches the resource with the given index mutably.
+    ///
+    /// Please see `fetch_indexed` for details.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_indexed_mut<T>(&self, index: ResourceIndex) -> FetchMut<T>
+        where T: Resource
+    {
+        let c = self.indexed_cell::<T>(index);
+
+        match c.try_borrow_mut() {
+            Ok(inner) => FetchMut::new(inner, index.id),
+            Err(_) => Self::borrow_conflict(type_name::<T>(), index.id, c, true),
+        }
+    }
+
+    /// Returns a mutable reference to the resource
+    /// with the specified type `T`, or `None` if
+    /// there is no such resource.
+    ///
+    /// No runtime checks are necessary, because this
+    /// requires exclusive access to the container. Writes
+    /// through the returned reference can't be tracked, so
+    /// this always counts as modification (see `version`),
+    /// even if the resource isn't changed.
+    pub fn get_mut<T>(&mut self, id: usize) -> Option<&mut T>
+        where T: Resource
+    {
+        self.get_raw_mut(ResourceId::new_with_id::<T>(id))
+            .map(|r| unsafe { r.downcast_mut_unchecked() })
+    }
+
+    /// Like `get_mut`, but returns the resource `res_id`
+    /// without knowing its type. This counts as
+    /// modification as well.
+    pub fn get_raw_mut(&mut self, res_id: ResourceId) -> Option<&mut Resource> {
+        self.resources
+            .get_mut(res_id)
+            .map(|cell| {
+                     let version = cell.0.version();
+                     cell.0.set_version(version + 1);
+
+                     &mut **cell.0.get_mut()
+                 })
+    }
+
+    /// Fetches the resource with the specified type `T`,
+    /// returning a handle which isn't tied to the lifetime
+    /// of a borrow, e.g. for async tasks or callbacks.
+    ///
+    /// The handle keeps the container alive; because it's
+    /// shared, it can't be modified until all handles are
+    /// dropped (see `Arc::get_mut`). The resource is
+    /// borrowed as long as the handle exists.
+    ///
+    /// This is an associated function, so it
+    /// can be called on an `Arc<Resources>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics for the same reasons as `fetch`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::sync::Arc;
+    /// use std::thread;
+    ///
+    /// use shred::Resources;
+    ///
+    /// let mut res = Resources::new();
+    /// res.add(5u32);
+    /// let res = Arc::new(res);
+    ///
+    /// let mut handle = Resources::fetch_owned_mut::<u32>(&res, 0);
+    /// thread::spawn(move || *handle += 1).join().unwrap();
+    ///
+    /// assert_eq!(*res.fetch::<u32>(0), 6);
+    /// ```
+    pub fn fetch_owned<T>(this: &Arc<Self>, id: usize) -> OwnedFetch<T, S>
+        where T: Resource
+    {
+        OwnedFetch {
+            inner: Self::shared(this).fetch(id),
+            res: this.clone(),
+        }
+    }
+
+    /// Like `fetch_owned`, but fetches the resource mutably.
+    ///
+    /// Please see `fetch_mut` for details.
+    pub fn fetch_owned_mut<T>(this: &Arc<Self>, id: usize) -> OwnedFetchMut<T, S>
+        where T: Resource
+    {
+        OwnedFetchMut {
+            inner: Self::shared(this).fetch_mut(id),
+            res: this.clone(),
+        }
+    }
+
+    /// Extends the lifetime of a shared container;
+    /// the returned reference may only be stored
+    /// next to a clone of the `Arc`.
+    fn shared(this: &Arc<Self>) -> &'static Self {
+        unsafe { &*(&**this as *const Self) }
+    }
+
+    /// Fetches the resource with the specified type id.
+    ///
+    /// Please see `fetch` for details.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_id(&self, id: TypeId, comp_id: usize) -> FetchId {
+        let res_id = ResourceId(id, comp_id);
+        let c = self.fetch_internal(id, comp_id);
+
+        match c.try_borrow() {
+            Ok(inner) => FetchId::new(inner, res_id),
+            Err(_) => Self::borrow_conflict(self.type_name(id), res_id, c, false),
+        }
+    }
+
+    /// Like `fetch_id`, but returns `None` instead of panicking
+    /// if there is no such resource or it is being accessed mutably.
+    pub fn try_fetch_id(&self, id: TypeId, comp_id: usize) -> Option<FetchId> {
+        let res_id = ResourceId(id, comp_id);
+
+        self.resources
+            .get(res_id)
+            .and_then(|c| c.0.try_borrow().ok())
+            .map(|inner| FetchId::new(inner, res_id))
+    }
+
+    /// Fetches the resource with the specified type id mutably.
+    ///
+    /// Please see `fetch` for details.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_id_mut(&self, id: TypeId, comp_id: usize) -> FetchIdMut {
+        let res_id = ResourceId(id, comp_id);
+        let c = self.fetch_internal(id, comp_id);
+
+        match c.try_borrow_mut() {
+            Ok(inner) => FetchIdMut::new(inner, res_id),
+            Err(_) => Self::borrow_conflict(self.type_name(id), res_id, c, true),
+        }
+    }
+
+    /// Like `fetch_id_mut`, but returns `None` instead of panicking
+    /// if there is no such resource or it is already being accessed.
+    pub fn try_fetch_id_mut(&self, id: TypeId, comp_id: usize) -> Option<FetchIdMut> {
+        let res_id = ResourceId(id, comp_id);
+
+        self.resources
+            .get(res_id)
+            .and_then(|c| c.0.try_borrow_mut().ok())
+            .map(|inner| FetchIdMut::new(inner, res_id))
+    }
+
+    /// Fetches the resource registered as `name`.
+    ///
+    /// Please see `fetch` for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name isn't registered, in
+    /// addition to the reasons of `fetch`.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_by_name(&self, name: &str) -> FetchId {
+        let res_id = self.named_id(name);
+
+        self.fetch_id(res_id.0, res_id.1)
+    }
+
+    /// Like `fetch_by_name`, but returns `None` instead of panicking
+    /// if the name isn't registered or the resource is being
+    /// accessed mutably.
+    pub fn try_fetch_by_name(&self, name: &str) -> Option<FetchId> {
+        self.resource_id(name)
+            .and_then(|res_id| self.try_fetch_id(res_id.0, res_id.1))
+    }
+
+    /// Fetches the resource registered as `name` mutably.
+    ///
+    /// Please see `fetch_by_name` for details.
+    #[cfg_attr(feature = "diagnostics", track_caller)]
+    pub fn fetch_by_name_mut(&self, name: &str) -> FetchIdMut {
+        let res_id = self.named_id(name);
+
+        self.fetch_id_mut(res_id.0, res_id.1)
+    }
+
+    /// Like `fetch_by_name_mut`, but returns `None` instead
+    /// of panicking if the name isn't registered or the
+    /// resource is already being accessed.
+    pub fn try_fetch_by_name_mut(&self, name: &str) -> Option<FetchIdMut> {
+        self.resource_id(name)
+            .and_then(|res_id| self.try_fetch_id_mut(res_id.0, res_id.1))
+    }
+
+    /// Adds a new thread-local resource to this container.
+    ///
+    /// In contrast to `add`, the resource does not need to
+    /// be `Send` or `Sync`, but it can only be fetched from
+    /// the thread calling this method.
+    ///
+    /// This method calls `add_thread_local_with_id` with
+    /// 0 for the id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is already registered.
+    #[cfg(feature = "std")]
+    pub fn add_thread_local<T>(&mut self, r: T)
+        where T: StdAny
+    {
+        self.add_thread_local_with_id(r, 0)
+    }
+
+    /// Like `add_thread_local()`, but allows specifying
+    /// and id while `add_thread_local()` assumes `0`.
+    #[cfg(feature = "std")]
+    pub fn add_thread_local_with_id<T>(&mut self, r: T, id: usize)
+        where T: StdAny
+    {
+        use std::collections::hash_map::Entry;
+
+        let entry = self.thread_local.entry(ResourceId(TypeId::of::<T>(), id));
+
+        if let Entry::Vacant(e) = entry {
+            e.insert(LocalCell {
+                         owner: thread::current().id(),
+                         cell: TrustCell::new(Box::new(r)),
+                     });
+        } else {
+            panic!("Tried to add a thread-local resource though it is already registered");
+        }
+    }
+
+    /// Adds a resource which is neither `Send` nor `Sync`,
+    /// e.g. a window context or an `Rc` based handle.
+    ///
+    /// This is another name for `add_thread_local`: the resource
+    /// can only be fetched (with `NonSendFetch` or `NonSendFetchMut`)
+    /// from the thread calling this method, which is checked
+    /// on every fetch, and only thread-local systems can use it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the resource is already registered.
+    #[cfg(feature = "std")]
+    pub fn add_non_send<T>(&mut self, r: T)
+        where T: StdAny
+    {
+        self.add_thread_local(r)
+    }
+
+    /// Like `add_non_send()`, but allows specifying
+    /// and id while `add_non_send()` assumes `0`.
+    #[cfg(feature = "std")]
+    pub fn add_non_send_with_id<T>(&mut self, r: T, id: usize)
+        where T: StdAny
+    {
+        self.add_thread_local_with_id(r, id)
+    }
+
+    /// Returns true if the specified type / id combination
+    /// is registered as a thread-local resource.
+    #[cfg(feature = "std")]
+    pub fn has_thread_local(&self, res_id: ResourceId) -> bool {
+        self.thread_local.contains_key(&res_id)
+    }
+
+    /// Fetches the thread-local resource with the specified type `T`.
+    ///
+    /// # Panics
+    ///
+    /// * if the current thread is not the one which added the resource
+    /// * if the resource is being accessed mutably
+    /// * if there is no such resource
+    #[cfg(feature = "std")]
+    pub fn fetch_thread_local<T>(&self, id: usize) -> FetchLocal<T>
+        where T: StdAny
+    {
+        let c = self.fetch_local_internal(TypeId::of::<T>(), id);
+
+        FetchLocal {
+            inner: c.borrow(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Fetches the thread-local resource with the specified type `T` mutably.
+    ///
+    /// Please see `fetch_thread_local` for details.
+    #[cfg(feature = "std")]
+    pub fn fetch_thread_local_mut<T>(&self, id: usize) -> FetchLocalMut<T>
+        where T: StdAny
+    {
+        let c = self.fetch_local_internal(TypeId::of::<T>(), id);
+
+        FetchLocalMut {
+            inner: c.borrow_mut(),
+            phantom: PhantomData,
+        }
+    }
+
+    fn named_id(&self, name: &str) -> ResourceId {
+        self.resource_id(name)
+            .expect("No resource with the given name")
+    }
+
+    fn fetch_internal(&self, id: TypeId, cid: usize) -> &TrustCell<Box<Resource>> {
+        match self.resources.get(ResourceId(id, cid)) {
+            Some(cell) => &cell.0,
+            None => {
+                panic!("No resource with the given id: `{}` ({})",
+                       self.type_name(id),
+                       DynamicId::from_usize(cid))
+            }
+        }
+    }
+
+    /// Like `fetch_internal`, but also names
+    /// types which were never added.
+    fn typed_cell<T: Resource>(&self, id: usize) -> &TrustCell<Box<Resource>> {
+        let res_id = ResourceId::new_with_id::<T>(id);
+
+        #[cfg(feature = "std")]
+        {
+            if let Some(cell) = self.resolved_cell(res_id) {
+                return cell;
+            }
+        }
+
+        match self.resources.get(res_id) {
+            Some(cell) => &cell.0,
+            None => {
+                panic!("No resource with the given id: `{}` ({})",
+                       type_name::<T>(),
+                       DynamicId::from_usize(id))
+            }
+        }
+    }
+
+    /// Looks `id` up in the resolution of the system running on this
+    /// thread (see `run_resolved`), recording it during its first run.
+    #[cfg(feature = "std")]
+    fn resolved_cell(&self, id: ResourceId) -> Option<&TrustCell<Box<Resource>>> {
+        RESOLUTION.with(|current| {
+            // The pointer is only set while `run_resolved`
+            // is borrowing the resolution.
+            let resolution = match unsafe { current.get().as_ref() } {
+                Some(resolution) if resolution.container == self.container => resolution,
+                _ => return None,
+            };
+
+            let next = resolution.next.get();
+            let mut indices = resolution.indices.borrow_mut();
+
+            let index = match indices.get(next) {
+                Some(&index) if index.id == id => index,
+                None if resolution.recording.get() => {
+                    let index = ResourceIndex {
+                        id: id,
+                        index: self.resources.index(id)?,
+                    };
+                    indices.push(index);
+
+                    index
+                }
+                _ => return None,
+            };
+
+            resolution.next.set(next + 1);
+
+            // Checked anyway, so a resolution can never
+            // return the cell of another resource
+            match self.resources.get_index(index.index) {
+                Some((stored, cell)) if stored == id => Some(&cell.0),
+                _ => None,
+            }
+        })
+    }
+
+    fn indexed_cell<T: Resource>(&self, index: ResourceIndex) -> &TrustCell<Box<Resource>> {
+        assert!(index.id.0 == TypeId::of::<T>(),
+                "Tried to fetch `{}` with the index of `{}`",
+                type_name::<T>(),
+                self.type_name(index.id.0));
+
+        match self.resources.get_index(index.index) {
+            Some((id, cell)) if id == index.id => &cell.0,
+            _ => panic!("No resource with the given id: `{}` ({})", type_name::<T>(), index.id.1),
+        }
+    }
+
+    /// Inserts a cell into the storage, which is a structural
+    /// change (see `generation`), and notifies the observers.
+    ///
+    /// The cell gets a version higher than all
+    /// versions of the cells removed before.
+    fn insert_cell(&mut self,
+                   id: ResourceId,
+                   mut cell: TrustCell<Box<Resource>>)
+                   -> Option<TrustCell<Box<Resource>>> {
+        self.generation += 1;
+
+        if let Some(old) = self.resources.get(id) {
+            self.last_version = max(self.last_version, old.0.version());
+        }
+
+        self.last_version += 1;
+        cell.set_version(self.last_version);
+        let mut old = self.resources.insert(id, ResourceCell(cell)).map(|old| old.0);
+
+        if !self.observers.is_empty() {
+            if let Some(ref mut old) = old {
+                for observer in &mut self.observers {
+                    observer.removed(id, &**old.get_mut());
+                }
+            }
+
+            let cell = self.resources.get(id).expect("Storage lost a resource");
+            let new = cell.0.borrow();
+            for observer in &mut self.observers {
+                observer.inserted(id, &**new);
+            }
+        }
+
+        old
+    }
+
+    fn remove_cell(&mut self, id: ResourceId) -> Option<TrustCell<Box<Resource>>> {
+        self.generation += 1;
+        let mut cell = self.resources.remove(id).map(|cell| cell.0);
+
+        if let Some(ref mut cell) = cell {
+            self.last_version = max(self.last_version, cell.version());
+
+            for observer in &mut self.observers {
+                observer.removed(id, &**cell.get_mut());
+            }
+        }
+
+        cell
+    }
+
+    fn register_type_name<T: Resource>(&mut self) {
+        self.type_names.insert(TypeId::of::<T>(), type_name::<T>());
+    }
+
+    fn type_name(&self, id: TypeId) -> &'static str {
+        self.type_names.get(&id).cloned().unwrap_or("<unknown type>")
+    }
+
+    /// Panics because the resource is borrowed already.
+    #[cfg_attr(not(feature = "diagnostics"), allow(unused_variables))]
+    fn borrow_conflict(name: &str,
+                       res_id: ResourceId,
+                       cell: &TrustCell<Box<Resource>>,
+                       mutable: bool)
+                       -> ! {
+        #[cfg(feature = "diagnostics")]
+        let note = cell.last_borrow()
+            .map_or(String::new(), |x| format!(", last borrowed {}", x));
+        #[cfg(not(feature = "diagnostics"))]
+        let note = "";
+
+        if mutable {
+            panic!("Already borrowed: `{}` ({}){}", name, DynamicId::from_usize(res_id.1), note);
+        } else {
+            panic!("Already borrowed mutably: `{}` ({}){}",
+                   name,
+                   DynamicId::from_usize(res_id.1),
+                   note);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn fetch_local_internal(&self, id: TypeId, cid: usize) -> &TrustCell<Box<StdAny>> {
+        let local = self.thread_local
+            .get(&ResourceId(id, cid))
+            .expect("No thread-local resource with the given id");
+
+        assert!(local.owner == thread::current().id(),
+                "Tried to fetch a thread-local resource from another thread");
+
+        &local.cell
+    }
+}
+
+type CloneFn = fn(&Resource) -> Box<Resource>;
+
+/// Copies of resources, created with [`Resources::snapshot`].
+///
+/// [`Resources::snapshot`]: struct.Resources.html#method.snapshot
+pub struct Snapshot {
+    resources: Vec<(ResourceId, Box<Resource>, CloneFn)>,
+}
+
+impl Snapshot {
+    /// Returns the ids of the copied resources.
+    pub fn ids(&self) -> Vec<ResourceId> {
+        self.resources.iter().map(|x| x.0).collect()
+    }
+}
+
+/// Return value of [`Resources::fetch_owned`].
+///
+/// [`Resources::fetch_owned`]: struct.Resources.html#method.fetch_owned
+pub struct OwnedFetch<T, S = DefaultStorage>
+    where T: 'static,
+          S: ResourceStorage + 'static
+{
+    // Has to be dropped before the container.
+    inner: Fetch<'static, T>,
+    #[allow(dead_code)]
+    res: Arc<Resources<S>>,
+}
+
+impl<T, S> Deref for OwnedFetch<T, S>
+    where T: Resource,
+          S: ResourceStorage
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// Return value of [`Resources::fetch_owned_mut`].
+///
+/// [`Resources::fetch_owned_mut`]: struct.Resources.html#method.fetch_owned_mut
+pub struct OwnedFetchMut<T, S = DefaultStorage>
+    where T: 'static,
+          S: ResourceStorage + 'static
+{
+    // Has to be dropped before the container.
+    inner: FetchMut<'static, T>,
+    #[allow(dead_code)]
+    res: Arc<Resources<S>>,
+}
+
+impl<T, S> Deref for OwnedFetchMut<T, S>
+    where T: Resource,
+          S: ResourceStorage
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T, S> DerefMut for OwnedFetchMut<T, S>
+    where T: Resource,
+          S: ResourceStorage
+{
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+/// A read-only view of a [`Resources`] container,
+/// created with [`Resources::view`].
+///
+/// The view is `Copy`, so it can be passed into multiple closures
+/// (e.g. tasks spawned with `rayon::scope`) without worrying about
+/// the lifetime of the `&Resources` it was created from.
+/// It only allows shared borrows, so it's guaranteed
+/// at compile time that no resource can be written through it:
+///
+/// ```rust,compile_fail
+/// # use shred::Resources;
+/// let mut res = Resources::new();
+/// res.add(5i32);
+///
+/// let view = res.view();
+/// view.fetch_mut::<i32>(0);
+/// ```
+///
+/// [`Resources`]: struct.Resources.html
+/// [`Resources::view`]: struct.Resources.html#method.view
+pub struct ResourcesView<'a, S = DefaultStorage>
+    where S: ResourceStorage + 'a
+{
+    res: &'a Resources<S>,
+}
+
+impl<'a, S> Clone for ResourcesView<'a, S>
+    where S: ResourceStorage + 'a
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, S> Copy for ResourcesView<'a, S> where S: ResourceStorage + 'a {}
+
+impl<'a, S> ResourcesView<'a, S>
+    where S: ResourceStorage + 'a
+{
+    /// Fetches the resource with the specified type `T`.
+    ///
+    /// Please see [`Resources::fetch`] for details.
+    ///
+    /// [`Resources::fetch`]: struct.Resources.html#method.fetch
+    pub fn fetch<T>(&self, id: usize) -> Fetch<'a, T>
+        where T: Resource
+    {
+        self.res.fetch(id)
+    }
+
+    /// Like `fetch`, but returns `None` instead of panicking.
+    ///
+    /// Please see [`Resources::try_fetch`] for details.
+    ///
+    /// [`Resources::try_fetch`]: struct.Resources.html#method.try_fetch
+    pub fn try_fetch<T>(&self, id: usize) -> Option<Fetch<'a, T>>
+        where T: Resource
+    {
+        self.res.try_fetch(id)
+    }
+
+    /// Returns true if the specified type / id combination
+    /// is registered.
+    pub fn has_value(&self, res_id: ResourceId) -> bool {
+        self.res.has_value(res_id)
+    }
+
+    /// Returns the ids of all registered resources,
+    /// excluding thread-local ones.
+    pub fn ids(&self) -> Vec<ResourceId> {
+        self.res.ids()
+    }
+}
+
+impl<S> Drop for Resources<S>
+    where S: ResourceStorage
+{
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Res;
+
+    #[test]
+    fn res_id() {
+        assert_eq!(ResourceId::new::<Res>(), ResourceId::new_with_id::<Res>(0));
+        assert_eq!(ResourceId::new_with_id::<Res>(5),
+                   ResourceId(TypeId::of::<Res>(), 5));
+    }
+
+    #[test]
+    fn fetch_aspects() {
+        assert_eq!(Fetch::<Res>::reads(4),
+                   vec![ResourceId::new_with_id::<Res>(4)]);
+        assert_eq!(Fetch::<Res>::writes(8), vec![]);
+
+        let mut res = Resources::new();
+        res.add_with_id(Res, 56);
+        Fetch::<Res>::fetch(&res, 56);
+    }
+
+    #[test]
+    fn reborrow() {
+        fn sum(a: Read<i32>, b: Read<i32>) -> i32 {
+            *a + *b
+        }
+
+        let mut res = Resources::new();
+        res.add(5i32);
+
+        let fetched = res.fetch::<i32>(0);
+        let read = fetched.reborrow();
+
+        assert_eq!(sum(read, read), 10);
+    }
+
+    #[test]
+    fn fetch_mut_aspects() {
+        assert_eq!(FetchMut::<Res>::reads(4), vec![]);
+        assert_eq!(FetchMut::<Res>::writes(8),
+                   vec![ResourceId::new_with_id::<Res>(8)]);
+
+        let mut res = Resources::new();
+        res.add_with_id(Res, 56);
+        FetchMut::<Res>::fetch(&res, 56);
+    }
+
+    #[test]
+    fn add() {
+        let mut res = Resources::new();
+        res.add(Res);
+
+        assert!(res.has_value(ResourceId::new::<Res>()));
+        assert!(!res.has_value(ResourceId::new_with_id::<Res>(1)));
+        assert!(!res.has_value(ResourceId::new_with_id::<Res>(1)));
+    }
+
+    #[allow(unused)]
+    #[test]
+    #[should_panic(expected = "Already borrowed")]
+    fn read_write_fails() {
+
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let read = res.fetch::<Res>(0);
+        let write = res.fetch_mut::<Res>(0);
+    }
+
+    #[allow(unused)]
+    #[test]
+    #[should_panic(expected = "Already borrowed mutably")]
+    fn write_read_fails() {
+
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let write = res.fetch_mut::<Res>(0);
+        let read = res.fetch::<Res>(0);
+    }
+
+    #[test]
+    fn panic_messages() {
+        use std::panic::{AssertUnwindSafe, catch_unwind};
+
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let message = |f: &Fn()| {
+            let payload = catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+
+            payload.downcast::<String>().map(|x| *x).unwrap()
+        };
+
+        let missing = message(&|| { res.fetch::<u32>(1); });
+        assert_eq!(missing, "No resource with the given id: `u32` (1)");
+
+        let missing = message(&|| { res.fetch_id(TypeId::of::<u32>(), 0); });
+        assert_eq!(missing, "No resource with the given id: `<unknown type>` (0)");
+
+        let named = DynamicId::from("panic_messages").to_usize();
+        let missing = message(&|| { res.fetch::<u32>(named); });
+        assert_eq!(missing, "No resource with the given id: `u32` (\"panic_messages\")");
+
+        let _write = res.fetch_mut::<Res>(0);
+        let conflict = message(&|| { res.fetch_id(TypeId::of::<Res>(), 0); });
+        assert!(conflict.starts_with("Already borrowed mutably: `shred::res::tests::Res` (0)"));
+
+        #[cfg(feature = "diagnostics")]
+        assert!(conflict.contains(&format!(", last borrowed mutably at {}:", file!())));
+    }
+
+    #[test]
+    fn dynamic_id() {
+        let a = DynamicId::from("dynamic_id_a").to_usize();
+        let b = DynamicId::from(String::from("dynamic_id_b")).to_usize();
+
+        assert!(a != b);
+        assert_eq!(a, DynamicId::from("dynamic_id_a").to_usize());
+        assert_eq!(DynamicId::from(3).to_usize(), 3);
+
+        assert_eq!(DynamicId::from_usize(b), DynamicId::from("dynamic_id_b"));
+        assert_eq!(DynamicId::from_usize(3), DynamicId::Index(3));
+        assert_eq!(DynamicId::from_usize(b).to_string(), "\"dynamic_id_b\"");
+        assert_eq!(DynamicId::from_usize(DynamicId::FIRST_NAMED - 1),
+                   DynamicId::Index(DynamicId::FIRST_NAMED - 1));
+
+        static NAMED: NamedId = NamedId::new("dynamic_id_b");
+        assert_eq!(NAMED.get(), b);
+        assert_eq!(NAMED.get(), b);
+
+        let mut res = Resources::new();
+        res.add_with_id(1u32, a);
+        res.add_with_id(2u32, b);
+        assert_eq!(*res.fetch::<u32>(DynamicId::from("dynamic_id_b").to_usize()), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Integer ids must be below `DynamicId::FIRST_NAMED`")]
+    fn dynamic_id_named_range() {
+        DynamicId::from(DynamicId::FIRST_NAMED).to_usize();
+    }
+
+    #[cfg(feature = "parking")]
+    #[test]
+    fn fetch_mut_retry() {
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let policy = BackoffPolicy::new(Duration::new(0, 1_000), 3);
+
+        {
+            let _read = res.fetch::<Res>(0);
+            assert!(res.fetch_mut_retry::<Res>(0, policy).is_none());
+        }
+
+        assert!(res.fetch_mut_retry::<Res>(0, policy).is_some());
+    }
+
+    #[cfg(feature = "parking")]
+    #[test]
+    fn fetch_mut_retry_waits() {
+        use std::thread;
+
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let policy = BackoffPolicy::new(Duration::new(0, 1_000_000), 10);
+        let write = res.fetch_mut::<Res>(0);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::new(0, 2_000_000));
+                drop(write);
+            });
+
+            assert!(res.fetch_mut_retry::<Res>(0, policy).is_some());
+        });
+    }
+
+    #[cfg(feature = "parking")]
+    #[test]
+    fn fetch_blocking() {
+        use std::thread;
+
+        let mut res = Resources::new();
+        res.add(Res);
+
+        let timeout = Duration::new(1, 0);
+        let read = res.fetch::<Res>(0);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::new(0, 2_000_000));
+                drop(read);
+            });
+
+            assert!(res.fetch_mut_blocking::<Res>(0, timeout).is_ok());
+        });
+
+        let _write = res.fetch_mut::<Res>(0);
+        assert_eq!(res.fetch_blocking::<Res>(0, timeout).err(),
+                   Some(BlockingError::Deadlock));
+    }
+
+    #[test]
+    fn try_fetch() {
+        let mut res = Resources::new();
+        res.add(Res);
+
+        assert!(res.try_fetch::<Res>(0).is_some());
+        assert!(res.try_fetch::<Res>(1).is_none());
+
+        let _write = res.fetch_mut::<Res>(0);
+        assert!(res.try_fetch::<Res>(0).is_none());
+    }
+
+    #[test]
+    fn try_fetch_mut() {
+        let mut res = Resources::new();
+        res.add(Res);
+
+        assert!(res.try_fetch_mut::<Res>(0).is_some());
+        assert!(res.try_fetch_mut::<Res>(1).is_none());
+        assert!(res.try_fetch_id_mut(TypeId::of::<Res>(), 1).is_none());
+
+        {
+            let _read = res.fetch::<Res>(0);
+            assert!(res.try_fetch_mut::<Res>(0).is_none());
+            assert!(res.try_fetch_id(TypeId::of::<Res>(), 0).is_some());
+            assert!(res.try_fetch_id_mut(TypeId::of::<Res>(), 0).is_none());
+        }
+
+        let _write = res.fetch_mut::<Res>(0);
+        assert!(res.try_fetch_id(TypeId::of::<Res>(), 0).is_none());
+    }
+
+    #[test]
+    fn named() {
+        let mut res = Resources::new();
+        res.add(5u32);
+
+        let id = res.add_named("answer", 42u32);
+        assert_eq!(id, ResourceId::new_with_id::<u32>(1));
+        res.register_name("five", ResourceId::new::<u32>());
+
+        assert_eq!(res.fetch_by_name("five").downcast_ref::<u32>(), Some(&5));
+        *res.fetch_by_name_mut("answer")
+             .downcast_mut::<u32>()
+             .unwrap() += 1;
+        assert_eq!(*res.fetch::<u32>(1), 43);
+
+        {
+            let _write = res.fetch_mut::<u32>(0);
+            assert!(res.try_fetch_by_name("five").is_none());
+            assert!(res.try_fetch_by_name_mut("answer").is_some());
+        }
+
+        res.rename(id, ResourceId::new_with_id::<u32>(7)).unwrap();
+        assert_eq!(res.resource_id("answer"),
+                   Some(ResourceId::new_with_id::<u32>(7)));
+
+        res.remove::<u32>(7);
+        assert!(res.resource_id("answer").is_none());
+        assert!(res.try_fetch_by_name("answer").is_none());
+    }
+
+    #[test]
+    fn versions() {
+        let mut res = Resources::new();
+        res.add(5u32);
+
+        let id = ResourceId::new::<u32>();
+        let start = res.version(id).unwrap();
+        assert!(res.version(ResourceId::new::<Res>()).is_none());
+
+        let _ = *res.fetch_mut::<u32>(0);
+        assert!(!res.modified_since(id, start));
+
+        *res.fetch_mut::<u32>(0) += 1;
+        assert!(res.modified_since(id, start));
+        assert!(!res.modified_since(id, res.version(id).unwrap()));
+
+        let mut last = None;
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+        assert!(!Changed::<u32>::fetch(&res, 0).changed(&mut last));
+
+        *res.fetch_mut::<u32>(0) += 1;
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+
+        *res.get_mut::<u32>(0).unwrap() += 1;
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+
+        // Exclusive access counts even without writing
+        assert!(res.get_mut::<u32>(0).is_some());
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+        assert!(res.get_raw_mut(id).is_some());
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+        assert!(!Changed::<u32>::fetch(&res, 0).changed(&mut last));
+
+        // Replacing the resource doesn't reset the version
+        res.register_cloneable::<u32>(0);
+        let snapshot = res.snapshot();
+        *res.fetch_mut::<u32>(0) += 1;
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+        res.restore(&snapshot);
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+
+        assert_eq!(res.remove::<u32>(0), Some(8));
+        res.add(7u32);
+        assert!(Changed::<u32>::fetch(&res, 0).changed(&mut last));
+        assert!(!Changed::<u32>::fetch(&res, 0).changed(&mut last));
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut res = Resources::new();
+        res.add(5u32);
+        res.add(vec![1u8]);
+        res.add(Res);
+        res.register_cloneable::<u32>(0);
+        res.register_cloneable::<Vec<u8>>(0);
+        res.register_cloneable::<u64>(0);
+
+        let snapshot = res.snapshot();
+        assert_eq!(snapshot.ids().len(), 2);
+
+        for _ in 0..2 {
+            *res.fetch_mut::<u32>(0) += 1;
+            res.remove::<Vec<u8>>(0);
+            res.add(7u64);
+
+            res.restore(&snapshot);
+
+            assert_eq!(*res.fetch::<u32>(0), 5);
+            assert_eq!(*res.fetch::<Vec<u8>>(0), vec![1]);
+            assert!(!res.has_value(ResourceId::new::<u64>()));
+            assert!(res.has_value(ResourceId::new::<Res>()));
+        }
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    fn serialize() {
+        use serde_json::{Deserializer, Serializer};
+
+        let mut res = Resources::new();
+        res.add(5u32);
+        res.add_with_id(vec![1u8, 2], 3);
+        res.register_serializable::<u32>("a", 0);
+        res.register_serializable::<Vec<u8>>("b", 3);
+        res.register_serializable::<u64>("missing", 0);
+
+        let mut out = Vec::new();
+        res.serialize(&mut Serializer::new(&mut out)).unwrap();
+        assert_eq!(out, br#"{"a":5,"b":[1,2]}"#.to_vec());
+
+        let input = br#"{"b":[3],"missing":4,"unknown":0}"#;
+        assert!(res.deserialize(&mut Deserializer::from_slice(input))
+                    .is_err());
+        assert!(!res.has_value(ResourceId::new::<u64>()));
+
+        let input = br#"{"b":[3],"missing":4}"#;
+        res.deserialize(&mut Deserializer::from_slice(input))
+            .unwrap();
+        assert_eq!(*res.fetch::<u32>(0), 5);
+        assert_eq!(*res.fetch::<Vec<u8>>(3), vec![3]);
+        assert_eq!(*res.fetch::<u64>(0), 4);
+    }
+
+    #[test]
+    fn scopes() {
+        let mut res = Resources::new();
+        res.add(1u32);
+        res.add(Res);
+
+        res.push_scope();
+        res.shadow(2u32, 0);
+        res.shadow(3u32, 0);
+
+        res.push_scope();
+        res.shadow(4u32, 0);
+        res.shadow(5u32, 1);
+        assert_eq!(*res.fetch::<u32>(0), 4);
+        assert_eq!(*res.fetch::<u32>(1), 5);
+
+        res.pop_scope();
+        assert_eq!(*res.fetch::<u32>(0), 3);
+        assert!(!res.has_value(ResourceId::new_with_id::<u32>(1)));
+
+        res.pop_scope();
+        assert_eq!(*res.fetch::<u32>(0), 1);
+        assert!(res.has_value(ResourceId::new::<Res>()));
+    }
+
+    #[test]
+    #[should_panic(expected = "No scope pushed")]
+    fn shadow_without_scope() {
+        Resources::new().shadow(1u32, 0);
+    }
+
+    #[test]
+    fn downgrade() {
+        let mut res = Resources::new();
+        res.add(5u32);
+
+        let mut write = res.fetch_mut::<u32>(0);
+        *write += 1;
+
+        let read = write.downgrade();
+        assert_eq!(*res.fetch::<u32>(0), 6);
+        assert_eq!(*read, 6);
+        assert!(res.try_fetch_mut::<u32>(0).is_none());
+    }
+
+    #[test]
+    fn map() {
+        let mut res = Resources::new();
+        res.add((5u32, vec![1u8, 2]));
+
+        {
+            let mut data = FetchMut::map(res.fetch_mut::<(u32, Vec<u8>)>(0), |x| &mut x.1[..]);
+            data[0] = 3;
+            assert!(res.try_fetch::<(u32, Vec<u8>)>(0).is_none());
+        }
+
+        let data = Fetch::map(res.fetch::<(u32, Vec<u8>)>(0), |x| &x.1[..1]);
+        assert_eq!(&*data, &[3]);
+        assert!(res.try_fetch_mut::<(u32, Vec<u8>)>(0).is_none());
+    }
+
+    #[test]
+    fn owned() {
+        let mut res = Resources::new();
+        res.add(5u32);
+        let mut res = Arc::new(res);
+
+        {
+            let a = Resources::fetch_owned::<u32>(&res, 0);
+            let b = Resources::fetch_owned::<u32>(&res, 0);
+            assert!(res.try_fetch_mut::<u32>(0).is_none());
+            assert!(Arc::get_mut(&mut res).is_none());
+            assert_eq!(*a + *b, 10);
+        }
+
+        *Resources::fetch_owned_mut::<u32>(&res, 0) += 1;
+        assert!(res.try_fetch_mut::<u32>(0).is_some());
+        Arc::get_mut(&mut res).unwrap().add(1u8);
     }
 
-    /// Like `add()`, but allows specifying
-    /// and id while `add()` assumes `0`.
-    pub fn add_with_id<R>(&mut self, r: R, id: usize)
-        where R: Resource
-    {
-        use std::collections::hash_map::Entry;
+    #[test]
+    fn get_mut() {
+        let mut res = Resources::new();
+        res.add(5u32);
 
-        let entry = self.resources.entry(ResourceId::new_with_id::<R>(id));
+        *res.get_mut::<u32>(0).unwrap() += 1;
+        assert!(res.get_mut::<u32>(1).is_none());
 
-        if let Entry::Vacant(e) = entry {
-            e.insert(TrustCell::new(Box::new(r)));
-        } else {
-            panic!("Tried to add a resource though it is already registered");
+        {
+            let raw = res.get_raw_mut(ResourceId::new::<u32>()).unwrap();
+            *raw.downcast_mut::<u32>().unwrap() += 1;
         }
+
+        assert_eq!(*res.fetch::<u32>(0), 7);
     }
 
-    /// Returns true if the specified type / id combination
-    /// is registered.
-    pub fn has_value(&self, res_id: ResourceId) -> bool {
-        self.resources.contains_key(&res_id)
+    #[test]
+    fn ids() {
+        let mut res = Resources::new();
+        res.add(Res);
+        res.add_with_id(Res, 3);
+        #[cfg(feature = "std")]
+        res.add_thread_local(5i32);
+
+        let mut ids = res.ids();
+        ids.sort();
+
+        let mut expected = vec![ResourceId::new::<Res>(), ResourceId::new_with_id::<Res>(3)];
+        expected.sort();
+
+        assert_eq!(ids, expected);
     }
 
-    /// Fetches the resource with the specified type `T`.
-    /// The id is useful if you don't define your resources
-    /// in Rust or you want a more dynamic resource handling.
-    /// By default, the `#[derive(SystemData)]` passes `()`
-    /// as id.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the resource is being accessed mutably.
-    /// Also panics if there is no such resource.
-    pub fn fetch<T>(&self, id: usize) -> Fetch<T>
-        where T: Resource
-    {
-        let c = self.fetch_internal(TypeId::of::<T>(), id);
+    #[test]
+    fn view_shared_reads() {
+        let mut res = Resources::new();
+        res.add(5i32);
 
-        Fetch {
-            inner: c.borrow(),
-            phantom: PhantomData,
-        }
+        let view = res.view();
+        // Held while the tasks are borrowing, too.
+        let outer = view.fetch::<i32>(0);
+
+        ::std::thread::scope(|s| for _ in 0..2 {
+                                 s.spawn(move || assert_eq!(*view.fetch::<i32>(0), 5));
+                             });
+
+        assert_eq!(*outer, 5);
+        assert!(view.has_value(ResourceId::new::<i32>()));
+        assert_eq!(view.ids(), vec![ResourceId::new::<i32>()]);
     }
 
-    /// Fetches the resource with the specified type `T` mutably.
-    ///
-    /// Please see `fetch` for details.
-    pub fn fetch_mut<T>(&self, id: usize) -> FetchMut<T>
-        where T: Resource
-    {
-        let c = self.fetch_internal(TypeId::of::<T>(), id);
+    #[cfg(feature = "std")]
+    #[test]
+    fn thread_local() {
+        use std::rc::Rc;
 
-        FetchMut {
-            inner: c.borrow_mut(),
-            phantom: PhantomData,
+        struct NotSend(Rc<i32>);
+
+        let mut res = Resources::new();
+        res.add_thread_local(NotSend(Rc::new(5)));
+
+        assert!(res.has_thread_local(ResourceId(TypeId::of::<NotSend>(), 0)));
+        assert!(!res.has_value(ResourceId(TypeId::of::<NotSend>(), 0)));
+
+        {
+            let mut local = res.fetch_thread_local_mut::<NotSend>(0);
+            local.0 = Rc::new(*local.0 * 2);
         }
+
+        assert_eq!(*res.fetch_thread_local::<NotSend>(0).0, 10);
     }
 
-    /// Fetches the resource with the specified type id.
-    ///
-    /// Please see `fetch` for details.
-    pub fn fetch_id(&self, id: TypeId, comp_id: usize) -> FetchId {
-        let c = self.fetch_internal(id, comp_id);
+    #[cfg(feature = "std")]
+    #[test]
+    fn non_send() {
+        use std::rc::Rc;
+
+        let mut res = Resources::new();
+        res.add_non_send(Rc::new(5));
+        res.add_non_send_with_id(Rc::new(7), 1);
 
-        FetchId { inner: c.borrow() }
+        {
+            let mut fetched = <NonSendFetchMut<Rc<i32>> as SystemData>::fetch(&res, 0);
+            *fetched = Rc::new(6);
+        }
+
+        assert_eq!(**<NonSendFetch<Rc<i32>> as SystemData>::fetch(&res, 0), 6);
+        assert_eq!(**res.fetch_thread_local::<Rc<i32>>(1), 7);
+        assert!(res.ids().is_empty());
     }
 
-    /// Fetches the resource with the specified type id mutably.
-    ///
-    /// Please see `fetch` for details.
-    pub fn fetch_id_mut(&self, id: TypeId, comp_id: usize) -> FetchIdMut {
-        let c = self.fetch_internal(id, comp_id);
+    #[cfg(feature = "std")]
+    #[test]
+    fn run_resolved() {
+        let mut res = Resources::new();
+        res.add(1u32);
+        res.add(2u64);
+
+        let mut resolution = Resolution::default();
+        let sum = |res: &Resources| *res.fetch::<u32>(0) as u64 + *res.fetch::<u64>(0);
+
+        assert_eq!(res.run_resolved(&mut resolution, || sum(&res)), 3);
+        assert!(resolution.complete);
+        assert_eq!(resolution.indices.borrow().len(), 2);
 
-        FetchIdMut { inner: c.borrow_mut() }
+        // Replaying doesn't record again
+        *res.fetch_mut::<u32>(0) = 5;
+        assert_eq!(res.run_resolved(&mut resolution, || sum(&res)), 7);
+        assert_eq!(resolution.next.get(), 2);
+        assert_eq!(resolution.indices.borrow().len(), 2);
+
+        // Other fetches are looked up as usual
+        let other = res.run_resolved(&mut resolution, || *res.fetch::<u64>(0));
+        assert_eq!(other, 2);
+        assert_eq!(resolution.next.get(), 0);
+
+        // Structural changes and other containers record again
+        res.remove::<u32>(0);
+        res.add(8u32);
+        assert_eq!(res.run_resolved(&mut resolution, || sum(&res)), 10);
+        assert_eq!(resolution.generation, res.generation());
+
+        let mut other = Resources::new();
+        other.add(1u32);
+        other.add(1u64);
+        assert_eq!(other.run_resolved(&mut resolution, || sum(&other)), 2);
+        assert_eq!(resolution.container, other.container);
+
+        // Without a resolution, nothing is recorded
+        assert_eq!(sum(&res), 10);
+        assert_eq!(resolution.container, other.container);
     }
 
-    fn fetch_internal(&self, id: TypeId, cid: usize) -> &TrustCell<Box<Resource>> {
-        self.resources
-            .get(&ResourceId(id, cid))
-            .expect("No resource with the given id")
+    #[cfg(feature = "std")]
+    #[test]
+    fn thread_local_other_thread() {
+        use std::rc::Rc;
+        use std::thread;
+
+        let mut res = Resources::new();
+        res.add_thread_local(Rc::new(5));
+
+        thread::scope(|s| {
+            let fetched = s.spawn(|| {
+                res.fetch_thread_local::<Rc<i32>>(0);
+            });
+
+            assert!(fetched.join().is_err());
+        });
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[cfg(feature = "std")]
+    #[test]
+    fn thread_local_dropped_on_other_thread() {
+        use std::rc::Rc;
+        use std::thread;
 
-    struct Res;
+        let mut res = Resources::new();
+        res.add(5u32);
+        res.add_thread_local(Rc::new(5));
+
+        let dropped = thread::spawn(move || drop(res));
+        assert!(dropped.join().is_err());
+
+        // Clearing on the owning thread first is fine
+        let mut res = Resources::new();
+        res.add_thread_local(Rc::new(5));
+        res.clear();
+
+        let dropped = thread::spawn(move || drop(res));
+        assert!(dropped.join().is_ok());
+    }
 
     #[test]
-    fn res_id() {
-        assert_eq!(ResourceId::new::<Res>(), ResourceId::new_with_id::<Res>(0));
-        assert_eq!(ResourceId::new_with_id::<Res>(5),
-                   ResourceId(TypeId::of::<Res>(), 5));
+    fn flush() {
+        struct Commands(Vec<i32>);
+
+        impl FlushableResource for Commands {
+            fn flush(&mut self, res: &Resources) {
+                *res.fetch_mut::<i32>(0) += self.0.drain(..).sum::<i32>();
+            }
+        }
+
+        let mut res = Resources::new();
+        res.add(0i32);
+        res.add(Commands(vec![]));
+        res.register_flushable::<Commands>(0);
+        res.register_flushable::<Commands>(0);
+
+        res.fetch_mut::<Commands>(0).0.extend(&[1, 2, 3]);
+        res.flush();
+
+        assert_eq!(*res.fetch::<i32>(0), 6);
+        assert!(res.fetch::<Commands>(0).0.is_empty());
+
+        res.flush();
+        assert_eq!(*res.fetch::<i32>(0), 6);
     }
 
     #[test]
-    fn fetch_aspects() {
-        assert_eq!(Fetch::<Res>::reads(4),
-                   vec![ResourceId::new_with_id::<Res>(4)]);
-        assert_eq!(Fetch::<Res>::writes(8), vec![]);
+    fn remove() {
+        let mut res = Resources::new();
+        res.add(5i32);
+        res.add_with_id(7i32, 1);
 
+        assert_eq!(res.remove::<i32>(1), Some(7));
+        assert_eq!(res.remove::<i32>(1), None);
+        assert_eq!(res.remove::<u32>(0), None);
+        assert!(res.has_value(ResourceId::new::<i32>()));
+    }
+
+    #[test]
+    fn entry() {
         let mut res = Resources::new();
-        res.add_with_id(Res, 56);
-        Fetch::<Res>::fetch(&res, 56);
+
+        *res.fetch_or_insert_with::<i32, _>(0, || 3) += 1;
+        *res.fetch_or_insert_with::<i32, _>(0, || panic!("Resource exists")) += 1;
+        *res.entry::<i32>(1).or_insert(10) += 1;
+
+        assert_eq!(*res.fetch::<i32>(0), 5);
+        assert_eq!(*res.fetch::<i32>(1), 11);
     }
 
     #[test]
-    fn fetch_mut_aspects() {
-        assert_eq!(FetchMut::<Res>::reads(4), vec![]);
-        assert_eq!(FetchMut::<Res>::writes(8),
-                   vec![ResourceId::new_with_id::<Res>(8)]);
+    fn read_write_setup() {
+        let mut res = Resources::new();
+        res.add_with_id(5i32, 1);
+
+        <(ReadDefault<i32>, WriteDefault<u32>, WriteDefault<i32>) as SystemData>::setup(&mut res,
+                                                                                      1);
+
+        {
+            let (read, mut write) =
+                <(ReadDefault<i32>, WriteDefault<u32>) as SystemData>::fetch(&res, 1);
+            assert_eq!(*read, 5);
+            *write += 2;
+        }
+
+        assert_eq!(*res.fetch::<u32>(1), 2);
+        assert!(!res.has_value(ResourceId::new::<u32>()));
+    }
 
+    #[test]
+    fn rename() {
         let mut res = Resources::new();
-        res.add_with_id(Res, 56);
-        FetchMut::<Res>::fetch(&res, 56);
+        res.add_with_id(5i32, 1);
+        res.add_with_id(7i32, 2);
+
+        let id = |x| ResourceId::new_with_id::<i32>(x);
+
+        assert_eq!(res.rename(id(1), id(3)), Ok(()));
+        assert_eq!(*res.fetch::<i32>(3), 5);
+        assert!(!res.has_value(id(1)));
+
+        assert_eq!(res.rename(id(1), id(4)), Err(RenameError::Missing));
+        assert_eq!(res.rename(id(3), id(2)), Err(RenameError::Occupied));
+        assert_eq!(res.rename(id(3), ResourceId::new::<Res>()),
+                   Err(RenameError::TypeMismatch));
+        assert_eq!(*res.fetch::<i32>(2), 7);
     }
 
     #[test]
-    fn add() {
+    fn clear() {
         let mut res = Resources::new();
         res.add(Res);
+        res.add_with_id(Res, 1);
+        #[cfg(feature = "std")]
+        res.add_thread_local(5i32);
+
+        res.clear();
 
+        assert!(res.ids().is_empty());
+        #[cfg(feature = "std")]
+        assert!(!res.has_thread_local(ResourceId::new::<i32>()));
+
+        res.add(Res);
         assert!(res.has_value(ResourceId::new::<Res>()));
-        assert!(!res.has_value(ResourceId::new_with_id::<Res>(1)));
-        assert!(!res.has_value(ResourceId::new_with_id::<Res>(1)));
     }
 
-    #[allow(unused)]
     #[test]
-    #[should_panic(expected = "Already borrowed")]
-    fn read_write_fails() {
+    fn clear_drop_hooks() {
+        use std::sync::{Arc, Mutex};
+
+        struct Logged(&'static str, Arc<Mutex<Vec<String>>>);
+
+        impl Drop for Logged {
+            fn drop(&mut self) {
+                self.1.lock().unwrap().push(format!("drop {}", self.0));
+            }
+        }
+
+        struct Device(Logged);
+        struct Buffers(Logged);
+
+        let log = Arc::new(Mutex::new(Vec::new()));
 
+        let mut res = Resources::new();
+        res.add(Buffers(Logged("buffers", log.clone())));
+        res.add(Logged("other", log.clone()));
+        res.add(Device(Logged("device", log.clone())));
+
+        let l = log.clone();
+        res.on_drop(0, move |_: &mut Buffers| l.lock().unwrap().push("hook buffers".to_owned()));
+        let l = log.clone();
+        res.on_drop(10, move |_: &mut Device| l.lock().unwrap().push("hook device".to_owned()));
+
+        res.clear();
+
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["hook device",
+                        "hook buffers",
+                        "drop device",
+                        "drop buffers",
+                        "drop other"]);
+
+        log.lock().unwrap().clear();
+        res.add(Device(Logged("device", log.clone())));
+        drop(res);
+
+        assert_eq!(*log.lock().unwrap(), vec!["hook device", "drop device"]);
+    }
+
+    #[test]
+    fn clear_drop_hooks_priorities() {
+        use std::sync::{Arc, Mutex};
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut res = Resources::new();
+        res.add(1u32);
+        res.add(2u64);
+
+        let l = log.clone();
+        res.on_drop(10, move |x: &mut u32| l.lock().unwrap().push(format!("10: {}", x)));
+        let l = log.clone();
+        res.on_drop(-5, move |x: &mut u32| l.lock().unwrap().push(format!("-5: {}", x)));
+        let l = log.clone();
+        res.on_drop(0, move |x: &mut u64| l.lock().unwrap().push(format!("0: {}", x)));
+
+        // Shadowed resources are passed to the hooks as well
+        res.push_scope();
+        res.shadow(3u32, 0);
+
+        res.clear();
+
+        assert_eq!(*log.lock().unwrap(), vec!["10: 3", "10: 1", "0: 2", "-5: 3", "-5: 1"]);
+    }
+
+    #[test]
+    fn dense_index() {
         let mut res = Resources::new();
         res.add(Res);
+        res.add_with_id(5u32, 1);
 
-        let read = res.fetch::<Res>(0);
-        let write = res.fetch_mut::<Res>(0);
+        let id = ResourceId::new_with_id::<u32>(1);
+        let index = res.index(id).unwrap();
+        assert_eq!(index.id(), id);
+        assert_eq!(res.index(ResourceId::new::<u32>()), None);
+
+        *res.fetch_indexed_mut::<u32>(index) += 1;
+        assert_eq!(*res.fetch::<u32>(1), 6);
+
+        res.remove::<u32>(1);
+        assert_eq!(res.index(id), None);
+        assert_eq!(res.resources.ids(), vec![ResourceId::new::<Res>()]);
+
+        res.add_with_id(2u32, 1);
+        assert_eq!(res.index(id), Some(index));
+        assert_eq!(*res.fetch_indexed::<u32>(index), 2);
+
+        let res: Resources<Map<_, _>> = Resources::with_storage(Default::default());
+        assert_eq!(res.index(id), None);
     }
 
-    #[allow(unused)]
     #[test]
-    #[should_panic(expected = "Already borrowed mutably")]
-    fn write_read_fails() {
+    #[should_panic(expected = "Tried to fetch `i32` with the index of `u32`")]
+    fn dense_index_wrong_type() {
+        let mut res = Resources::new();
+        res.add(5u32);
+
+        let index = res.index(ResourceId::new::<u32>()).unwrap();
+        res.fetch_indexed::<i32>(index);
+    }
+
+    #[test]
+    fn custom_storage() {
+        #[derive(Default)]
+        struct VecStorage(Vec<(ResourceId, ResourceCell)>);
+
+        unsafe impl ResourceStorage for VecStorage {
+            fn get(&self, id: ResourceId) -> Option<&ResourceCell> {
+                self.0.iter().find(|x| x.0 == id).map(|x| &x.1)
+            }
+
+            fn get_mut(&mut self, id: ResourceId) -> Option<&mut ResourceCell> {
+                self.0.iter_mut().find(|x| x.0 == id).map(|x| &mut x.1)
+            }
+
+            fn insert(&mut self,
+                      id: ResourceId,
+                      cell: ResourceCell)
+                      -> Option<ResourceCell> {
+                let old = self.remove(id);
+                self.0.push((id, cell));
+
+                old
+            }
+
+            fn remove(&mut self, id: ResourceId) -> Option<ResourceCell> {
+                self.0
+                    .iter()
+                    .position(|x| x.0 == id)
+                    .map(|index| self.0.swap_remove(index).1)
+            }
+
+            fn ids(&self) -> Vec<ResourceId> {
+                self.0.iter().map(|x| x.0).collect()
+            }
+        }
+
+        let mut res = Resources::with_storage(VecStorage::default());
+        res.add(5i32);
+        res.add_with_id(Res, 2);
+
+        *res.fetch_mut::<i32>(0) += 1;
+
+        assert_eq!(*res.fetch::<i32>(0), 6);
+        assert!(res.has_value(ResourceId::new_with_id::<Res>(2)));
+        assert!(!res.has_value(ResourceId::new::<Res>()));
+        assert_eq!(res.ids().len(), 2);
+    }
+
+    #[test]
+    fn observers() {
+        use std::sync::{Arc, Mutex};
+
+        struct Log(Arc<Mutex<Vec<String>>>);
+
+        impl ResourceObserver for Log {
+            fn inserted(&mut self, id: ResourceId, _: &Resource) {
+                self.0.lock().unwrap().push(format!("+{}", id.1));
+            }
+
+            fn removed(&mut self, id: ResourceId, _: &Resource) {
+                self.0.lock().unwrap().push(format!("-{}", id.1));
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let values = Arc::new(Mutex::new(Vec::new()));
 
         let mut res = Resources::new();
+        res.add_observer(Log(log.clone()));
+        let list = values.clone();
+        res.on_remove(move |_, value: &i32| list.lock().unwrap().push(*value));
+
+        res.add_with_id(1i32, 1);
         res.add(Res);
+        assert_eq!(res.remove::<i32>(1), Some(1));
+        res.add_with_id(2i32, 1);
+        res.rename(ResourceId::new_with_id::<i32>(1), ResourceId::new_with_id::<i32>(2))
+            .unwrap();
+        res.push_scope();
+        res.shadow(3i32, 2);
+        res.pop_scope();
+        res.remove::<Res>(0);
+        res.clear();
 
-        let write = res.fetch_mut::<Res>(0);
-        let read = res.fetch::<Res>(0);
+        assert_eq!(*log.lock().unwrap(),
+                   vec!["+1", "+0", "-1", "+1", "-1", "+2", "-2", "+2", "-2", "+2", "-0", "-2"]);
+        assert_eq!(*values.lock().unwrap(), vec![1, 2, 2, 3, 2]);
+    }
+
+    #[test]
+    fn fetch_many() {
+        let mut res = Resources::new();
+        res.add(Res);
+        res.add(5i32);
+
+        {
+            let (_, mut x, _) = res.fetch_many::<(Fetch<Res>, WriteDefault<i32>, Fetch<Res>)>(0)
+                .unwrap();
+            *x += 1;
+        }
+        assert_eq!(*res.fetch::<i32>(0), 6);
+
+        let error = res.fetch_many::<(Fetch<i32>, FetchMut<i32>)>(0).err().unwrap();
+        assert_eq!(error.problems(),
+                   &[FetchProblem::Aliased(type_name::<i32>(), ResourceId::new::<i32>())]);
+
+        let _res = res.fetch_mut::<Res>(0);
+        let error = res.fetch_many::<(FetchMut<i32>, Fetch<Res>, Fetch<u8>)>(0).err().unwrap();
+        assert_eq!(error.problems(),
+                   &[FetchProblem::Borrowed(type_name::<Res>(), ResourceId::new::<Res>()),
+                     FetchProblem::Missing(type_name::<u8>(), ResourceId::new::<u8>())]);
+        assert!(res.try_fetch_mut::<i32>(0).is_some());
+    }
+
+    #[test]
+    fn extend() {
+        let mut res = Resources::new();
+        res.add(1i32);
+        res.add_named("res", Res);
+
+        let bundle = || {
+            let mut bundle = Resources::new();
+            bundle.add(2i32);
+            bundle.add(3u8);
+            bundle.register_cloneable::<u8>(0);
+            bundle.add_named("other", Res);
+
+            bundle
+        };
+
+        res.extend(bundle(), ConflictPolicy::Skip);
+        assert_eq!(*res.fetch::<i32>(0), 1);
+        assert_eq!(*res.fetch::<u8>(0), 3);
+        assert_eq!(res.resource_id("res"), Some(ResourceId::new::<Res>()));
+        assert!(res.resource_id("other").is_some());
+        assert_eq!(res.snapshot().ids(), vec![ResourceId::new::<u8>()]);
+
+        res.extend(bundle(), ConflictPolicy::Replace);
+        assert_eq!(*res.fetch::<i32>(0), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to extend with a resource which is already registered: `i32`")]
+    fn extend_conflict() {
+        let mut res = Resources::new();
+        res.add(1i32);
+
+        let mut other = Resources::new();
+        other.add(3u8);
+        other.add(2i32);
+
+        res.extend(other, ConflictPolicy::Panic);
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    #[should_panic(expected = "Tried to extend with a serializable key which is already \
+                               registered: `score`")]
+    fn extend_serializable_conflict() {
+        let mut res = Resources::new();
+        res.add(1i32);
+        res.register_serializable::<i32>("score", 0);
+
+        let mut other = Resources::new();
+        other.add(2u8);
+        other.register_serializable::<u8>("score", 0);
+
+        res.extend(other, ConflictPolicy::Panic);
+    }
+
+    #[test]
+    fn split_off() {
+        let mut res = Resources::new();
+        res.add(1i32);
+        let id = res.add_named("res", Res);
+        res.register_cloneable::<i32>(0);
+
+        let split = res.split_off(&[ResourceId::new::<i32>(), id, ResourceId::new::<u8>()]);
+
+        assert_eq!(res.ids(), vec![]);
+        assert_eq!(res.resource_id("res"), None);
+        assert_eq!(*split.fetch::<i32>(0), 1);
+        assert_eq!(split.resource_id("res"), Some(id));
+        assert_eq!(split.snapshot().ids(), vec![ResourceId::new::<i32>()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Tried to split off a shadowed resource: `i32`")]
+    fn split_off_shadowed() {
+        let mut res = Resources::new();
+        res.add(1i32);
+        res.add(2u8);
+
+        res.push_scope();
+        res.shadow(3i32, 0);
+
+        res.split_off(&[ResourceId::new::<u8>()]);
+        res.split_off(&[ResourceId::new::<i32>()]);
     }
 
     #[test]
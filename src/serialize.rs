@@ -0,0 +1,92 @@
+//! Serialization of the resources registered with
+//! `Resources::register_serializable`, only compiled
+//! with the `serialize` feature.
+
+use std::fmt::{Error as FormatError, Formatter};
+
+use erased_serde::{self, Deserializer as ErasedDeserializer, Serialize as ErasedSerialize};
+use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer, Error, MapAccess, Visitor};
+use serde::Serialize;
+
+use res::{Resource, ResourceId};
+
+type SerializeFn = fn(&Resource) -> &ErasedSerialize;
+type DeserializeFn = fn(&mut ErasedDeserializer) -> Result<Box<Resource>, erased_serde::Error>;
+
+/// A resource registered for serialization.
+pub struct Serializable {
+    pub deserialize: DeserializeFn,
+    pub id: ResourceId,
+    pub key: String,
+    pub serialize: SerializeFn,
+}
+
+impl Serializable {
+    pub fn new<T>(key: &str, id: ResourceId) -> Self
+        where T: DeserializeOwned + Resource + Serialize
+    {
+        fn serialize<T: Resource + Serialize>(r: &Resource) -> &ErasedSerialize {
+            r.downcast_ref::<T>()
+                .expect("Resource stored with a wrong type id")
+        }
+
+        fn deserialize<T>(d: &mut ErasedDeserializer) -> Result<Box<Resource>, erased_serde::Error>
+            where T: DeserializeOwned + Resource
+        {
+            erased_serde::deserialize::<T>(d).map(|r| Box::new(r) as Box<Resource>)
+        }
+
+        Serializable {
+            deserialize: deserialize::<T>,
+            id: id,
+            key: key.to_owned(),
+            serialize: serialize::<T>,
+        }
+    }
+}
+
+/// Deserializes a map from keys to resources,
+/// looking up the types of the keys in `entries`.
+pub struct ResourcesVisitor<'a> {
+    pub entries: &'a [Serializable],
+}
+
+impl<'a, 'de> Visitor<'de> for ResourcesVisitor<'a> {
+    type Value = Vec<(ResourceId, Box<Resource>)>;
+
+    fn expecting(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        write!(f, "a map of resources")
+    }
+
+    fn visit_map<M>(self, mut map: M) -> Result<Self::Value, M::Error>
+        where M: MapAccess<'de>
+    {
+        let mut resources = Vec::new();
+
+        while let Some(key) = map.next_key::<String>()? {
+            let entry = self.entries
+                .iter()
+                .find(|x| x.key == key)
+                .ok_or_else(|| M::Error::custom(format!("unknown resource \"{}\"", key)))?;
+
+            let r = map.next_value_seed(ResourceSeed(entry.deserialize))?;
+            resources.push((entry.id, r));
+        }
+
+        Ok(resources)
+    }
+}
+
+struct ResourceSeed(DeserializeFn);
+
+impl<'de> DeserializeSeed<'de> for ResourceSeed {
+    type Value = Box<Resource>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where D: Deserializer<'de>
+    {
+        let mut erased = <ErasedDeserializer>::erase(deserializer);
+
+        (self.0)(&mut erased).map_err(D::Error::custom)
+    }
+}
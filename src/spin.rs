@@ -0,0 +1,97 @@
+//! A spin lock replacing `std::sync::Mutex`
+//! if the crate is built without std.
+
+use std::cell::UnsafeCell;
+use std::convert::Infallible;
+use std::hint::spin_loop;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A lock which spins while another thread holds it.
+///
+/// It has the API of `std::sync::Mutex`, but isn't poisoned
+/// by panics (without std, they can't be detected), so
+/// `lock` never fails.
+#[derive(Default)]
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Creates an unlocked lock containing `value`.
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            locked: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Locks, spinning until other threads released the lock.
+    pub fn lock(&self) -> Result<MutexGuard<T>, Infallible> {
+        while self.locked
+                  .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                  .is_err() {
+            spin_loop();
+        }
+
+        Ok(MutexGuard { mutex: self })
+    }
+}
+
+/// Releases the lock once dropped.
+pub struct MutexGuard<'a, T: 'a> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.mutex.locked.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::Mutex;
+
+    #[test]
+    fn lock() {
+        let mutex = Arc::new(Mutex::new(0));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                     let mutex = mutex.clone();
+
+                     thread::spawn(move || for _ in 0..1000 {
+                                       *mutex.lock().unwrap() += 1;
+                                   })
+                 })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*mutex.lock().unwrap(), 4000);
+    }
+}
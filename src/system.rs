@@ -1,6 +1,39 @@
-use {ResourceId, Resources};
+use std::error::Error;
+
+use {FetchId, FetchIdMut, ResourceId, Resources};
+#[cfg(not(feature = "std"))]
+use prelude::*;
 
 /// Trait for fetching data and running systems. Automatically implemented for systems.
+///
+/// This allows running single systems without a dispatcher,
+/// e.g. in tests. The trait is object-safe, so systems
+/// of different types can be stored as trait objects:
+///
+/// ```rust
+/// # use shred::{FetchMut, Resources, RunNow, System};
+/// struct Inc;
+///
+/// impl<'a> System<'a> for Inc {
+///     type SystemData = FetchMut<'a, u32>;
+///
+///     fn run(&mut self, mut data: Self::SystemData) {
+///         *data += 1;
+///     }
+/// }
+///
+/// let mut res = Resources::new();
+/// res.add(0u32);
+///
+/// let mut systems: Vec<Box<for<'a> RunNow<'a>>> = vec![Box::new(Inc), Box::new(Inc)];
+///
+/// for system in &mut systems {
+///     system.setup(&mut res);
+///     system.run_now(&res);
+/// }
+///
+/// assert_eq!(*res.fetch::<u32>(0), 2);
+/// ```
 pub trait RunNow<'a> {
     /// Runs the system now.
     ///
@@ -11,6 +44,11 @@ pub trait RunNow<'a> {
     /// (tries to read from a resource which is already written to or
     /// tries to write to a resource which is read from).
     fn run_now(&mut self, res: &'a Resources);
+
+    /// Sets up the system (see `System::setup`).
+    ///
+    /// Defaults to doing nothing.
+    fn setup(&mut self, _: &mut Resources) {}
 }
 
 impl<'a, T> RunNow<'a> for T
@@ -20,6 +58,10 @@ impl<'a, T> RunNow<'a> for T
         let data = T::SystemData::fetch(res, 0);
         self.run(data);
     }
+
+    fn setup(&mut self, res: &mut Resources) {
+        System::setup(self, res);
+    }
 }
 
 #[repr(u8)]
@@ -57,6 +99,49 @@ pub trait System<'a> {
     fn running_time(&self) -> RunningTime {
         RunningTime::Average
     }
+
+    /// Sets up the system, which usually means adding
+    /// the resources it requires in case they are missing.
+    ///
+    /// Defaults to calling `SystemData::setup`.
+    fn setup(&mut self, res: &mut Resources) {
+        <Self::SystemData as SystemData<'a>>::setup(res, 0);
+    }
+}
+
+/// A system which may fail, returning an error from `run`.
+///
+/// Fallible systems are added with `DispatcherBuilder::add_fallible`;
+/// their errors are collected by the dispatcher and returned from
+/// `Dispatcher::try_dispatch`, so they don't have to be stored in
+/// a resource.
+pub trait FallibleSystem<'a> {
+    /// The resource bundle required
+    /// to execute this system.
+    type SystemData: SystemData<'a>;
+
+    /// The error returned from `run`, which implements
+    /// `shred::Error` without the `std` feature.
+    type Error: Error + Send + Sync + 'static;
+
+    /// Executes the system with the required system
+    /// data.
+    fn run(&mut self, data: Self::SystemData) -> Result<(), Self::Error>;
+
+    /// Returns a hint how long the system needs
+    /// for running (see `System::running_time`).
+    ///
+    /// Defaults to `RunningTime::Average`.
+    fn running_time(&self) -> RunningTime {
+        RunningTime::Average
+    }
+
+    /// Sets up the system (see `System::setup`).
+    ///
+    /// Defaults to calling `SystemData::setup`.
+    fn setup(&mut self, res: &mut Resources) {
+        <Self::SystemData as SystemData<'a>>::setup(res, 0);
+    }
 }
 
 /// A struct implementing
@@ -64,6 +149,14 @@ pub trait System<'a> {
 /// bundles some resources which are
 /// required for the execution.
 pub trait SystemData<'a> {
+    /// Sets up the resource bundle by adding
+    /// the resources it requires, in case
+    /// they are missing.
+    ///
+    /// Defaults to doing nothing, so the
+    /// resources have to be added manually.
+    fn setup(_: &mut Resources, _: usize) {}
+
     /// Creates a new resource bundle
     /// by fetching the required resources
     /// from the [`Resources`] struct.
@@ -107,11 +200,136 @@ pub trait SystemData<'a> {
     fn writes(id: usize) -> Vec<ResourceId>;
 }
 
+/// Declares the resources accessed by a system
+/// at runtime (see `DynamicSystemData`).
+pub trait Accessor {
+    /// The resources which are read from.
+    ///
+    /// Like `SystemData::reads`, this is only called
+    /// once when the system is added to a dispatcher.
+    fn reads(&self) -> Vec<ResourceId>;
+
+    /// The resources which are written to.
+    ///
+    /// Like `SystemData::writes`, this is only called
+    /// once when the system is added to a dispatcher.
+    fn writes(&self) -> Vec<ResourceId>;
+}
+
+/// Lists the resources accessed by a system,
+/// e.g. computed from a script or a configuration.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DynamicAccessor {
+    /// The resources which are read from.
+    pub reads: Vec<ResourceId>,
+    /// The resources which are written to.
+    pub writes: Vec<ResourceId>,
+}
+
+impl Accessor for DynamicAccessor {
+    fn reads(&self) -> Vec<ResourceId> {
+        self.reads.clone()
+    }
+
+    fn writes(&self) -> Vec<ResourceId> {
+        self.writes.clone()
+    }
+}
+
+/// Like `SystemData`, but the resources are
+/// determined at runtime by an `Accessor`.
+pub trait DynamicSystemData<'a> {
+    /// The accessor declaring the resources.
+    type Accessor: Accessor;
+
+    /// Sets up the resource bundle (see `SystemData::setup`).
+    ///
+    /// Defaults to doing nothing.
+    fn setup(_: &Self::Accessor, _: &mut Resources) {}
+
+    /// Creates a new resource bundle.
+    ///
+    /// # Contract
+    ///
+    /// Only fetch the resources the
+    /// accessor returned from `reads` / `writes`!
+    fn fetch(accessor: &Self::Accessor, res: &'a Resources) -> Self;
+}
+
+/// The resources declared by a `DynamicAccessor`,
+/// fetched in the same order.
+pub struct DynamicData<'a> {
+    /// The resources which are read from.
+    pub reads: Vec<FetchId<'a>>,
+    /// The resources which are written to.
+    pub writes: Vec<FetchIdMut<'a>>,
+}
+
+impl<'a> DynamicSystemData<'a> for DynamicData<'a> {
+    type Accessor = DynamicAccessor;
+
+    fn fetch(accessor: &DynamicAccessor, res: &'a Resources) -> Self {
+        DynamicData {
+            reads: accessor
+                .reads
+                .iter()
+                .map(|id| res.fetch_id(id.0, id.1))
+                .collect(),
+            writes: accessor
+                .writes
+                .iter()
+                .map(|id| res.fetch_id_mut(id.0, id.1))
+                .collect(),
+        }
+    }
+}
+
+/// A system whose resources are determined at runtime,
+/// which is the case for hot-loaded or data-driven systems.
+///
+/// Dynamic systems are added with `DispatcherBuilder::add_dynamic`,
+/// which schedules them using the resources declared by
+/// their accessor.
+pub trait DynamicSystem<'a> {
+    /// The resource bundle required
+    /// to execute this system.
+    type SystemData: DynamicSystemData<'a>;
+
+    /// Returns the accessor declaring
+    /// the resources of this system.
+    fn accessor(&self) -> &<Self::SystemData as DynamicSystemData<'a>>::Accessor;
+
+    /// Executes the system with the required system
+    /// data.
+    fn run(&mut self, data: Self::SystemData);
+
+    /// Returns a hint how long the system needs
+    /// for running (see `System::running_time`).
+    ///
+    /// Defaults to `RunningTime::Average`.
+    fn running_time(&self) -> RunningTime {
+        RunningTime::Average
+    }
+
+    /// Sets up the system (see `System::setup`).
+    ///
+    /// Defaults to calling `DynamicSystemData::setup`.
+    fn setup(&mut self, res: &mut Resources) {
+        <Self::SystemData as DynamicSystemData<'a>>::setup(self.accessor(), res);
+    }
+}
+
 macro_rules! impl_data {
     ( $($ty:ident),* ) => {
         impl<'a, $($ty),*> SystemData<'a> for ( $( $ty , )* )
             where $( $ty : SystemData<'a> ),*
         {
+            fn setup(res: &mut Resources, id: usize) {
+                #![allow(unused_variables)]
+
+                $( <$ty as SystemData<'a>>::setup(res, id.clone()); )*
+            }
+
             fn fetch(res: &'a Resources, id: usize) -> Self {
                 #![allow(unused_variables)]
 
@@ -1,8 +1,15 @@
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg_attr(feature = "parallel", macro_use)]
 extern crate shred;
 #[macro_use]
 extern crate shred_derive;
 
-use shred::{Dispatcher, DispatcherBuilder, Fetch, FetchMut, Resources, RunningTime, System};
+#[cfg(feature = "std")]
+use shred::{CancellationToken, FetchLocalMut};
+use shred::{BuildError, DeterministicScheduler, Dispatcher, DispatcherBuilder, DynamicId, Fetch,
+            FetchMut, Layout, NewSystem, Placement, ReadDefault, ResourceId, Resources, RunningTime,
+            Scheduler, System, SystemData, WriteDefault};
 
 fn sleep_short() {
     use std::thread::sleep;
@@ -72,6 +79,35 @@ fn dispatch_builder_invalid() {
         .build();
 }
 
+#[test]
+fn dispatch_try_build() {
+    let errors = DispatcherBuilder::new()
+        .add(DummySys, "a", &[])
+        .add(DummySys, "b", &["z", "c"])
+        .add(DummySys, "c", &[])
+        .add(DummySys, "a", &["c"])
+        .try_build()
+        .err()
+        .unwrap();
+
+    assert_eq!(errors,
+               vec![BuildError::MissingDependency {
+                        system: "b".to_owned(),
+                        dependency: "z".to_owned(),
+                    },
+                    BuildError::MissingDependency {
+                        system: "b".to_owned(),
+                        dependency: "c".to_owned(),
+                    },
+                    BuildError::DuplicateName("a".to_owned())]);
+
+    assert!(DispatcherBuilder::new()
+                .add(DummySys, "a", &[])
+                .add(DummySys, "b", &["a"])
+                .try_build()
+                .is_ok());
+}
+
 #[test]
 fn dispatch_basic() {
     let mut res = Resources::new();
@@ -215,3 +251,851 @@ fn dispatch_stage_group() {
 
     d.dispatch(&mut res);
 }
+
+#[cfg(feature = "std")]
+#[test]
+fn dispatch_thread_local_resource() {
+    use std::rc::Rc;
+
+    struct Counter(Rc<u32>);
+
+    struct IncCounter;
+
+    impl<'a> System<'a> for IncCounter {
+        type SystemData = (Fetch<'a, Res>, FetchLocalMut<'a, Counter>);
+
+        fn run(&mut self, (_, mut counter): Self::SystemData) {
+            counter.0 = Rc::new(*counter.0 + 1);
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Res);
+    res.add_thread_local(Counter(Rc::new(0)));
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(DummySysMut, "a", &[])
+        .add_thread_local(IncCounter)
+        .build();
+
+    d.dispatch(&mut res);
+    d.dispatch(&mut res);
+
+    assert_eq!(*res.fetch_thread_local::<Counter>(0).0, 2);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn dispatch_with_budget() {
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Ran(Vec<&'static str>);
+
+    struct Slow;
+
+    impl<'a> System<'a> for Slow {
+        type SystemData = WriteDefault<'a, Ran>;
+
+        fn run(&mut self, mut ran: Self::SystemData) {
+            std::thread::sleep(Duration::from_millis(20));
+            ran.0.push("slow");
+        }
+    }
+
+    struct Named(&'static str);
+
+    impl<'a> System<'a> for Named {
+        type SystemData = (WriteDefault<'a, Ran>, ReadDefault<'a, CancellationToken>);
+
+        fn run(&mut self, (mut ran, token): Self::SystemData) {
+            if !token.is_cancelled() {
+                ran.0.push(self.0);
+            }
+        }
+    }
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(Slow, "slow", &[])
+        .add(Named("background"), "background", &["slow"])
+        .add(Named("checking"), "checking", &["background"])
+        .with_interruptible("background")
+        .build();
+
+    let mut res = Resources::new();
+    d.setup(&mut res);
+
+    d.dispatch_with_budget(&mut res, Duration::from_millis(5));
+    assert_eq!(res.fetch::<Ran>(0).0, vec!["slow"]);
+    assert!(res.fetch::<CancellationToken>(0).deadline().is_none());
+
+    res.fetch_mut::<Ran>(0).0.clear();
+    d.dispatch_with_budget(&mut res, Duration::new(60, 0));
+    assert_eq!(res.fetch::<Ran>(0).0, vec!["slow", "background", "checking"]);
+
+    res.fetch_mut::<Ran>(0).0.clear();
+    res.fetch::<CancellationToken>(0).cancel();
+    d.dispatch(&mut res);
+    assert_eq!(res.fetch::<Ran>(0).0, vec!["slow"]);
+
+    // The budget doesn't discard the cancellation
+    res.fetch_mut::<Ran>(0).0.clear();
+    d.dispatch_with_budget(&mut res, Duration::new(60, 0));
+    assert_eq!(res.fetch::<Ran>(0).0, vec!["slow"]);
+    assert!(res.fetch::<CancellationToken>(0).is_cancelled());
+}
+
+#[test]
+fn dispatch_field_ids() {
+    #[derive(SystemData)]
+    struct WorldData<'a> {
+        current: Fetch<'a, u32>,
+        #[shred(id = 1)]
+        first: Fetch<'a, u32>,
+        #[shred(id = "spectator")]
+        spectator: FetchMut<'a, u32>,
+    }
+
+    #[derive(SystemData)]
+    struct Pair<'a>(Fetch<'a, u32>, #[shred(id = 1)] Fetch<'a, u32>);
+
+    const OFFSET: usize = 1;
+
+    #[derive(SystemData)]
+    struct Neighbours<'a> {
+        #[shred(id_expr = "id + OFFSET")]
+        next: Fetch<'a, u32>,
+        #[shred(id_expr = "id.saturating_sub(1)")]
+        previous: Fetch<'a, u32>,
+    }
+
+    struct Sum;
+
+    impl<'a> System<'a> for Sum {
+        type SystemData = WorldData<'a>;
+
+        fn run(&mut self, mut data: Self::SystemData) {
+            *data.spectator = *data.current + *data.first;
+        }
+    }
+
+    let spectator = DynamicId::from("spectator").to_usize();
+
+    let mut res = Resources::new();
+    res.add_with_id(2u32, 0);
+    res.add_with_id(3u32, 1);
+    res.add_with_id(0u32, spectator);
+
+    assert_eq!(WorldData::reads(5),
+               vec![ResourceId::new_with_id::<u32>(5), ResourceId::new_with_id::<u32>(1)]);
+    assert_eq!(WorldData::writes(5),
+               vec![ResourceId::new_with_id::<u32>(spectator)]);
+
+    let mut d: Dispatcher = DispatcherBuilder::new().add(Sum, "sum", &[]).build();
+    d.dispatch(&mut res);
+    assert_eq!(*res.fetch::<u32>(spectator), 5);
+
+    let pair = Pair::fetch(&res, 0);
+    assert_eq!((*pair.0, *pair.1), (2, 3));
+
+    assert_eq!(Neighbours::reads(4),
+               vec![ResourceId::new_with_id::<u32>(5), ResourceId::new_with_id::<u32>(3)]);
+    let neighbours = Neighbours::fetch(&res, 0);
+    assert_eq!((*neighbours.next, *neighbours.previous), (3, 2));
+}
+
+#[test]
+fn dispatch_optional_resource() {
+    #[derive(SystemData)]
+    struct OptionalData<'a> {
+        res: Option<Fetch<'a, Res>>,
+        res_b: Option<FetchMut<'a, ResB>>,
+    }
+
+    struct CheckOptional;
+
+    impl<'a> System<'a> for CheckOptional {
+        type SystemData = OptionalData<'a>;
+
+        fn run(&mut self, data: Self::SystemData) {
+            assert!(data.res.is_some());
+            assert!(data.res_b.is_none());
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Res);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(CheckOptional, "check", &[])
+        .build();
+
+    d.dispatch(&mut res);
+}
+
+#[test]
+fn dispatch_setup() {
+    #[derive(Default)]
+    struct Counter(u32);
+
+    struct Step(u32);
+
+    struct IncCounter;
+
+    impl<'a> System<'a> for IncCounter {
+        type SystemData = (Fetch<'a, Step>, WriteDefault<'a, Counter>);
+
+        fn setup(&mut self, res: &mut Resources) {
+            res.add(Step(2));
+        }
+
+        fn run(&mut self, (step, mut counter): Self::SystemData) {
+            counter.0 += step.0;
+        }
+    }
+
+    struct ReadCounter;
+
+    impl<'a> System<'a> for ReadCounter {
+        type SystemData = ReadDefault<'a, Counter>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(ReadCounter, "read", &[])
+        .add(IncCounter, "inc", &["read"])
+        .add_thread_local(ReadCounter)
+        .build();
+
+    let mut res = Resources::new();
+    d.setup(&mut res);
+    d.dispatch(&mut res);
+
+    assert_eq!(res.fetch::<Counter>(0).0, 2);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn dispatch_shared_pool() {
+    use std::sync::Arc;
+
+    use rayon::{Configuration, ThreadPool};
+
+    let pool = Arc::new(ThreadPool::new(Configuration::new()).unwrap());
+
+    let mut res = Resources::new();
+    res.add(Res);
+
+    let mut first: Dispatcher = DispatcherBuilder::new()
+        .with_pool(pool.clone())
+        .add(DummySys, "a", &[])
+        .build();
+    let mut second: Dispatcher = DispatcherBuilder::new()
+        .with_pool(pool.clone())
+        .add(DummySysMut, "b", &[])
+        .build();
+
+    first.dispatch(&mut res);
+    second.dispatch(&mut res);
+
+    assert_eq!(Arc::strong_count(&pool), 3);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn dispatch_par_seq() {
+    use std::sync::{Arc, Barrier};
+
+    use rayon::{Configuration, ThreadPool};
+    use shred::{ParSeq, ParallelContext};
+
+    struct Wait;
+
+    impl<'a> System<'a> for Wait {
+        type SystemData = Fetch<'a, Barrier>;
+
+        fn run(&mut self, barrier: Self::SystemData) {
+            barrier.wait();
+        }
+    }
+
+    let pool = Arc::new(ThreadPool::new(Configuration::new().num_threads(2)).unwrap());
+
+    let mut res = Resources::new();
+    res.add(Barrier::new(2));
+    res.add(Res);
+
+    // Only finishes if the two `Wait`s run in parallel
+    let mut dispatcher = ParSeq::new(par![Wait, seq![DummySys, Wait]],
+                                     ParallelContext::new(pool));
+    dispatcher.setup(&mut res);
+    dispatcher.dispatch(&res);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn setup_keeps_parallel_context() {
+    use std::sync::Arc;
+
+    use rayon::{Configuration, ThreadPool};
+    use shred::ParallelContext;
+
+    let pool = |n| Arc::new(ThreadPool::new(Configuration::new().num_threads(n)).unwrap());
+
+    let mut res = Resources::new();
+    let mut first = DispatcherBuilder::new().with_pool(pool(2)).build();
+    let mut second = DispatcherBuilder::new().with_pool(pool(3)).build();
+
+    first.setup(&mut res);
+    second.setup(&mut res);
+    assert_eq!(res.fetch::<ParallelContext>(0).num_threads(), 2);
+
+    *res.fetch_mut::<ParallelContext>(0) = second.parallel_context();
+    first.setup(&mut res);
+    assert_eq!(res.fetch::<ParallelContext>(0).num_threads(), 3);
+}
+
+#[cfg(all(feature = "future", feature = "parallel"))]
+#[test]
+fn dispatch_async_future() {
+    use std::future::Future;
+    use std::sync::Arc;
+    use std::task::{Context, Wake};
+    use std::thread::{self, Thread};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Res);
+
+    let mut d = DispatcherBuilder::new()
+        .add(DummySysMut, "a", &[])
+        .add(DummySys, "b", &["a"])
+        .build_async(res);
+
+    d.dispatch();
+
+    {
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut finished = Box::pin(d.finished());
+
+        while finished.as_mut().poll(&mut cx).is_pending() {
+            thread::park();
+        }
+    }
+
+    d.dispatch_thread_local();
+    d.mut_res().add_with_id(Res, 2);
+}
+
+#[test]
+fn dispatch_thread_local_non_send() {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Count(u32);
+
+    struct Inc;
+
+    impl<'a> System<'a> for Inc {
+        type SystemData = FetchMut<'a, Count>;
+
+        fn run(&mut self, mut data: Self::SystemData) {
+            data.0 += 1;
+        }
+    }
+
+    struct Observe(Rc<Cell<u32>>);
+
+    impl<'a> System<'a> for Observe {
+        type SystemData = Fetch<'a, Count>;
+
+        fn run(&mut self, data: Self::SystemData) {
+            self.0.set(data.0);
+        }
+    }
+
+    let observed = Rc::new(Cell::new(0));
+
+    let mut res = Resources::new();
+    res.add(Count(0));
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add_thread_local(Observe(observed.clone()))
+        .add(Inc, "inc", &[])
+        .build();
+
+    d.dispatch(&mut res);
+    assert_eq!(observed.get(), 1);
+
+    d.dispatch(&mut res);
+    assert_eq!(observed.get(), 2);
+}
+
+#[test]
+fn dispatch_run_conditions() {
+    struct Paused(bool);
+
+    struct Count(u32);
+
+    struct Inc;
+
+    impl<'a> System<'a> for Inc {
+        type SystemData = (Fetch<'a, Paused>, FetchMut<'a, Count>);
+
+        fn run(&mut self, (_, mut count): Self::SystemData) {
+            count.0 += 1;
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Paused(false));
+    res.add(Count(0));
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(Inc, "inc", &[])
+        .with_run_if("inc", |res| !res.fetch::<Paused>(0).0)
+        .build();
+
+    d.dispatch(&mut res);
+    assert_eq!(res.fetch::<Count>(0).0, 1);
+
+    res.fetch_mut::<Paused>(0).0 = true;
+    d.dispatch(&mut res);
+    assert_eq!(res.fetch::<Count>(0).0, 1);
+
+    res.fetch_mut::<Paused>(0).0 = false;
+    d.set_enabled("inc", false);
+    d.dispatch_seq(&mut res);
+    assert_eq!(res.fetch::<Count>(0).0, 1);
+
+    d.set_enabled("inc", true);
+    d.dispatch_seq(&mut res);
+    assert_eq!(res.fetch::<Count>(0).0, 2);
+
+    let errors = DispatcherBuilder::new()
+        .with_run_if("missing", |_| true)
+        .with_interruptible("missing")
+        .try_build()
+        .err()
+        .unwrap();
+    assert_eq!(errors, vec![BuildError::UnknownSystem("missing".to_owned()); 2]);
+}
+
+#[test]
+fn dispatch_groups() {
+    struct Count;
+
+    impl<'a> System<'a> for Count {
+        type SystemData = WriteDefault<'a, u32>;
+
+        fn run(&mut self, mut count: Self::SystemData) {
+            *count += 1;
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Res);
+    res.add(0u32);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .with_group("input", &[], |b| b.add(DummySys, "keyboard", &[]).add(DummySys, "mouse", &[]))
+        .with_group("physics", &["input"], |b| b.add(Count, "count", &[]))
+        .add(DummySys, "render", &["physics"])
+        .build();
+
+    assert_eq!(d.stages(),
+               vec![vec!["keyboard", "mouse"], vec!["count"], vec!["render"]]);
+    assert_eq!(d.group_systems("input"), vec!["keyboard", "mouse"]);
+
+    d.dispatch(&mut res);
+    d.set_group_enabled("physics", false);
+    d.dispatch(&mut res);
+    assert_eq!(*res.fetch::<u32>(0), 1);
+
+    d.set_group_enabled("physics", true);
+    d.dispatch(&mut res);
+    assert_eq!(*res.fetch::<u32>(0), 2);
+
+    d.set_enabled("count", false);
+    d.set_group_enabled("physics", false);
+    d.set_group_enabled("physics", true);
+    d.dispatch(&mut res);
+    assert_eq!(*res.fetch::<u32>(0), 2);
+
+    d.set_enabled("count", true);
+    d.dispatch(&mut res);
+    assert_eq!(*res.fetch::<u32>(0), 3);
+
+    let errors = DispatcherBuilder::new()
+        .with_group("physics", &["input"], |b| b)
+        .try_build()
+        .err()
+        .unwrap();
+    assert_eq!(errors,
+               vec![BuildError::MissingDependency {
+                        system: "physics".to_owned(),
+                        dependency: "input".to_owned(),
+                    }]);
+}
+
+#[test]
+fn dispatch_batch() {
+    struct Steps(u32);
+
+    struct Count(u32);
+
+    struct Inc;
+
+    impl<'a> System<'a> for Inc {
+        type SystemData = (Fetch<'a, Steps>, FetchMut<'a, Count>);
+
+        fn run(&mut self, (_, mut count): Self::SystemData) {
+            count.0 += 1;
+        }
+    }
+
+    struct Check;
+
+    impl<'a> System<'a> for Check {
+        type SystemData = Fetch<'a, Count>;
+
+        fn run(&mut self, count: Self::SystemData) {
+            assert_eq!(count.0 % 3, 0);
+        }
+    }
+
+    let batch = DispatcherBuilder::new()
+        .add(Inc, "inc", &[])
+        .add(Inc, "inc_again", &["inc"]);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add_batch(batch,
+                   |batch| {
+                       let steps = batch.res().fetch::<Steps>(0).0;
+
+                       for _ in 0..steps {
+                           batch.dispatch();
+                       }
+                   },
+                   "batch",
+                   &[])
+        .add(Check, "check", &["batch"])
+        .build();
+
+    let mut res = Resources::new();
+    res.add(Steps(3));
+    res.add(Count(0));
+
+    d.dispatch(&mut res);
+    d.dispatch_seq(&mut res);
+
+    assert_eq!(res.fetch::<Count>(0).0, 12);
+
+    let (_, reads, writes) = d.systems().next().unwrap();
+    assert_eq!(reads.len(), 1);
+    assert_eq!(writes.len(), 1);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn dispatch_profiling() {
+    use shred::SystemStats;
+
+    let mut res = Resources::new();
+    res.add(Res);
+    res.add(SystemStats::new(2));
+
+    let batch = DispatcherBuilder::new().add(DummySys, "inner", &[]);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(DummySysMut, "a", &[])
+        .add(DummySys, "b", &["a"])
+        .add_batch(batch, |batch| batch.dispatch(), "batch", &["b"])
+        .build();
+
+    for _ in 0..3 {
+        d.dispatch(&mut res);
+    }
+    d.dispatch_seq(&mut res);
+
+    let stats = res.fetch::<SystemStats>(0);
+    assert_eq!(stats.frames().count(), 2);
+
+    let frame = stats.last_frame().unwrap();
+    let samples: Vec<_> = frame.iter().map(|x| (x.name.as_str(), x.stage)).collect();
+    assert_eq!(samples, vec![("a", 0), ("b", 1), ("batch", 2)]);
+    assert!(frame.iter().all(|x| x.duration.subsec_nanos() >= 1_000));
+}
+
+#[test]
+fn dispatch_fallible() {
+    #[cfg(feature = "std")]
+    use std::error::Error;
+    use std::fmt::{Display, Error as FormatError, Formatter};
+
+    #[cfg(not(feature = "std"))]
+    use shred::Error;
+    use shred::FallibleSystem;
+
+    #[derive(Debug)]
+    struct LoadError(&'static str);
+
+    impl Display for LoadError {
+        fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+            write!(f, "Failed to load {}", self.0)
+        }
+    }
+
+    impl Error for LoadError {
+        fn description(&self) -> &str {
+            "Failed to load an asset"
+        }
+    }
+
+    struct Load(Option<&'static str>);
+
+    impl<'a> FallibleSystem<'a> for Load {
+        type SystemData = DummyData<'a>;
+        type Error = LoadError;
+
+        fn run(&mut self, _: Self::SystemData) -> Result<(), LoadError> {
+            match self.0 {
+                Some(asset) => Err(LoadError(asset)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    struct Frames;
+
+    impl<'a> System<'a> for Frames {
+        type SystemData = WriteDefault<'a, usize>;
+
+        fn run(&mut self, mut frames: Self::SystemData) {
+            *frames += 1;
+        }
+    }
+
+    let batch = DispatcherBuilder::new().add_fallible(Load(Some("sound")), "inner", &[]);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add_fallible(Load(Some("texture")), "a", &[])
+        .add_fallible(Load(None), "b", &[])
+        .add_batch(batch, |batch| batch.dispatch(), "batch", &["a"])
+        .add_thread_local(Frames)
+        .build();
+
+    let mut res = Resources::new();
+    res.add(Res);
+    d.setup(&mut res);
+
+    for frame in 1..3 {
+        let e = d.try_dispatch(&mut res).unwrap_err();
+        assert!(e.panicked().is_empty());
+
+        let mut errors: Vec<_> = e.into_errors()
+            .into_iter()
+            .map(|(name, e)| (name, e.to_string()))
+            .collect();
+        errors.sort();
+
+        assert_eq!(errors,
+                   vec![("a".to_owned(), "Failed to load texture".to_owned()),
+                        ("inner".to_owned(), "Failed to load sound".to_owned())]);
+        // Thread local systems still run after errors
+        assert_eq!(*res.fetch::<usize>(0), frame);
+    }
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add_fallible(Load(None), "a", &[])
+        .build();
+
+    assert!(d.try_dispatch(&mut res).is_ok());
+}
+
+#[test]
+fn dispatch_dynamic() {
+    use shred::{DynamicAccessor, DynamicData, DynamicSystem};
+
+    struct Script {
+        accessor: DynamicAccessor,
+    }
+
+    impl<'a> DynamicSystem<'a> for Script {
+        type SystemData = DynamicData<'a>;
+
+        fn accessor(&self) -> &DynamicAccessor {
+            &self.accessor
+        }
+
+        fn run(&mut self, mut data: DynamicData<'a>) {
+            let sum: u32 = data.reads
+                .iter()
+                .map(|x| *x.downcast_ref::<u32>().unwrap())
+                .sum();
+
+            for write in &mut data.writes {
+                *write.downcast_mut::<u32>().unwrap() += sum;
+            }
+        }
+    }
+
+    let mut res = Resources::new();
+    let a = res.add_named("a", 1u32);
+    let b = res.add_named("b", 2u32);
+
+    let script = |reads, writes| {
+        Script {
+            accessor: DynamicAccessor {
+                reads: reads,
+                writes: writes,
+            },
+        }
+    };
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add_dynamic(script(vec![a], vec![b]), "a_to_b", &[])
+        .add_dynamic(script(vec![b], vec![a]), "b_to_a", &[])
+        .build();
+
+    assert_eq!(d.readers(a), vec!["a_to_b"]);
+    assert_eq!(d.writers(a), vec!["b_to_a"]);
+    assert_eq!(d.stages(), vec![vec!["a_to_b"], vec!["b_to_a"]]);
+
+    d.dispatch(&mut res);
+
+    assert_eq!(res.fetch_by_name("b").downcast_ref::<u32>(), Some(&3));
+    assert_eq!(res.fetch_by_name("a").downcast_ref::<u32>(), Some(&4));
+}
+
+#[test]
+fn dispatch_custom_scheduler() {
+    struct Sequential;
+
+    impl Scheduler for Sequential {
+        fn place(&mut self, _: &Layout, _: &NewSystem) -> Placement {
+            Placement::NewStage
+        }
+    }
+
+    let mut res = Resources::new();
+    res.add(Res);
+
+    let mut d: Dispatcher = DispatcherBuilder::new()
+        .add(DummySys, "a", &[])
+        .add(DummySys, "b", &[])
+        .with_scheduler(Sequential)
+        .add(DummySys, "c", &[])
+        .build();
+
+    assert_eq!(d.stages(), vec![vec!["a"], vec!["b"], vec!["c"]]);
+
+    d.dispatch(&mut res);
+}
+
+#[test]
+#[should_panic(expected = "invalid placement")]
+fn dispatch_invalid_scheduler() {
+    struct SingleStage;
+
+    impl Scheduler for SingleStage {
+        fn place(&mut self, layout: &Layout, _: &NewSystem) -> Placement {
+            if layout.num_stages() == 0 {
+                Placement::NewStage
+            } else {
+                Placement::NewGroup(0)
+            }
+        }
+    }
+
+    DispatcherBuilder::new()
+        .with_scheduler(SingleStage)
+        .add(DummySysMut, "a", &[])
+        .add(DummySysMut, "b", &[]);
+}
+
+#[test]
+#[should_panic(expected = "invalid placement")]
+fn dispatch_scheduler_ignoring_dependency() {
+    struct FirstStage;
+
+    impl Scheduler for FirstStage {
+        fn place(&mut self, layout: &Layout, _: &NewSystem) -> Placement {
+            if layout.num_stages() < 2 {
+                Placement::NewStage
+            } else {
+                Placement::NewGroup(0)
+            }
+        }
+    }
+
+    DispatcherBuilder::new()
+        .with_scheduler(FirstStage)
+        .add(DummySys, "a", &[])
+        .add(DummySys, "b", &[])
+        .add(DummySys, "c", &["b"]);
+}
+
+#[test]
+fn dispatch_deterministic_scheduler() {
+    struct Order(Vec<u32>);
+
+    struct WriteB;
+
+    impl<'a> System<'a> for WriteB {
+        type SystemData = FetchMut<'a, ResB>;
+
+        fn run(&mut self, _: Self::SystemData) {}
+    }
+
+    struct ReadB;
+
+    impl<'a> System<'a> for ReadB {
+        type SystemData = (Fetch<'a, ResB>, FetchMut<'a, Order>);
+
+        fn run(&mut self, (_, mut order): Self::SystemData) {
+            order.0.push(1);
+        }
+    }
+
+    struct Push;
+
+    impl<'a> System<'a> for Push {
+        type SystemData = FetchMut<'a, Order>;
+
+        fn run(&mut self, mut order: Self::SystemData) {
+            order.0.push(2);
+        }
+    }
+
+    let builder = || {
+        DispatcherBuilder::new()
+            .add(WriteB, "write_b", &[])
+            .add(ReadB, "read_b", &[])
+            .add(Push, "push", &[])
+    };
+
+    // `push` doesn't conflict with `write_b`, so it's moved before `read_b`
+    let d: Dispatcher = builder().build();
+    assert_eq!(d.stages(), vec![vec!["write_b", "push"], vec!["read_b"]]);
+
+    let mut d: Dispatcher = builder().with_scheduler(DeterministicScheduler).build();
+    assert_eq!(d.stages(), vec![vec!["write_b", "read_b", "push"]]);
+
+    let mut res = Resources::new();
+    res.add(ResB);
+    res.add(Order(Vec::new()));
+
+    d.dispatch(&mut res);
+
+    assert_eq!(res.fetch::<Order>(0).0, vec![1, 2]);
+}